@@ -5,9 +5,17 @@ use std::convert::Infallible;
 use std::fmt;
 use std::net::IpAddr;
 use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::util::expiration::{format_duration, parse_duration, parse_expiration, Bounds};
 
 lazy_static! {
-    pub static ref ARGS: Args = Args::parse();
+    // tests get the defaults instead of the test runner's own arguments
+    pub static ref ARGS: Args = if cfg!(test) {
+        Args::parse_from(["microbin"])
+    } else {
+        Args::parse()
+    };
 }
 
 #[derive(Parser, Debug, Clone, Serialize)]
@@ -46,11 +54,32 @@ pub struct Args {
     #[clap(long, env = "MICROBIN_HIGHLIGHTSYNTAX")]
     pub highlightsyntax: bool,
 
+    #[clap(long, env = "MICROBIN_HIGHLIGHT_MAX_KB", default_value_t = 512)]
+    pub highlight_max_kb: u64,
+
+    #[clap(long, env = "MICROBIN_HIGHLIGHT_THEME", default_value = "InspiredGitHub")]
+    pub highlight_theme: String,
+
+    #[clap(long, env = "MICROBIN_HIGHLIGHT_THEME_DARK", default_value = "base16-ocean.dark")]
+    pub highlight_theme_dark: String,
+
     #[clap(short, long, env = "MICROBIN_PORT", default_value_t = 8080)]
     pub port: u16,
 
-    #[clap(short, long, env="MICROBIN_BIND", default_value_t = IpAddr::from([0, 0, 0, 0]))]
-    pub bind: IpAddr,
+    #[clap(
+        short,
+        long,
+        env = "MICROBIN_BIND",
+        value_delimiter = ',',
+        default_value = "0.0.0.0"
+    )]
+    pub bind: Vec<IpAddr>,
+
+    #[clap(long, env = "MICROBIN_UNIX_SOCKET")]
+    pub unix_socket: Option<String>,
+
+    #[clap(long, env = "MICROBIN_UNIX_SOCKET_MODE")]
+    pub unix_socket_mode: Option<String>,
 
     #[clap(long, env = "MICROBIN_PRIVATE")]
     pub private: bool,
@@ -89,7 +118,18 @@ pub struct Args {
     pub enable_burn_after: bool,
 
     #[clap(short, long, env = "MICROBIN_DEFAULT_BURN_AFTER", default_value_t = 0)]
-    pub default_burn_after: u16,
+    pub default_burn_after: u64,
+
+    #[clap(
+        long,
+        env = "MICROBIN_BURN_AFTER_PRESETS",
+        value_delimiter = ',',
+        default_value = "1,10,100,1000,10000"
+    )]
+    pub burn_after_presets: Vec<u64>,
+
+    #[clap(long, env = "MICROBIN_MAX_BURN_AFTER", default_value_t = 0)]
+    pub max_burn_after: u64,
 
     #[clap(long, env = "MICROBIN_WIDE")]
     pub wide: bool,
@@ -106,6 +146,20 @@ pub struct Args {
     #[clap(long, env = "MICROBIN_DEFAULT_EXPIRY", default_value = "never")]
     pub default_expiry: String,
 
+    #[clap(
+        long,
+        env = "MICROBIN_EXPIRY_PRESETS",
+        value_delimiter = ',',
+        default_value = "1min,10min,1hour,24hour,3days,1week,never"
+    )]
+    pub expiry_presets: Vec<String>,
+
+    #[clap(long, env = "MICROBIN_MIN_EXPIRY")]
+    pub min_expiry: Option<Lifetime>,
+
+    #[clap(long, env = "MICROBIN_MAX_EXPIRY")]
+    pub max_expiry: Option<Lifetime>,
+
     #[clap(long, env = "MICROBIN_DATA_DIR", default_value = "microbin_data")]
     pub data_dir: String,
 
@@ -146,6 +200,57 @@ pub struct Args {
         default_value_t = 2048
     )]
     pub max_file_size_unencrypted_mb: usize,
+
+    #[clap(long, env = "MICROBIN_DISABLE_SECURITY_HEADERS")]
+    pub disable_security_headers: bool,
+
+    #[clap(long, env = "MICROBIN_CSP_REPORT_ONLY")]
+    pub csp_report_only: bool,
+
+    #[clap(long, env = "MICROBIN_CSP_REPORT_URI")]
+    pub csp_report_uri: Option<String>,
+
+    #[clap(long, env = "MICROBIN_HSTS_MAX_AGE", default_value_t = 31536000)]
+    pub hsts_max_age: u32,
+
+    #[clap(long, env = "MICROBIN_FRAME_ANCESTORS", default_value = "'none'")]
+    pub frame_ancestors: String,
+
+    #[clap(long, env = "MICROBIN_REFERRER_POLICY", default_value = "same-origin")]
+    pub referrer_policy: String,
+
+    #[clap(long, env = "MICROBIN_TLS_CERT")]
+    pub tls_cert: Option<String>,
+
+    #[clap(long, env = "MICROBIN_TLS_KEY")]
+    pub tls_key: Option<String>,
+
+    #[clap(long, env = "MICROBIN_TLS_REDIRECT_PORT")]
+    pub tls_redirect_port: Option<u16>,
+
+    #[clap(long, env = "MICROBIN_SHUTDOWN_TIMEOUT", default_value_t = 30)]
+    pub shutdown_timeout: u64,
+
+    #[clap(long, env = "MICROBIN_MAX_REVISIONS", default_value_t = 100)]
+    pub max_revisions: usize,
+
+    #[clap(long, env = "MICROBIN_WEBHOOK_URLS", value_delimiter = ',')]
+    pub webhook_urls: Vec<String>,
+
+    #[clap(long, env = "MICROBIN_WEBHOOK_SECRET")]
+    pub webhook_secret: Option<String>,
+
+    #[clap(long, env = "MICROBIN_WEBHOOK_RETRIES", default_value_t = 5)]
+    pub webhook_retries: u32,
+
+    #[clap(long, env = "MICROBIN_ACCESS_LOG_DAYS", default_value_t = 30)]
+    pub access_log_days: u64,
+
+    #[clap(long, env = "MICROBIN_TRUST_PROXY_HEADERS")]
+    pub trust_proxy_headers: bool,
+
+    #[clap(long, env = "MICROBIN_DEFAULT_LANG", default_value = "zh")]
+    pub default_lang: String,
 }
 
 impl Args {
@@ -167,6 +272,59 @@ impl Args {
         }
     }
 
+    pub fn bind_as_str(&self) -> String {
+        self.bind
+            .iter()
+            .map(|addr| addr.to_string())
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    /// The expiry presets for the upload form that are within the bounds,
+    /// with the default first if it isn't one of them.
+    pub fn expiry_options(&self) -> Vec<String> {
+        let bounds = Bounds::from_args();
+        let mut options: Vec<String> = self
+            .expiry_presets
+            .iter()
+            .map(|preset| preset.trim().to_string())
+            .filter(|preset| match parse_duration(preset) {
+                Some(lifetime) => bounds.allows(lifetime),
+                None => preset == "never" && bounds.allow_never,
+            })
+            .collect();
+        if !options.contains(&self.default_expiry) && parse_duration(&self.default_expiry).is_some() {
+            options.insert(0, self.default_expiry.clone());
+        }
+        options
+    }
+
+    /// Warns if uploads can't get the default expiry, they are given the
+    /// longest allowed lifetime instead. Checked once at startup.
+    pub fn check_default_expiry(&self) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |n| n.as_secs() as i64);
+        if let Err(error) = parse_expiration(&self.default_expiry, now, &Bounds::from_args()) {
+            log::warn!("Default expiry \"{}\" can't be used: {}", self.default_expiry, error);
+        }
+    }
+
+    /// The burn after presets within the maximum, the default included.
+    pub fn burn_after_options(&self) -> Vec<u64> {
+        let mut options: Vec<u64> = self
+            .burn_after_presets
+            .iter()
+            .copied()
+            .filter(|count| *count > 0 && (self.max_burn_after == 0 || *count <= self.max_burn_after))
+            .collect();
+        if self.default_burn_after > 0 && !options.contains(&self.default_burn_after) {
+            options.push(self.default_burn_after);
+            options.sort_unstable();
+        }
+        options
+    }
+
     pub fn without_secrets(self) -> Args {
         Args {
             auth_basic_username: None,
@@ -180,8 +338,13 @@ impl Args {
             hide_logo: self.hide_logo,
             no_listing: self.no_listing,
             highlightsyntax: self.highlightsyntax,
+            highlight_max_kb: self.highlight_max_kb,
+            highlight_theme: self.highlight_theme.clone(),
+            highlight_theme_dark: self.highlight_theme_dark.clone(),
             port: self.port,
             bind: self.bind,
+            unix_socket: None,
+            unix_socket_mode: self.unix_socket_mode,
             private: self.private,
             pure_html: self.pure_html,
             json_db: self.json_db,
@@ -196,11 +359,16 @@ impl Args {
             gc_days: self.gc_days,
             enable_burn_after: self.enable_burn_after,
             default_burn_after: self.default_burn_after,
+            burn_after_presets: self.burn_after_presets,
+            max_burn_after: self.max_burn_after,
             wide: self.wide,
             qr: self.qr,
             eternal_pasta: self.eternal_pasta,
             enable_readonly: self.enable_readonly,
             default_expiry: self.default_expiry,
+            expiry_presets: self.expiry_presets,
+            min_expiry: self.min_expiry,
+            max_expiry: self.max_expiry,
             data_dir: String::from(""),
             no_file_upload: self.no_file_upload,
             custom_css: self.custom_css,
@@ -211,6 +379,23 @@ impl Args {
             max_file_size_encrypted_mb: self.max_file_size_encrypted_mb,
             max_file_size_unencrypted_mb: self.max_file_size_unencrypted_mb,
             disable_update_checking: self.disable_update_checking,
+            disable_security_headers: self.disable_security_headers,
+            csp_report_only: self.csp_report_only,
+            csp_report_uri: self.csp_report_uri,
+            hsts_max_age: self.hsts_max_age,
+            frame_ancestors: self.frame_ancestors,
+            referrer_policy: self.referrer_policy,
+            tls_cert: None,
+            tls_key: None,
+            tls_redirect_port: self.tls_redirect_port,
+            shutdown_timeout: self.shutdown_timeout,
+            max_revisions: self.max_revisions,
+            webhook_urls: Vec::new(),
+            webhook_secret: None,
+            webhook_retries: self.webhook_retries,
+            access_log_days: self.access_log_days,
+            trust_proxy_headers: self.trust_proxy_headers,
+            default_lang: self.default_lang,
         }
     }
 }
@@ -232,3 +417,23 @@ impl FromStr for PublicUrl {
         Ok(PublicUrl(uri))
     }
 }
+
+/// A lifetime in seconds, given in any form `parse_duration` reads.
+#[derive(Debug, Clone, Serialize)]
+pub struct Lifetime(pub i64);
+
+impl fmt::Display for Lifetime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", format_duration(self.0))
+    }
+}
+
+impl FromStr for Lifetime {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_duration(s)
+            .map(Lifetime)
+            .ok_or_else(|| format!("\"{}\" is not a duration like 90m or P30D", s))
+    }
+}
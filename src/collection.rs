@@ -0,0 +1,81 @@
+use serde::{Deserialize, Serialize};
+
+use crate::args::ARGS;
+use crate::util::animalnumbers::{to_animal_names, to_u64};
+use crate::util::hashids::{to_hashids, to_u64 as hashid_to_u64};
+use crate::util::misc::decrypt;
+
+/// A named group of pastas shared under one URL.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Collection {
+    pub id: u64,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Pasta ids in the order they are shown.
+    pub members: Vec<u64>,
+    /// The id encrypted with the collection's password, if editing it needs
+    /// one. Checked the same way as for readonly pastas.
+    #[serde(default)]
+    pub encrypted_key: Option<String>,
+    pub created: i64,
+}
+
+impl Collection {
+    pub fn id_as_animals(&self) -> String {
+        if ARGS.hash_ids {
+            to_hashids(self.id)
+        } else {
+            to_animal_names(self.id)
+        }
+    }
+
+    pub fn has_password(&self) -> bool {
+        self.encrypted_key.as_ref().is_some_and(|key| !key.is_empty())
+    }
+
+    pub fn password_matches(&self, password: &str) -> bool {
+        match &self.encrypted_key {
+            Some(key) if !key.is_empty() => {
+                decrypt(key, password).is_ok_and(|id| id == self.id.to_string())
+            }
+            _ => true,
+        }
+    }
+
+    /// The members one per line, as the edit form takes them.
+    pub fn members_as_string(&self) -> String {
+        self.members
+            .iter()
+            .map(|id| {
+                if ARGS.hash_ids {
+                    to_hashids(*id)
+                } else {
+                    to_animal_names(*id)
+                }
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+/// Reads pasta references from the members field, one per line. Each may be
+/// a bare id or a link to the pasta, only the last path segment counts.
+pub fn parse_members(input: &str) -> Vec<u64> {
+    let mut members: Vec<u64> = Vec::new();
+    for line in input.lines() {
+        let reference = line.trim().trim_end_matches('/');
+        let id = reference.rsplit('/').next().unwrap_or("");
+        let id = if ARGS.hash_ids {
+            hashid_to_u64(id).ok()
+        } else {
+            to_u64(id).ok()
+        };
+        if let Some(id) = id {
+            if !members.contains(&id) {
+                members.push(id);
+            }
+        }
+    }
+    members
+}
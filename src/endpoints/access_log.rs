@@ -0,0 +1,115 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use actix_multipart::Multipart;
+use actix_web::{get, post, web, Error, HttpRequest, HttpResponse};
+use askama::Template;
+
+use crate::args::{Args, ARGS};
+use crate::endpoints::errors::ErrorTemplate;
+use crate::pasta::{Access, Pasta};
+use crate::translation::{request_translation, Translation};
+use crate::util::access_log::prune;
+use crate::util::animalnumbers::to_u64;
+use crate::util::auth;
+use crate::util::hashids::to_u64 as hashid_to_u64;
+use crate::util::misc::remove_expired;
+use crate::AppState;
+
+#[derive(Template)]
+#[template(path = "access_log.html")]
+struct AccessLogTemplate<'a> {
+    pasta: &'a Pasta,
+    /// Newest first, empty until the password was given.
+    entries: Vec<Access>,
+    authorized: bool,
+    status: String,
+    args: &'a Args,
+    text: Translation,
+}
+
+fn render(
+    req: &HttpRequest,
+    data: &web::Data<AppState>,
+    id: &str,
+    password: Option<String>,
+) -> HttpResponse {
+    let mut pastas = data.pastas.lock().unwrap();
+
+    remove_expired(&mut pastas);
+
+    let text = request_translation(req);
+
+    let id = if ARGS.hash_ids {
+        hashid_to_u64(id).unwrap_or(0)
+    } else {
+        to_u64(id).unwrap_or(0)
+    };
+
+    let Some(pasta) = pastas.iter().find(|p| p.id == id && p.access_log.is_some()) else {
+        return HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body(ErrorTemplate { args: &ARGS, text }.render().unwrap());
+    };
+
+    let authorized = password.as_deref().is_some_and(|password| authorized(pasta, password));
+    let status = match password {
+        Some(_) if !authorized => String::from("incorrect"),
+        _ => String::from(""),
+    };
+
+    let mut entries = if authorized {
+        pasta.access_log.clone().unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+    // entries past the retention are only dropped on the next access, don't
+    // show them in the meantime
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |n| n.as_secs() as i64);
+    prune(&mut entries, now);
+    entries.reverse();
+
+    HttpResponse::Ok().content_type("text/html; charset=utf-8").body(
+        AccessLogTemplate {
+            pasta,
+            entries,
+            authorized,
+            status,
+            args: &ARGS,
+            text,
+        }
+        .render()
+        .unwrap(),
+    )
+}
+
+/// The log is shown for the pasta's own password, which only read-only and
+/// private pastas have, or the admin password.
+fn authorized(pasta: &Pasta, password: &str) -> bool {
+    if password.is_empty() {
+        return false;
+    }
+    password == ARGS.auth_admin_password
+        || ((pasta.readonly || pasta.encrypt_server) && pasta.edit_password_matches(password))
+}
+
+#[get("/access_log/{id}")]
+pub async fn get_access_log(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    id: web::Path<String>,
+) -> HttpResponse {
+    render(&req, &data, &id, None)
+}
+
+#[post("/access_log/{id}")]
+pub async fn post_access_log(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    id: web::Path<String>,
+    payload: Multipart,
+) -> Result<HttpResponse, Error> {
+    let password = auth::password_from_multipart(payload).await?;
+    Ok(render(&req, &data, &id, Some(password)))
+}
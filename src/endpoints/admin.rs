@@ -1,8 +1,11 @@
 use crate::args::{Args, ARGS};
 use crate::pasta::Pasta;
-use crate::translation::{get_translation, Translation};
+use crate::translation::{request_translation, Translation};
+use crate::util::listing::ListQuery;
 use crate::util::misc::remove_expired;
+use crate::util::security_headers::csp_nonce;
 use crate::util::version::{fetch_latest_version, Version, CURRENT_VERSION};
+use crate::util::webhooks::{deliveries, DeliveryRecord};
 use crate::AppState;
 use actix_multipart::Multipart;
 use actix_web::{get, post, web, Error, HttpResponse, HttpRequest};
@@ -12,13 +15,23 @@ use futures::TryStreamExt;
 #[derive(Template)]
 #[template(path = "admin.html")]
 struct AdminTemplate<'a> {
-    pastas: &'a Vec<Pasta>,
+    pastas: Vec<&'a Pasta>,
+    /// all stored pastas, not just the ones on this page
+    pasta_count: usize,
+    query: ListQuery,
+    page: usize,
+    pages: usize,
+    total: usize,
+    username: String,
+    password: String,
     args: &'a Args,
     status: &'a String,
     version_string: &'a String,
     message: &'a String,
     update: &'a Option<Version>,
+    deliveries: Vec<DeliveryRecord>,
     text: Translation,
+    nonce: String,
 }
 
 #[get("/admin")]
@@ -36,16 +49,26 @@ pub async fn post_admin(
 ) -> Result<HttpResponse, Error> {
     let mut username = String::from("");
     let mut password = String::from("");
+    let mut query = ListQuery::default();
 
     while let Some(mut field) = payload.try_next().await? {
-        if field.name() == Some("username") {
-            while let Some(chunk) = field.try_next().await? {
-                username.push_str(std::str::from_utf8(&chunk).unwrap().to_string().as_str());
-            }
-        } else if field.name() == Some("password") {
-            while let Some(chunk) = field.try_next().await? {
-                password.push_str(std::str::from_utf8(&chunk).unwrap().to_string().as_str());
-            }
+        let mut value = String::from("");
+        while let Some(chunk) = field.try_next().await? {
+            value.push_str(std::str::from_utf8(&chunk).unwrap_or(""));
+        }
+        match field.name() {
+            Some("username") => username = value,
+            Some("password") => password = value,
+            Some("sort") => query.sort = value,
+            Some("order") => query.order = value,
+            Some("page") => query.page = value,
+            Some("per_page") => query.per_page = value,
+            Some("type") => query.pasta_type = value,
+            Some("has_file") => query.has_file = value,
+            Some("extension") => query.extension = value,
+            Some("tag") => query.tag = value,
+            Some("privacy") => query.privacy = value,
+            _ => {}
         }
     }
 
@@ -59,8 +82,7 @@ pub async fn post_admin(
 
     remove_expired(&mut pastas);
 
-    // sort pastas in reverse-chronological order of creation time
-    pastas.sort_by(|a, b| b.created.cmp(&a.created));
+    let page = query.page(query.apply(pastas.iter()));
 
     // todo status report more sophisticated
     let mut status = "OK";
@@ -94,18 +116,26 @@ pub async fn post_admin(
         update = None;
     }
 
-    let lang = req.cookie("lang").map(|c| c.value().to_string()).unwrap_or_else(|| "zh".to_string());
-    let text = get_translation(&lang);
+    let text = request_translation(&req);
 
     Ok(HttpResponse::Ok().content_type("text/html; charset=utf-8").body(
         AdminTemplate {
-            pastas: &pastas,
+            pastas: page.items,
+            pasta_count: pastas.len(),
+            page: page.page,
+            pages: page.pages,
+            total: page.total,
+            query,
+            username,
+            password,
             args: &ARGS,
             status: &String::from(status),
             version_string: &format!("{}", CURRENT_VERSION.long_title),
             message: &String::from(message),
             update: &update,
+            deliveries: deliveries(),
             text,
+            nonce: csp_nonce(&req),
         }
         .render()
         .unwrap(),
@@ -1,4 +1,4 @@
-use crate::translation::{get_translation, Translation};
+use crate::translation::{request_translation, Translation};
 use crate::args::{Args, ARGS};
 use actix_web::{get, web, HttpResponse, HttpRequest};
 use askama::Template;
@@ -13,8 +13,7 @@ struct AuthAdmin<'a> {
 
 #[get("/auth_admin")]
 pub async fn auth_admin(req: HttpRequest) -> HttpResponse {
-    let lang = req.cookie("lang").map(|c| c.value().to_string()).unwrap_or_else(|| "zh".to_string());
-    let text = get_translation(&lang);
+    let text = request_translation(&req);
 
     return HttpResponse::Ok().content_type("text/html; charset=utf-8").body(
         AuthAdmin {
@@ -30,8 +29,7 @@ pub async fn auth_admin(req: HttpRequest) -> HttpResponse {
 #[get("/auth_admin/{status}")]
 pub async fn auth_admin_with_status(req: HttpRequest, param: web::Path<String>) -> HttpResponse {
     let status = param.into_inner();
-    let lang = req.cookie("lang").map(|c| c.value().to_string()).unwrap_or_else(|| "zh".to_string());
-    let text = get_translation(&lang);
+    let text = request_translation(&req);
 
     return HttpResponse::Ok().content_type("text/html; charset=utf-8").body(
         AuthAdmin {
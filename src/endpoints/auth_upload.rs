@@ -1,9 +1,10 @@
-use crate::translation::{get_translation, Translation};
+use crate::translation::{request_translation, Translation};
 use crate::args::{Args, ARGS};
 use crate::endpoints::errors::ErrorTemplate;
 use crate::util::animalnumbers::to_u64;
 use crate::util::hashids::to_u64 as hashid_to_u64;
 use crate::util::misc::remove_expired;
+use crate::util::security_headers::csp_nonce;
 use crate::AppState;
 use actix_web::{get, web, HttpResponse, HttpRequest};
 use askama::Template;
@@ -18,6 +19,7 @@ struct AuthPasta<'a> {
     encrypt_client: bool,
     path: String,
     text: Translation,
+    nonce: String,
 }
 
 #[get("/auth/{id}")]
@@ -33,8 +35,7 @@ pub async fn auth_upload(req: HttpRequest, data: web::Data<AppState>, id: web::P
         to_u64(&id).unwrap_or(0)
     };
     
-    let lang = req.cookie("lang").map(|c| c.value().to_string()).unwrap_or_else(|| "zh".to_string());
-    let text = get_translation(&lang);
+    let text = request_translation(&req);
 
     for (_i, pasta) in pastas.iter().enumerate() {
         if pasta.id == intern_id {
@@ -47,6 +48,7 @@ pub async fn auth_upload(req: HttpRequest, data: web::Data<AppState>, id: web::P
                     encrypt_client: pasta.encrypt_client,
                     path: String::from("upload"),
                     text,
+                    nonce: csp_nonce(&req),
                 }
                 .render()
                 .unwrap(),
@@ -78,8 +80,7 @@ pub async fn auth_upload_with_status(
         to_u64(&id).unwrap_or(0)
     };
     
-    let lang = req.cookie("lang").map(|c| c.value().to_string()).unwrap_or_else(|| "zh".to_string());
-    let text = get_translation(&lang);
+    let text = request_translation(&req);
 
     for (_i, pasta) in pastas.iter().enumerate() {
         if pasta.id == intern_id {
@@ -92,6 +93,7 @@ pub async fn auth_upload_with_status(
                     encrypt_client: pasta.encrypt_client,
                     path: String::from("upload"),
                     text,
+                    nonce: csp_nonce(&req),
                 }
                 .render()
                 .unwrap(),
@@ -117,8 +119,7 @@ pub async fn auth_raw_pasta(req: HttpRequest, data: web::Data<AppState>, id: web
         to_u64(&id).unwrap_or(0)
     };
     
-    let lang = req.cookie("lang").map(|c| c.value().to_string()).unwrap_or_else(|| "zh".to_string());
-    let text = get_translation(&lang);
+    let text = request_translation(&req);
 
     for (_i, pasta) in pastas.iter().enumerate() {
         if pasta.id == intern_id {
@@ -131,6 +132,7 @@ pub async fn auth_raw_pasta(req: HttpRequest, data: web::Data<AppState>, id: web
                     encrypt_client: pasta.encrypt_client,
                     path: String::from("raw"),
                     text,
+                    nonce: csp_nonce(&req),
                 }
                 .render()
                 .unwrap(),
@@ -162,8 +164,7 @@ pub async fn auth_raw_pasta_with_status(
         to_u64(&id).unwrap_or(0)
     };
     
-    let lang = req.cookie("lang").map(|c| c.value().to_string()).unwrap_or_else(|| "zh".to_string());
-    let text = get_translation(&lang);
+    let text = request_translation(&req);
 
     for (_i, pasta) in pastas.iter().enumerate() {
         if pasta.id == intern_id {
@@ -176,6 +177,7 @@ pub async fn auth_raw_pasta_with_status(
                     encrypt_client: pasta.encrypt_client,
                     path: String::from("raw"),
                     text,
+                    nonce: csp_nonce(&req),
                 }
                 .render()
                 .unwrap(),
@@ -201,8 +203,7 @@ pub async fn auth_edit_private(req: HttpRequest, data: web::Data<AppState>, id:
         to_u64(&id).unwrap_or(0)
     };
     
-    let lang = req.cookie("lang").map(|c| c.value().to_string()).unwrap_or_else(|| "zh".to_string());
-    let text = get_translation(&lang);
+    let text = request_translation(&req);
 
     for (_, pasta) in pastas.iter().enumerate() {
         if pasta.id == intern_id {
@@ -215,6 +216,7 @@ pub async fn auth_edit_private(req: HttpRequest, data: web::Data<AppState>, id:
                     encrypt_client: pasta.encrypt_client,
                     path: String::from("edit_private"),
                     text,
+                    nonce: csp_nonce(&req),
                 }
                 .render()
                 .unwrap(),
@@ -246,8 +248,7 @@ pub async fn auth_edit_private_with_status(
         to_u64(&id).unwrap_or(0)
     };
     
-    let lang = req.cookie("lang").map(|c| c.value().to_string()).unwrap_or_else(|| "zh".to_string());
-    let text = get_translation(&lang);
+    let text = request_translation(&req);
 
     for (_i, pasta) in pastas.iter().enumerate() {
         if pasta.id == intern_id {
@@ -260,6 +261,175 @@ pub async fn auth_edit_private_with_status(
                     encrypt_client: pasta.encrypt_client,
                     path: String::from("edit_private"),
                     text,
+                    nonce: csp_nonce(&req),
+                }
+                .render()
+                .unwrap(),
+            );
+        }
+    }
+
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(ErrorTemplate { args: &ARGS, text: text.clone() }.render().unwrap())
+}
+
+#[get("/auth_history/{id}")]
+pub async fn auth_history(req: HttpRequest, data: web::Data<AppState>, id: web::Path<String>) -> HttpResponse {
+    // get access to the pasta collection
+    let mut pastas = data.pastas.lock().unwrap();
+
+    remove_expired(&mut pastas);
+
+    let intern_id = if ARGS.hash_ids {
+        hashid_to_u64(&id).unwrap_or(0)
+    } else {
+        to_u64(&id).unwrap_or(0)
+    };
+    
+    let text = request_translation(&req);
+
+    for pasta in pastas.iter() {
+        if pasta.id == intern_id {
+            return HttpResponse::Ok().content_type("text/html; charset=utf-8").body(
+                AuthPasta {
+                    args: &ARGS,
+                    id: id.into_inner(),
+                    status: String::from(""),
+                    encrypted_key: pasta.encrypted_key.to_owned().unwrap_or_default(),
+                    encrypt_client: pasta.encrypt_client,
+                    path: String::from("history"),
+                    text,
+                    nonce: csp_nonce(&req),
+                }
+                .render()
+                .unwrap(),
+            );
+        }
+    }
+
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(ErrorTemplate { args: &ARGS, text: text.clone() }.render().unwrap())
+}
+
+#[get("/auth_history/{id}/{status}")]
+pub async fn auth_history_with_status(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    param: web::Path<(String, String)>,
+) -> HttpResponse {
+    // get access to the pasta collection
+    let mut pastas = data.pastas.lock().unwrap();
+
+    remove_expired(&mut pastas);
+
+    let (id, status) = param.into_inner();
+
+    let intern_id = if ARGS.hash_ids {
+        hashid_to_u64(&id).unwrap_or(0)
+    } else {
+        to_u64(&id).unwrap_or(0)
+    };
+    
+    let text = request_translation(&req);
+
+    for pasta in pastas.iter() {
+        if pasta.id == intern_id {
+            return HttpResponse::Ok().content_type("text/html; charset=utf-8").body(
+                AuthPasta {
+                    args: &ARGS,
+                    id,
+                    status,
+                    encrypted_key: pasta.encrypted_key.to_owned().unwrap_or_default(),
+                    encrypt_client: pasta.encrypt_client,
+                    path: String::from("history"),
+                    text,
+                    nonce: csp_nonce(&req),
+                }
+                .render()
+                .unwrap(),
+            );
+        }
+    }
+
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(ErrorTemplate { args: &ARGS, text: text.clone() }.render().unwrap())
+}
+
+#[get("/auth_fork/{id}")]
+pub async fn auth_fork(req: HttpRequest, data: web::Data<AppState>, id: web::Path<String>) -> HttpResponse {
+    // get access to the pasta collection
+    let mut pastas = data.pastas.lock().unwrap();
+
+    remove_expired(&mut pastas);
+
+    let intern_id = if ARGS.hash_ids {
+        hashid_to_u64(&id).unwrap_or(0)
+    } else {
+        to_u64(&id).unwrap_or(0)
+    };
+    
+    let text = request_translation(&req);
+
+    for pasta in pastas.iter() {
+        if pasta.id == intern_id {
+            return HttpResponse::Ok().content_type("text/html; charset=utf-8").body(
+                AuthPasta {
+                    args: &ARGS,
+                    id: id.into_inner(),
+                    status: String::from(""),
+                    encrypted_key: pasta.encrypted_key.to_owned().unwrap_or_default(),
+                    encrypt_client: pasta.encrypt_client,
+                    path: String::from("fork"),
+                    text,
+                    nonce: csp_nonce(&req),
+                }
+                .render()
+                .unwrap(),
+            );
+        }
+    }
+
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(ErrorTemplate { args: &ARGS, text: text.clone() }.render().unwrap())
+}
+
+#[get("/auth_fork/{id}/{status}")]
+pub async fn auth_fork_with_status(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    param: web::Path<(String, String)>,
+) -> HttpResponse {
+    // get access to the pasta collection
+    let mut pastas = data.pastas.lock().unwrap();
+
+    remove_expired(&mut pastas);
+
+    let (id, status) = param.into_inner();
+
+    let intern_id = if ARGS.hash_ids {
+        hashid_to_u64(&id).unwrap_or(0)
+    } else {
+        to_u64(&id).unwrap_or(0)
+    };
+    
+    let text = request_translation(&req);
+
+    for pasta in pastas.iter() {
+        if pasta.id == intern_id {
+            return HttpResponse::Ok().content_type("text/html; charset=utf-8").body(
+                AuthPasta {
+                    args: &ARGS,
+                    id,
+                    status,
+                    encrypted_key: pasta.encrypted_key.to_owned().unwrap_or_default(),
+                    encrypt_client: pasta.encrypt_client,
+                    path: String::from("fork"),
+                    text,
+                    nonce: csp_nonce(&req),
                 }
                 .render()
                 .unwrap(),
@@ -285,8 +455,7 @@ pub async fn auth_file(req: HttpRequest, data: web::Data<AppState>, id: web::Pat
         to_u64(&id).unwrap_or(0)
     };
     
-    let lang = req.cookie("lang").map(|c| c.value().to_string()).unwrap_or_else(|| "zh".to_string());
-    let text = get_translation(&lang);
+    let text = request_translation(&req);
 
     for (_, pasta) in pastas.iter().enumerate() {
         if pasta.id == intern_id {
@@ -299,6 +468,7 @@ pub async fn auth_file(req: HttpRequest, data: web::Data<AppState>, id: web::Pat
                     encrypt_client: pasta.encrypt_client,
                     path: String::from("secure_file"),
                     text,
+                    nonce: csp_nonce(&req),
                 }
                 .render()
                 .unwrap(),
@@ -330,8 +500,7 @@ pub async fn auth_file_with_status(
         to_u64(&id).unwrap_or(0)
     };
     
-    let lang = req.cookie("lang").map(|c| c.value().to_string()).unwrap_or_else(|| "zh".to_string());
-    let text = get_translation(&lang);
+    let text = request_translation(&req);
 
     for (_i, pasta) in pastas.iter().enumerate() {
         if pasta.id == intern_id {
@@ -344,6 +513,7 @@ pub async fn auth_file_with_status(
                     encrypt_client: pasta.encrypt_client,
                     path: String::from("secure_file"),
                     text,
+                    nonce: csp_nonce(&req),
                 }
                 .render()
                 .unwrap(),
@@ -369,8 +539,7 @@ pub async fn auth_remove_private(req: HttpRequest, data: web::Data<AppState>, id
         to_u64(&id).unwrap_or(0)
     };
     
-    let lang = req.cookie("lang").map(|c| c.value().to_string()).unwrap_or_else(|| "zh".to_string());
-    let text = get_translation(&lang);
+    let text = request_translation(&req);
 
     for (_, pasta) in pastas.iter().enumerate() {
         if pasta.id == intern_id {
@@ -383,6 +552,7 @@ pub async fn auth_remove_private(req: HttpRequest, data: web::Data<AppState>, id
                     encrypt_client: pasta.encrypt_client,
                     path: String::from("remove"),
                     text,
+                    nonce: csp_nonce(&req),
                 }
                 .render()
                 .unwrap(),
@@ -414,8 +584,7 @@ pub async fn auth_remove_private_with_status(
         to_u64(&id).unwrap_or(0)
     };
     
-    let lang = req.cookie("lang").map(|c| c.value().to_string()).unwrap_or_else(|| "zh".to_string());
-    let text = get_translation(&lang);
+    let text = request_translation(&req);
 
     for (_i, pasta) in pastas.iter().enumerate() {
         if pasta.id == intern_id {
@@ -428,6 +597,7 @@ pub async fn auth_remove_private_with_status(
                     encrypt_client: pasta.encrypt_client,
                     path: String::from("remove"),
                     text,
+                    nonce: csp_nonce(&req),
                 }
                 .render()
                 .unwrap(),
@@ -0,0 +1,298 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use actix_multipart::Multipart;
+use actix_web::{get, post, web, Error, HttpRequest, HttpResponse};
+use askama::Template;
+use futures::TryStreamExt;
+use rand::Rng;
+
+use crate::args::{Args, ARGS};
+use crate::collection::{parse_members, Collection};
+use crate::endpoints::errors::ErrorTemplate;
+use crate::pasta::Pasta;
+use crate::translation::{request_translation, Translation};
+use crate::util::access_log;
+use crate::util::animalnumbers::to_u64;
+use crate::util::db::{delete_collection, save_collection, update};
+use crate::util::hashids::to_u64 as hashid_to_u64;
+use crate::util::misc::{encrypt, remove_expired};
+use crate::AppState;
+
+#[derive(Template)]
+#[template(path = "collections.html")]
+struct CollectionsTemplate<'a> {
+    collections: Vec<&'a Collection>,
+    args: &'a Args,
+    text: Translation,
+}
+
+#[derive(Template)]
+#[template(path = "collection.html")]
+struct CollectionTemplate<'a> {
+    collection: &'a Collection,
+    /// Members that still exist, in order.
+    members: Vec<&'a Pasta>,
+    status: String,
+    args: &'a Args,
+    text: Translation,
+}
+
+/// The fields of the create and edit forms.
+#[derive(Default)]
+struct CollectionForm {
+    name: String,
+    description: String,
+    members: String,
+    password: String,
+    admin_password: String,
+    delete: bool,
+}
+
+async fn read_collection_form(payload: &mut Multipart) -> Result<CollectionForm, Error> {
+    let mut form = CollectionForm::default();
+
+    while let Some(mut field) = payload.try_next().await? {
+        let mut value = String::from("");
+        while let Some(chunk) = field.try_next().await? {
+            value.push_str(std::str::from_utf8(&chunk).unwrap_or(""));
+        }
+        match field.name() {
+            Some("name") => form.name = value.trim().chars().take(100).collect(),
+            Some("description") => form.description = value.trim().chars().take(1000).collect(),
+            Some("members") => form.members = value,
+            Some("password") => form.password = value,
+            Some("admin_password") => form.admin_password = value,
+            Some("delete") => form.delete = value == "on",
+            _ => {}
+        }
+    }
+
+    Ok(form)
+}
+
+fn intern_id(id: &str) -> u64 {
+    if ARGS.hash_ids {
+        hashid_to_u64(id).unwrap_or(0)
+    } else {
+        to_u64(id).unwrap_or(0)
+    }
+}
+
+fn redirect(location: String) -> HttpResponse {
+    HttpResponse::Found()
+        .append_header(("Location", location))
+        .finish()
+}
+
+fn error_page(req: &HttpRequest) -> HttpResponse {
+    let text = request_translation(req);
+
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(ErrorTemplate { args: &ARGS, text }.render().unwrap())
+}
+
+/// Lists the collections, unless listing is turned off, and offers a form
+/// for creating one.
+#[get("/collections")]
+pub async fn get_collections(req: HttpRequest, data: web::Data<AppState>) -> HttpResponse {
+    let collections = data.collections.lock().unwrap();
+
+    let mut listed: Vec<&Collection> = if ARGS.no_listing {
+        Vec::new()
+    } else {
+        collections.iter().collect()
+    };
+    listed.sort_by_key(|c| std::cmp::Reverse(c.created));
+
+    let text = request_translation(&req);
+
+    HttpResponse::Ok().content_type("text/html; charset=utf-8").body(
+        CollectionsTemplate {
+            collections: listed,
+            args: &ARGS,
+            text,
+        }
+        .render()
+        .unwrap(),
+    )
+}
+
+#[post("/collections")]
+pub async fn post_collections(
+    data: web::Data<AppState>,
+    mut payload: Multipart,
+) -> Result<HttpResponse, Error> {
+    let form = read_collection_form(&mut payload).await?;
+
+    let known: Vec<u64> = data.pastas.lock().unwrap().iter().map(|p| p.id).collect();
+    let mut collections = data.collections.lock().unwrap();
+
+    let mut id = rand::thread_rng().gen::<u16>() as u64;
+    while collections.iter().any(|c| c.id == id) {
+        id = rand::thread_rng().gen::<u16>() as u64;
+    }
+
+    let created = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |n| n.as_secs() as i64);
+
+    let collection = Collection {
+        id,
+        name: if form.name.is_empty() {
+            format!("Collection {}", id)
+        } else {
+            form.name
+        },
+        description: form.description,
+        members: parse_members(&form.members)
+            .into_iter()
+            .filter(|id| known.contains(id))
+            .collect(),
+        encrypted_key: if form.password.is_empty() {
+            None
+        } else {
+            Some(encrypt(&id.to_string(), &form.password))
+        },
+        created,
+    };
+
+    let location = format!(
+        "{}/collection/{}",
+        ARGS.public_path_as_str(),
+        collection.id_as_animals()
+    );
+
+    collections.push(collection);
+    save_collection(&collections, collections.last().unwrap());
+
+    Ok(redirect(location))
+}
+
+fn collection_page(
+    req: &HttpRequest,
+    data: &web::Data<AppState>,
+    id: &str,
+    status: String,
+) -> HttpResponse {
+    let mut pastas = data.pastas.lock().unwrap();
+    let collections = data.collections.lock().unwrap();
+
+    remove_expired(&mut pastas);
+
+    let id = intern_id(id);
+    let Some(collection) = collections.iter().find(|c| c.id == id) else {
+        return error_page(req);
+    };
+
+    for id in &collection.members {
+        if let Some(index) = pastas.iter().position(|p| p.id == *id) {
+            if pastas[index].access_log.is_some() {
+                access_log::record(&mut pastas[index], req, "collection");
+                update(Some(&pastas), Some(&pastas[index]));
+            }
+        }
+    }
+
+    let members = collection
+        .members
+        .iter()
+        .filter_map(|id| pastas.iter().find(|p| p.id == *id))
+        .collect();
+
+    let text = request_translation(req);
+
+    HttpResponse::Ok().content_type("text/html; charset=utf-8").body(
+        CollectionTemplate {
+            collection,
+            members,
+            status,
+            args: &ARGS,
+            text,
+        }
+        .render()
+        .unwrap(),
+    )
+}
+
+#[get("/collection/{id}")]
+pub async fn get_collection(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    id: web::Path<String>,
+) -> HttpResponse {
+    collection_page(&req, &data, &id, String::from(""))
+}
+
+#[get("/collection/{id}/{status}")]
+pub async fn get_collection_with_status(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    param: web::Path<(String, String)>,
+) -> HttpResponse {
+    let (id, status) = param.into_inner();
+    collection_page(&req, &data, &id, status)
+}
+
+/// Changes or deletes a collection. Password protected collections need
+/// their password or the admin password, deleting one without a password
+/// needs the admin password.
+#[post("/collection/{id}")]
+pub async fn post_collection(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    id: web::Path<String>,
+    mut payload: Multipart,
+) -> Result<HttpResponse, Error> {
+    let form = read_collection_form(&mut payload).await?;
+
+    let known: Vec<u64> = data.pastas.lock().unwrap().iter().map(|p| p.id).collect();
+    let mut collections = data.collections.lock().unwrap();
+
+    let id = intern_id(&id);
+    let Some(index) = collections.iter().position(|c| c.id == id) else {
+        return Ok(error_page(&req));
+    };
+
+    let admin = !form.admin_password.is_empty() && form.admin_password == ARGS.auth_admin_password;
+    let allowed = admin
+        || if form.delete {
+            collections[index].has_password() && collections[index].password_matches(&form.password)
+        } else {
+            collections[index].password_matches(&form.password)
+        };
+
+    if !allowed {
+        return Ok(redirect(format!(
+            "{}/collection/{}/incorrect",
+            ARGS.public_path_as_str(),
+            collections[index].id_as_animals()
+        )));
+    }
+
+    if form.delete {
+        collections.remove(index);
+        delete_collection(&collections, id);
+        return Ok(redirect(format!("{}/collections", ARGS.public_path_as_str())));
+    }
+
+    let collection = &mut collections[index];
+    if !form.name.is_empty() {
+        collection.name = form.name;
+    }
+    collection.description = form.description;
+    collection.members = parse_members(&form.members)
+        .into_iter()
+        .filter(|id| known.contains(id))
+        .collect();
+
+    let location = format!(
+        "{}/collection/{}/saved",
+        ARGS.public_path_as_str(),
+        collection.id_as_animals()
+    );
+
+    save_collection(&collections, &collections[index]);
+
+    Ok(redirect(location))
+}
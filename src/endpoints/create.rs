@@ -1,9 +1,14 @@
-use crate::pasta::PastaFile;
-use crate::translation::{get_translation, Translation};
-use crate::util::animalnumbers::to_animal_names;
+use crate::pasta::{Link, PastaFile};
+use crate::translation::{request_translation, Translation};
+use crate::util::animalnumbers::{to_animal_names, to_u64};
 use crate::util::db::insert;
-use crate::util::hashids::to_hashids;
-use crate::util::misc::{encrypt, encrypt_file, is_valid_url};
+use crate::util::diff::{compute_patch, has_hunks, parse_patch};
+use crate::util::expiration::{default_expiration, parse_burn_after, parse_expiration, Bounds};
+use crate::util::hashids::{to_hashids, to_u64 as hashid_to_u64};
+use crate::util::links::{parse_status, validate_slug};
+use crate::util::misc::{encrypt, encrypt_file, is_valid_url, parse_tags, save_upload};
+use crate::util::security_headers::csp_nonce;
+use crate::util::webhooks::{notify, Event};
 use crate::{AppState, Pasta, ARGS};
 use actix_multipart::Multipart;
 use actix_web::error::ErrorBadRequest;
@@ -15,27 +20,44 @@ use bytesize::ByteSize;
 use futures::TryStreamExt;
 use log::warn;
 use rand::Rng;
-use std::io::Write;
+use std::sync::atomic::Ordering;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 #[derive(Template)]
 #[template(path = "index.html")]
-struct IndexTemplate<'a> {
-    args: &'a ARGS,
-    status: String,
-    text: Translation,
+pub struct IndexTemplate<'a> {
+    pub args: &'a ARGS,
+    pub status: String,
+    pub text: Translation,
+    pub nonce: String,
+    pub fork: ForkSource,
+}
+
+/// Values to pre-fill the upload form with when forking a pasta. Left empty
+/// for a normal upload.
+#[derive(Default)]
+pub struct ForkSource {
+    pub id: String,
+    pub title: String,
+    pub content: String,
+    pub extension: String,
+    /// Comma separated, as in the form.
+    pub tags: String,
+    /// Name of the attachment that can be copied along, if any.
+    pub file_name: String,
 }
 
 #[get("/")]
 pub async fn index(req: HttpRequest) -> impl Responder {
-    let lang = req.cookie("lang").map(|c| c.value().to_string()).unwrap_or_else(|| "zh".to_string());
-    let text = get_translation(&lang);
+    let text = request_translation(&req);
 
     HttpResponse::Ok().content_type("text/html; charset=utf-8").body(
         IndexTemplate {
             args: &ARGS,
             status: String::from(""),
             text,
+            nonce: csp_nonce(&req),
+            fork: ForkSource::default(),
         }
         .render()
         .unwrap(),
@@ -45,38 +67,104 @@ pub async fn index(req: HttpRequest) -> impl Responder {
 #[get("/{status}")]
 pub async fn index_with_status(req: HttpRequest, param: web::Path<String>) -> HttpResponse {
     let status = param.into_inner();
-    let lang = req.cookie("lang").map(|c| c.value().to_string()).unwrap_or_else(|| "zh".to_string());
-    let text = get_translation(&lang);
+    let text = request_translation(&req);
 
     return HttpResponse::Ok().content_type("text/html; charset=utf-8").body(
         IndexTemplate {
             args: &ARGS,
             status,
             text,
+            nonce: csp_nonce(&req),
+            fork: ForkSource::default(),
         }
         .render()
         .unwrap(),
     );
 }
 
-pub fn expiration_to_timestamp(expiration: &str, timenow: i64) -> i64 {
-    match expiration {
-        "1min" => timenow + 60,
-        "10min" => timenow + 60 * 10,
-        "1hour" => timenow + 60 * 60,
-        "24hour" => timenow + 60 * 60 * 24,
-        "3days" => timenow + 60 * 60 * 24 * 3,
-        "1week" => timenow + 60 * 60 * 24 * 7,
-        "never" => {
-            if ARGS.eternal_pasta {
-                0
-            } else {
-                timenow + 60 * 60 * 24 * 7
+/// The expiration and burn after count from the form fields, `None` where
+/// the form left them out.
+fn resolve_settings(
+    expiration: Option<String>,
+    expiration_custom: &str,
+    burn_after: Option<String>,
+    burn_after_custom: &str,
+    timenow: i64,
+) -> Result<(Option<i64>, Option<u64>), String> {
+    let expiration = match expiration_custom.trim() {
+        "" => expiration.filter(|e| !e.trim().is_empty()),
+        custom => Some(custom.to_string()),
+    };
+    let burn_after = match burn_after_custom.trim() {
+        "" => burn_after.filter(|b| !b.trim().is_empty()),
+        custom => Some(custom.to_string()),
+    };
+
+    Ok((
+        expiration
+            .map(|e| parse_expiration(&e, timenow, &Bounds::from_args()))
+            .transpose()?,
+        burn_after
+            .map(|b| parse_burn_after(&b, ARGS.max_burn_after))
+            .transpose()?,
+    ))
+}
+
+/// Turns the upload into a diff pasta if the form asked for one: `diff` keeps
+/// the content as the patch, `compare` replaces it with the diff from the
+/// content to `diff_new`.
+fn prepare_diff(pasta: &mut Pasta, requested: &str, diff_new: &str) -> Result<(), String> {
+    match requested {
+        "diff" | "compare" if pasta.encrypt_client => {
+            return Err(String::from("Secret uploads can't be shown as a diff."));
+        }
+        "diff" => {
+            if !has_hunks(&parse_patch(&pasta.content)) {
+                return Err(String::from("The content isn't a unified diff."));
             }
         }
-        _ => {
-            log::error!("{}", "Unexpected expiration time!");
-            timenow + 60 * 60 * 24 * 7
+        "compare" => {
+            let patch = compute_patch(&pasta.content, diff_new);
+            if !has_hunks(&parse_patch(&patch)) {
+                return Err(String::from("The two texts are the same."));
+            }
+            pasta.content = patch;
+        }
+        _ => return Ok(()),
+    }
+    pasta.pasta_type = String::from("diff");
+    pasta.extension = String::from("diff");
+    Ok(())
+}
+
+/// Redirect settings for URL pastas. A custom link is refused for anything
+/// else, the other settings only matter for redirects and are ignored.
+fn prepare_link(pasta: &mut Pasta, pastas: &[Pasta], slug: &str, status: &str, preview: bool) -> Result<(), String> {
+    if pasta.pasta_type != "url" {
+        if !slug.trim().is_empty() {
+            return Err(String::from("Custom links only work for uploads of a single URL."));
+        }
+        return Ok(());
+    }
+    let slug = match slug.trim() {
+        "" => None,
+        slug => Some(validate_slug(slug, pastas)?),
+    };
+    pasta.link = Some(Link {
+        slug,
+        status: parse_status(status)?,
+        preview,
+        clicks: Vec::new(),
+    });
+    Ok(())
+}
+
+/// Removes the attachment of an upload that was turned down.
+fn discard_attachment(pasta: &Pasta) {
+    if pasta.file.is_some() {
+        let dirpath = format!("{}/attachments/{}", ARGS.data_dir, pasta.id_as_animals());
+        if let Err(e) = std::fs::remove_dir_all(&dirpath) {
+            log::error!("Failed to remove rejected upload {}: {}", dirpath, e);
         }
     }
 }
@@ -89,6 +177,12 @@ pub async fn create(
     data: web::Data<AppState>,
     mut payload: Multipart,
 ) -> Result<HttpResponse, Error> {
+    if data.shutting_down.load(Ordering::SeqCst) {
+        return Ok(HttpResponse::ServiceUnavailable()
+            .append_header(("Retry-After", "30"))
+            .body("MicroBin is shutting down, please try again shortly."));
+    }
+
     let mut pastas = data.pastas.lock().unwrap();
 
     let timenow: i64 = match SystemTime::now().duration_since(UNIX_EPOCH) {
@@ -116,12 +210,31 @@ pub async fn create(
         burn_after_reads: 0,
         last_read: timenow,
         pasta_type: String::from(""),
-        expiration: expiration_to_timestamp(&ARGS.default_expiry, timenow),
+        expiration: default_expiration(timenow, &Bounds::from_args()),
+        revisions: Vec::new(),
+        version: 1,
+        parent: None,
+        tags: Vec::new(),
+        access_log: None,
+        link: None,
     };
 
     let mut random_key: String = String::from("");
     let mut plain_key: String = String::from("");
     let mut uploader_password = String::from("");
+    let mut fork_attachment = false;
+    // a filled in custom value wins over the dropdown
+    let mut expiration = None;
+    let mut expiration_custom = String::from("");
+    let mut burn_after = None;
+    let mut burn_after_custom = String::from("");
+    // "diff" or "compare", and the changed text to compare the content with
+    let mut requested_type = String::from("");
+    let mut diff_new = String::from("");
+    // custom slug, status code and preview page of a URL upload
+    let mut link_slug = String::from("");
+    let mut redirect_status = String::from("");
+    let mut link_preview = false;
 
     while let Some(mut field) = payload.try_next().await? {
         let Some(field_name) = field.name() else {
@@ -176,30 +289,18 @@ pub async fn create(
                 }
                 continue;
             }
-            "expiration" => {
+            "expiration" | "expiration_custom" | "burn_after" | "burn_after_custom" => {
+                let name = field_name.to_string();
+                let mut value = String::from("");
                 while let Some(chunk) = field.try_next().await? {
-                    new_pasta.expiration =
-                        expiration_to_timestamp(std::str::from_utf8(&chunk).unwrap(), timenow);
+                    value.push_str(std::str::from_utf8(&chunk).unwrap_or(""));
                 }
-
-                continue;
-            }
-            "burn_after" => {
-                while let Some(chunk) = field.try_next().await? {
-                    new_pasta.burn_after_reads = match std::str::from_utf8(&chunk).unwrap() {
-                        "1" => 1,
-                        "10" => 10,
-                        "100" => 100,
-                        "1000" => 1000,
-                        "10000" => 10000,
-                        "0" => 0,
-                        _ => {
-                            log::error!("{}", "Unexpected burn after value!");
-                            0
-                        }
-                    };
+                match name.as_str() {
+                    "expiration" => expiration = Some(value),
+                    "expiration_custom" => expiration_custom = value,
+                    "burn_after" => burn_after = Some(value),
+                    _ => burn_after_custom = value,
                 }
-
                 continue;
             }
             "content" => {
@@ -218,6 +319,22 @@ pub async fn create(
                 }
                 continue;
             }
+            "pasta_type" | "diff_new" | "link_slug" | "redirect_status" => {
+                let name = field_name.to_string();
+                let mut buf = BytesMut::new();
+                while let Some(chunk) = field.try_next().await? {
+                    buf.extend_from_slice(&chunk);
+                }
+                let value = String::from_utf8(buf.to_vec())
+                    .map_err(|_| ErrorBadRequest("Invalid UTF-8 in content"))?;
+                match name.as_str() {
+                    "pasta_type" => requested_type = value,
+                    "diff_new" => diff_new = value,
+                    "link_slug" => link_slug = value,
+                    _ => redirect_status = value,
+                }
+                continue;
+            }
             "syntax_highlight" => {
                 while let Some(chunk) = field.try_next().await? {
                     new_pasta.extension = std::str::from_utf8(&chunk).unwrap().to_string();
@@ -231,6 +348,42 @@ pub async fn create(
                 }
                 continue;
             }
+            "tags" => {
+                let mut tags = String::from("");
+                while let Some(chunk) = field.try_next().await? {
+                    tags.push_str(std::str::from_utf8(&chunk).unwrap_or(""));
+                }
+                new_pasta.tags = parse_tags(&tags);
+                continue;
+            }
+            "fork_of" => {
+                let mut fork_of = String::from("");
+                while let Some(chunk) = field.try_next().await? {
+                    fork_of.push_str(std::str::from_utf8(&chunk).unwrap());
+                }
+                let parent_id = if ARGS.hash_ids {
+                    hashid_to_u64(&fork_of).ok()
+                } else {
+                    to_u64(&fork_of).ok()
+                };
+                new_pasta.parent = parent_id.filter(|id| pastas.iter().any(|p| p.id == *id));
+                continue;
+            }
+            "fork_attachment" => {
+                while field.try_next().await?.is_some() {}
+                fork_attachment = true;
+                continue;
+            }
+            "link_preview" => {
+                while field.try_next().await?.is_some() {}
+                link_preview = true;
+                continue;
+            }
+            "access_log" => {
+                while field.try_next().await?.is_some() {}
+                new_pasta.access_log = Some(Vec::new());
+                continue;
+            }
             "file" => {
                 if ARGS.no_file_upload {
                     continue;
@@ -252,34 +405,35 @@ pub async fn create(
                     }
                 };
 
-                std::fs::create_dir_all(format!(
+                let dirpath = format!(
                     "{}/attachments/{}",
                     ARGS.data_dir,
                     &new_pasta.id_as_animals()
-                ))
-                .unwrap();
-
-                let filepath = format!(
-                    "{}/attachments/{}/{}",
-                    ARGS.data_dir,
-                    &new_pasta.id_as_animals(),
-                    &file.name()
                 );
+                std::fs::create_dir_all(&dirpath).unwrap();
 
-                let mut f = web::block(|| std::fs::File::create(filepath)).await??;
-                let mut size = 0;
-                while let Some(chunk) = field.try_next().await? {
-                    size += chunk.len();
-                    if (new_pasta.encrypt_server
-                        && size > ARGS.max_file_size_encrypted_mb * 1024 * 1024)
-                        || size > ARGS.max_file_size_unencrypted_mb * 1024 * 1024
-                    {
-                        return Err(ErrorBadRequest("File exceeded size limit."));
+                let filepath = format!("{}/{}", dirpath, &file.name());
+
+                let max_size = if new_pasta.encrypt_server {
+                    ARGS.max_file_size_encrypted_mb.min(ARGS.max_file_size_unencrypted_mb)
+                } else {
+                    ARGS.max_file_size_unencrypted_mb
+                } * 1024
+                    * 1024;
+
+                // don't leave half-written attachments behind
+                let (size, mime) = match save_upload(&mut field, &filepath, max_size).await {
+                    Ok(upload) => upload,
+                    Err(e) => {
+                        if let Err(e) = std::fs::remove_dir_all(&dirpath) {
+                            log::error!("Failed to remove partial upload {}: {}", dirpath, e);
+                        }
+                        return Err(e);
                     }
-                    f = web::block(move || f.write_all(&chunk).map(|_| f)).await??;
-                }
+                };
 
                 file.size = ByteSize::b(size as u64);
+                file.mime = mime;
 
                 new_pasta.file = Some(file);
                 new_pasta.pasta_type = String::from("text");
@@ -290,6 +444,35 @@ pub async fn create(
         }
     }
 
+    let settings = resolve_settings(
+        expiration,
+        &expiration_custom,
+        burn_after,
+        &burn_after_custom,
+        timenow,
+    );
+    match settings {
+        Ok((expiration, burn_after)) => {
+            if let Some(expiration) = expiration {
+                new_pasta.expiration = expiration;
+            }
+            if let Some(burn_after) = burn_after {
+                new_pasta.burn_after_reads = burn_after;
+            }
+        }
+        Err(message) => {
+            discard_attachment(&new_pasta);
+            return Err(ErrorBadRequest(message));
+        }
+    }
+
+    if let Err(message) = prepare_diff(&mut new_pasta, &requested_type, &diff_new)
+        .and_then(|_| prepare_link(&mut new_pasta, &pastas, &link_slug, &redirect_status, link_preview))
+    {
+        discard_attachment(&new_pasta);
+        return Err(ErrorBadRequest(message));
+    }
+
     if ARGS.readonly && ARGS.uploader_password.is_some() {
         if uploader_password.trim() != ARGS.uploader_password.as_ref().unwrap().trim() {
             log::warn!("Uploader password mismatch. Input length: {}, Expected length: {}", uploader_password.trim().len(), ARGS.uploader_password.as_ref().unwrap().trim().len());
@@ -299,9 +482,48 @@ pub async fn create(
         }
     }
 
+    // copy the attachment of the forked pasta unless a new file was uploaded.
+    // Encrypted attachments can't be copied, the server doesn't know the key,
+    // and copying from burn after reading pastas would bypass the read count.
+    if fork_attachment && new_pasta.file.is_none() && !ARGS.no_file_upload {
+        let parent = new_pasta
+            .parent
+            .and_then(|id| pastas.iter().find(|p| p.id == id))
+            .filter(|p| !p.encrypt_server && !p.burns_after_reading());
+        if let Some((parent, file)) = parent.and_then(|p| p.file.as_ref().map(|f| (p, f))) {
+            let dirpath = format!(
+                "{}/attachments/{}",
+                ARGS.data_dir,
+                &new_pasta.id_as_animals()
+            );
+            std::fs::create_dir_all(&dirpath)?;
+            std::fs::copy(
+                format!(
+                    "{}/attachments/{}/{}",
+                    ARGS.data_dir,
+                    parent.id_as_animals(),
+                    file.name()
+                ),
+                format!("{}/{}", dirpath, file.name()),
+            )?;
+
+            new_pasta.file = Some(PastaFile {
+                name: file.name.to_owned(),
+                size: file.size,
+                mime: file.mime.to_owned(),
+            });
+            if new_pasta.pasta_type.is_empty() {
+                new_pasta.pasta_type = String::from("text");
+            }
+        }
+    }
+
+    let content = new_pasta.content.clone();
+    new_pasta.detect_extension(&content);
+
     let id = new_pasta.id;
 
-    if plain_key != *"" && new_pasta.readonly {
+    if plain_key != *"" && (new_pasta.readonly || (new_pasta.encrypt_server && !new_pasta.encrypt_client)) {
         new_pasta.encrypted_key = Some(encrypt(id.to_string().as_str(), &plain_key));
     }
 
@@ -339,6 +561,7 @@ pub async fn create(
     for (_, pasta) in pastas.iter().enumerate() {
         if pasta.id == id {
             insert(Some(&pastas), Some(pasta));
+            notify(Event::Created, pasta);
         }
     }
 
@@ -1,17 +1,371 @@
-use crate::translation::{get_translation, Translation};
+use crate::translation::{request_translation, Translation};
 use crate::args::Args;
 use crate::endpoints::errors::ErrorTemplate;
 use crate::util::animalnumbers::to_u64;
 use crate::util::db::update;
+use crate::util::diff::unified_diff_html;
+use crate::util::expiration::{parse_burn_after, parse_expiration, Bounds};
 use crate::util::hashids::to_u64 as hashid_to_u64;
-use crate::util::misc::{decrypt, encrypt, remove_expired};
+use crate::pasta::PastaFile;
+use crate::util::misc::{
+    decrypt, decrypt_file, encrypt, parse_tags, remove_expired, save_upload,
+};
+use crate::util::webhooks::{notify, Event};
 use crate::{AppState, Pasta, ARGS};
 use actix_multipart::Multipart;
 use actix_web::error::ErrorBadRequest;
-use actix_web::{get, post, web, Error, HttpResponse, HttpRequest};
+use actix_web::http::header;
+use actix_web::{get, post, put, web, Error, HttpResponse, HttpRequest};
 use askama::Template;
 use bytes::BytesMut;
+use bytesize::ByteSize;
 use futures::TryStreamExt;
+use magic_crypt::{new_magic_crypt, MagicCryptTrait};
+use rand::Rng;
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Trims the optional editor name from the edit form to something that fits
+/// in the history table.
+pub fn sanitize_editor(editor: &str) -> String {
+    editor.trim().chars().take(64).collect()
+}
+
+/// A replacement attachment streamed to a temporary file while the form is
+/// read. The file is removed again unless it was moved into the pasta.
+struct PendingUpload {
+    path: String,
+    file: PastaFile,
+}
+
+impl Drop for PendingUpload {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// The settings part of the edit form. Empty fields leave a setting as it is.
+#[derive(Default)]
+struct EditSettings {
+    /// New expiration timestamp, 0 for never.
+    expiration: Option<i64>,
+    burn_after: Option<u64>,
+    extension: Option<String>,
+    privacy: Option<String>,
+    new_password: String,
+    admin_password: String,
+    remove_file: bool,
+    upload: Option<PendingUpload>,
+}
+
+impl EditSettings {
+    /// Whether submitting these settings would change anything on `pasta`.
+    fn changes(&self, pasta: &Pasta) -> bool {
+        self.expiration.is_some()
+            || self.burn_after.is_some()
+            || self.extension.as_ref().is_some_and(|e| *e != pasta.extension)
+            || self.privacy.as_ref().is_some_and(|p| p != pasta.privacy())
+            || !self.new_password.is_empty()
+            || (self.remove_file && pasta.file.is_some())
+            || self.upload.is_some()
+    }
+
+    /// Read-only uploads have had their password checked already, anything
+    /// else can only be reconfigured here with the admin password. Private
+    /// uploads change their settings through the private edit form instead.
+    fn authorized(&self, pasta: &Pasta) -> bool {
+        pasta.readonly
+            || (!self.admin_password.is_empty() && self.admin_password == ARGS.auth_admin_password)
+    }
+}
+
+struct EditForm {
+    content: String,
+    title: String,
+    password: String,
+    editor: String,
+    version: Option<u64>,
+    /// `None` if the form had no tags field
+    tags: Option<Vec<String>>,
+    settings: EditSettings,
+}
+
+async fn read_edit_form(payload: &mut Multipart) -> Result<EditForm, Error> {
+    let mut form = EditForm {
+        content: String::from(""),
+        title: String::from(""),
+        password: String::from(""),
+        editor: String::from(""),
+        version: None,
+        tags: None,
+        settings: EditSettings::default(),
+    };
+
+    while let Some(mut field) = payload.try_next().await? {
+        let Some(name) = field.name().map(|n| n.to_string()) else {
+            continue;
+        };
+
+        if name == "file" {
+            if ARGS.no_file_upload {
+                continue;
+            }
+            let file = match field.content_disposition().and_then(|cd| cd.get_filename()) {
+                Some("") | None => continue,
+                Some(path) => match PastaFile::from_unsanitized(path) {
+                    Ok(file) => file,
+                    Err(e) => {
+                        log::warn!("Unsafe file name: {e:?}");
+                        continue;
+                    }
+                },
+            };
+
+            let dirpath = format!("{}/attachments", ARGS.data_dir);
+            fs::create_dir_all(&dirpath)?;
+            let mut upload = PendingUpload {
+                path: format!("{}/.pending-{}", dirpath, rand::thread_rng().gen::<u64>()),
+                file,
+            };
+
+            // the encrypted limit is checked once the new privacy is known
+            let (size, mime) =
+                save_upload(&mut field, &upload.path, ARGS.max_file_size_unencrypted_mb * 1024 * 1024)
+                    .await?;
+            upload.file.size = ByteSize::b(size as u64);
+            upload.file.mime = mime;
+            form.settings.upload = Some(upload);
+            continue;
+        }
+
+        let mut buf = BytesMut::new();
+        while let Some(chunk) = field.try_next().await? {
+            buf.extend_from_slice(&chunk);
+        }
+        let value = String::from_utf8(buf.to_vec())
+            .map_err(|_| ErrorBadRequest(format!("Invalid UTF-8 in {}", name)))?;
+
+        match name.as_str() {
+            "content" => form.content = value,
+            "title" => form.title = value,
+            "password" => form.password = value,
+            "editor" => form.editor = sanitize_editor(&value),
+            "version" => form.version = value.trim().parse().ok(),
+            "tags" => form.tags = Some(parse_tags(&value)),
+            // a filled in custom value wins over the dropdown
+            "expiration" | "expiration_custom"
+                if !value.trim().is_empty()
+                    && (name == "expiration_custom" || form.settings.expiration.is_none()) =>
+            {
+                let timenow = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |n| n.as_secs() as i64);
+                form.settings.expiration = Some(
+                    parse_expiration(&value, timenow, &Bounds::from_args()).map_err(ErrorBadRequest)?,
+                );
+            }
+            "burn_after" | "burn_after_custom"
+                if !value.trim().is_empty()
+                    && (name == "burn_after_custom" || form.settings.burn_after.is_none()) =>
+            {
+                form.settings.burn_after =
+                    Some(parse_burn_after(&value, ARGS.max_burn_after).map_err(ErrorBadRequest)?);
+            }
+            "syntax_highlight" if !value.is_empty() => form.settings.extension = Some(value),
+            "privacy" if !value.is_empty() => form.settings.privacy = Some(value),
+            "new_password" => form.settings.new_password = value,
+            "admin_password" => form.settings.admin_password = value,
+            "remove_file" => form.settings.remove_file = value == "on",
+            _ => {}
+        }
+    }
+
+    Ok(form)
+}
+
+/// Applies the settings part of the edit form to `pasta`. `password` is the
+/// pasta's current, already verified password (empty if it has none).
+///
+/// Changing privacy or password decrypts the content, every revision and the
+/// attachment with the old key and encrypts them again with the new one. The
+/// pasta is only touched once all of that has worked, so a failed edit leaves
+/// it readable with the old password.
+/// Returns the key new content has to be encrypted with, if any.
+fn apply_settings(
+    pasta: &mut Pasta,
+    mut settings: EditSettings,
+    password: &str,
+) -> Result<Option<String>, Error> {
+    let privacy = settings
+        .privacy
+        .clone()
+        .unwrap_or_else(|| pasta.privacy().to_string());
+
+    let allowed = match privacy.as_str() {
+        "public" => true,
+        "unlisted" => ARGS.private,
+        "readonly" => ARGS.enable_readonly,
+        "private" => ARGS.encryption_server_side,
+        _ => false,
+    };
+    if !allowed {
+        return Err(ErrorBadRequest("This privacy setting can not be chosen here."));
+    }
+
+    let new_key = if privacy == "readonly" || privacy == "private" {
+        let key = if settings.new_password.is_empty() {
+            password
+        } else {
+            &settings.new_password
+        };
+        if key.is_empty() {
+            return Err(ErrorBadRequest("This privacy setting needs a password."));
+        }
+        Some(key.to_string())
+    } else {
+        None
+    };
+
+    let old_content_key = (pasta.encrypt_server && !pasta.readonly).then(|| password.to_string());
+    let new_content_key = if privacy == "private" { new_key.clone() } else { None };
+    let rekey = old_content_key != new_content_key;
+
+    if let Some(upload) = &settings.upload {
+        if new_content_key.is_some()
+            && upload.file.size.as_u64() > ARGS.max_file_size_encrypted_mb as u64 * 1024 * 1024
+        {
+            return Err(ErrorBadRequest("File exceeded size limit."));
+        }
+    }
+
+    // re-key the content and its history, all or nothing
+    let recoded = if rekey {
+        let recode = |text: &str| -> Result<String, Error> {
+            let plain = match &old_content_key {
+                Some(key) => decrypt(text, key).map_err(|_| ErrorBadRequest("Incorrect password."))?,
+                None => text.to_string(),
+            };
+            Ok(match &new_content_key {
+                Some(key) => encrypt(&plain, key),
+                None => plain,
+            })
+        };
+        let content = recode(&pasta.content)?;
+        let revisions = pasta
+            .revisions
+            .iter()
+            .map(|r| recode(&r.content))
+            .collect::<Result<Vec<String>, Error>>()?;
+        Some((content, revisions))
+    } else {
+        None
+    };
+
+    let attachments = format!("{}/attachments", ARGS.data_dir);
+    let dirpath = format!("{}/{}", attachments, pasta.id_as_animals());
+    let encrypted_path = format!("{}/data.enc", dirpath);
+    let stage = |bytes: &[u8], file: &PastaFile| -> Result<PendingUpload, Error> {
+        fs::create_dir_all(&attachments)?;
+        let staged = PendingUpload {
+            path: format!("{}/.pending-{}", attachments, rand::thread_rng().gen::<u64>()),
+            file: file.clone(),
+        };
+        fs::write(&staged.path, bytes)?;
+        Ok(staged)
+    };
+    let seal = |bytes: Vec<u8>| match &new_content_key {
+        Some(key) => new_magic_crypt!(key, 256).encrypt_bytes_to_bytes(&bytes),
+        None => bytes,
+    };
+
+    // write the attachment as it should end up to a temporary file, along
+    // with where it goes and which of the old files have to go
+    let mut staged: Option<(PendingUpload, String)> = None;
+    let mut stale = Vec::new();
+
+    if settings.remove_file || settings.upload.is_some() {
+        if let Some(file) = &pasta.file {
+            stale.push(format!("{}/{}", dirpath, file.name()));
+            stale.push(encrypted_path.clone());
+        }
+    } else if let (Some(file), true) = (&pasta.file, rekey) {
+        let plain_path = format!("{}/{}", dirpath, file.name());
+        let plain = match &old_content_key {
+            Some(key) => Some(
+                fs::File::open(&encrypted_path)
+                    .ok()
+                    .and_then(|f| decrypt_file(key, &f).ok())
+                    .ok_or_else(|| ErrorBadRequest("Failed to decrypt the attachment."))?,
+            ),
+            None if Path::new(&plain_path).exists() => Some(fs::read(&plain_path)?),
+            None => None,
+        };
+        if let Some(plain) = plain {
+            let (target, old) = match &new_content_key {
+                Some(_) => (encrypted_path.clone(), plain_path),
+                None => (plain_path, encrypted_path.clone()),
+            };
+            staged = Some((stage(&seal(plain), file)?, target));
+            stale.push(old);
+        }
+    }
+
+    if let Some(mut upload) = settings.upload.take() {
+        if new_content_key.is_some() {
+            let sealed = seal(fs::read(&upload.path)?);
+            upload = stage(&sealed, &upload.file)?;
+        }
+        let target = match &new_content_key {
+            Some(_) => encrypted_path.clone(),
+            None => format!("{}/{}", dirpath, upload.file.name()),
+        };
+        staged = Some((upload, target));
+    }
+
+    // nothing below can leave the pasta half re-keyed
+    let mut file = if stale.is_empty() { pasta.file.clone() } else { None };
+    if let Some((upload, target)) = &staged {
+        fs::create_dir_all(&dirpath)?;
+        fs::rename(&upload.path, target)?;
+        file = Some(upload.file.clone());
+    }
+    for path in stale.iter().filter(|p| staged.as_ref().map_or(true, |(_, t)| t != *p)) {
+        let _ = fs::remove_file(path);
+    }
+    pasta.file = file;
+
+    if let Some((content, revisions)) = recoded {
+        pasta.content = content;
+        for (revision, content) in pasta.revisions.iter_mut().zip(revisions) {
+            revision.content = content;
+        }
+    }
+
+    if let Some(expiration) = settings.expiration {
+        pasta.expiration = expiration;
+    }
+    if let Some(burn_after) = settings.burn_after {
+        pasta.burn_after_reads = if burn_after == 0 {
+            0
+        } else {
+            pasta.read_count + burn_after
+        };
+    }
+    if let Some(extension) = settings.extension {
+        pasta.extension = extension;
+    }
+
+    pasta.private = privacy != "public";
+    pasta.readonly = privacy == "readonly";
+    pasta.encrypt_server = privacy == "private";
+    pasta.encrypted_key = match &new_key {
+        Some(key) => Some(encrypt(&pasta.id.to_string(), key)),
+        None => Some(String::from("")),
+    };
+
+    Ok(new_content_key)
+}
 
 #[derive(Template)]
 #[template(path = "edit.html", escape = "none")]
@@ -23,6 +377,53 @@ struct EditTemplate<'a> {
     text: Translation,
 }
 
+#[derive(Template)]
+#[template(path = "conflict.html")]
+struct ConflictTemplate<'a> {
+    pasta: &'a Pasta,
+    path: &'a str,
+    current: String,
+    yours: String,
+    title: String,
+    editor: String,
+    diff: String,
+    args: &'a Args,
+    text: Translation,
+}
+
+/// Page shown instead of saving when the pasta changed since the editor
+/// loaded it. `current` is the decrypted content of the pasta as it is now.
+fn conflict_response(
+    req: &HttpRequest,
+    pasta: &Pasta,
+    path: &str,
+    current: String,
+    yours: String,
+    title: String,
+    editor: String,
+) -> HttpResponse {
+    let text = request_translation(req);
+
+    HttpResponse::Conflict()
+        .content_type("text/html; charset=utf-8")
+        .insert_header((header::ETAG, pasta.etag()))
+        .body(
+            ConflictTemplate {
+                pasta,
+                path,
+                diff: unified_diff_html(&current, &yours),
+                current,
+                yours,
+                title: if title.is_empty() { pasta.title.to_owned() } else { title },
+                editor,
+                args: &ARGS,
+                text,
+            }
+            .render()
+            .unwrap(),
+        )
+}
+
 #[get("/edit/{id}")]
 pub async fn get_edit(req: HttpRequest, data: web::Data<AppState>, id: web::Path<String>) -> HttpResponse {
     let mut pastas = data.pastas.lock().unwrap();
@@ -35,8 +436,7 @@ pub async fn get_edit(req: HttpRequest, data: web::Data<AppState>, id: web::Path
 
     remove_expired(&mut pastas);
     
-    let lang = req.cookie("lang").map(|c| c.value().to_string()).unwrap_or_else(|| "zh".to_string());
-    let text = get_translation(&lang);
+    let text = request_translation(&req);
 
     for pasta in pastas.iter() {
         if pasta.id == id {
@@ -92,8 +492,7 @@ pub async fn get_edit_with_status(
 
     remove_expired(&mut pastas);
     
-    let lang = req.cookie("lang").map(|c| c.value().to_string()).unwrap_or_else(|| "zh".to_string());
-    let text = get_translation(&lang);
+    let text = request_translation(&req);
 
     for pasta in pastas.iter() {
         if pasta.id == intern_id {
@@ -171,8 +570,7 @@ pub async fn post_edit_private(
         }
     }
     
-    let lang = req.cookie("lang").map(|c| c.value().to_string()).unwrap_or_else(|| "zh".to_string());
-    let text = get_translation(&lang);
+    let text = request_translation(&req);
 
     if found && !pastas[index].encrypt_client {
         let original_content = pastas[index].content.to_owned();
@@ -231,41 +629,24 @@ pub async fn post_submit_edit_private(
     id: web::Path<String>,
     mut payload: Multipart,
 ) -> Result<HttpResponse, Error> {
-    // get access to the pasta collection
-    let mut pastas = data.pastas.lock().unwrap();
-
     let id = if ARGS.hash_ids {
         hashid_to_u64(&id).unwrap_or(0)
     } else {
         to_u64(&id.into_inner()).unwrap_or(0)
     };
 
-    let mut password = String::from("");
-    let mut new_content = String::from("");
-    let mut new_title = String::from("");
+    let EditForm {
+        content: new_content,
+        title: new_title,
+        password,
+        editor,
+        version,
+        tags,
+        settings,
+    } = read_edit_form(&mut payload).await?;
 
-    while let Some(mut field) = payload.try_next().await? {
-        if field.name() == Some("content") {
-            let mut buf = BytesMut::new();
-            while let Some(chunk) = field.try_next().await? {
-                buf.extend_from_slice(&chunk);
-            }
-            if !buf.is_empty() {
-                new_content = String::from_utf8(buf.to_vec())
-                    .map_err(|_| ErrorBadRequest("Invalid UTF-8 in content"))?;
-            }
-        }
-        if field.name() == Some("title") {
-            while let Some(chunk) = field.try_next().await? {
-                new_title = std::str::from_utf8(&chunk).unwrap().to_string();
-            }
-        }
-        if field.name() == Some("password") {
-            while let Some(chunk) = field.try_next().await? {
-                password = std::str::from_utf8(&chunk).unwrap().to_string();
-            }
-        }
-    }
+    // get access to the pasta collection
+    let mut pastas = data.pastas.lock().unwrap();
 
     // remove expired pastas (including this one if needed)
     remove_expired(&mut pastas);
@@ -282,56 +663,91 @@ pub async fn post_submit_edit_private(
     }
 
     if found && pastas[index].editable && !pastas[index].encrypt_client {
-        if pastas[index].readonly {
-            let res = decrypt(pastas[index].encrypted_key.as_ref().unwrap(), &password);
-            if res.is_ok() {
-                pastas[index]
-                    .content
-                    .replace_range(.., &encrypt(&new_content, &password));
-            } else {
-                return Ok(HttpResponse::Found()
-                    .append_header((
-                        "Location",
-                        format!("{}/edit/{}/incorrect", ARGS.public_path_as_str(), pastas[index].id_as_animals()),
-                    ))
-                    .finish());
-            }
-        } else if pastas[index].private {
-            let res = decrypt(&pastas[index].content, &password);
-            if res.is_ok() {
-                pastas[index]
-                    .content
-                    .replace_range(.., &encrypt(&new_content, &password));
-                // Update title if provided
-                if !new_title.is_empty() {
-                    pastas[index].title = new_title.clone();
-                }
-                // save pasta in database
-                update(Some(&pastas), Some(&pastas[index]));
+        if pastas[index].readonly || pastas[index].private {
+            let pasta = &pastas[index];
+            // read-only pastas are stored as they are, private ones encrypted
+            // with their password
+            let (current, key, form_path, auth_path) = if pasta.readonly {
+                (
+                    pasta.edit_password_matches(&password).then(|| pasta.content.to_owned()),
+                    None,
+                    "edit",
+                    "edit",
+                )
             } else {
+                (
+                    decrypt(&pasta.content, &password)
+                        .ok()
+                        .filter(|_| pasta.edit_password_matches(&password)),
+                    Some(password.clone()),
+                    "submit_edit_private",
+                    "auth_edit_private",
+                )
+            };
+
+            let Some(current) = current else {
                 return Ok(HttpResponse::Found()
                     .append_header((
                         "Location",
                         format!(
-                            "{}/auth_edit_private/{}/incorrect",
+                            "{}/{}/{}/incorrect",
                             ARGS.public_path_as_str(),
-                            pastas[index].id_as_animals()
+                            auth_path,
+                            pasta.id_as_animals()
                         ),
                     ))
                     .finish());
+            };
+
+            if pasta.changed_since(version) {
+                return Ok(conflict_response(
+                    &req,
+                    pasta,
+                    form_path,
+                    current,
+                    new_content,
+                    new_title,
+                    editor,
+                ));
+            }
+            // settings go first, as they may change the key the new
+            // content is encrypted with
+            let key = if settings.changes(&pastas[index]) {
+                apply_settings(&mut pastas[index], settings, &password)?
+            } else {
+                key
+            };
+            pastas[index].detect_extension(&new_content);
+            let content = match key {
+                Some(key) => encrypt(&new_content, &key),
+                None => new_content,
+            };
+            pastas[index].set_content(content, &editor);
+            // Update title if provided
+            if !new_title.is_empty() {
+                pastas[index].title = new_title.clone();
+            }
+            if let Some(tags) = tags {
+                pastas[index].tags = tags;
             }
+            // save pasta in database
+            update(Some(&pastas), Some(&pastas[index]));
+            notify(Event::Edited, &pastas[index]);
         }
 
+        // the pasta may not be private anymore after a settings change
+        let location = if pastas[index].encrypt_server {
+            format!("{}/auth/{}/success", ARGS.public_path_as_str(), pastas[index].id_as_animals())
+        } else {
+            format!("{}/upload/{}", ARGS.public_path_as_str(), pastas[index].id_as_animals())
+        };
+
         return Ok(HttpResponse::Found()
-            .append_header((
-                "Location",
-                format!("{}/auth/{}/success", ARGS.public_path_as_str(), pastas[index].id_as_animals()),
-            ))
+            .append_header(("Location", location))
             .finish());
     }
     
-    let lang = req.cookie("lang").map(|c| c.value().to_string()).unwrap_or_else(|| "zh".to_string());
-    let text = get_translation(&lang);
+    let text = request_translation(&req);
     
     Ok(HttpResponse::Ok()
         .content_type("text/html; charset=utf-8")
@@ -351,94 +767,366 @@ pub async fn post_edit(
         to_u64(&id.into_inner()).unwrap_or(0)
     };
 
+    let EditForm {
+        content: new_content,
+        title: new_title,
+        password,
+        editor,
+        version,
+        tags,
+        settings,
+    } = read_edit_form(&mut payload).await?;
+
     let mut pastas = data.pastas.lock().unwrap();
 
     remove_expired(&mut pastas);
 
-    let mut new_content = String::from("");
-    let mut new_title = String::from("");
-    let mut password = String::from("");
+    let index = pastas
+        .iter()
+        .position(|p| p.id == id && p.editable && !p.encrypt_client);
 
-    while let Some(mut field) = payload.try_next().await? {
-        if field.name() == Some("content") {
-            let mut buf = BytesMut::new();
-            while let Some(chunk) = field.try_next().await? {
-                buf.extend_from_slice(&chunk);
-            }
-            if !buf.is_empty() {
-                new_content = String::from_utf8(buf.to_vec())
-                    .map_err(|_| ErrorBadRequest("Invalid UTF-8 in content"))?;
-            }
+    if let Some(i) = index {
+        let incorrect = HttpResponse::Found()
+            .append_header((
+                "Location",
+                format!("{}/edit/{}/incorrect", ARGS.public_path_as_str(), pastas[i].id_as_animals()),
+            ))
+            .finish();
+
+        if (pastas[i].readonly || pastas[i].encrypt_server)
+            && (password.is_empty() || !pastas[i].edit_password_matches(&password))
+        {
+            return Ok(incorrect);
         }
-        if field.name() == Some("title") {
-            while let Some(chunk) = field.try_next().await? {
-                new_title = std::str::from_utf8(&chunk).unwrap().to_string();
-            }
+
+        if pastas[i].changed_since(version) {
+            let current = if pastas[i].encrypt_server && !pastas[i].readonly {
+                match decrypt(&pastas[i].content, &password) {
+                    Ok(current) => current,
+                    Err(_) => return Ok(incorrect),
+                }
+            } else {
+                pastas[i].content.to_owned()
+            };
+            return Ok(conflict_response(
+                &req,
+                &pastas[i],
+                "edit",
+                current,
+                new_content,
+                new_title,
+                editor,
+            ));
         }
-        if field.name() == Some("password") {
-            while let Some(chunk) = field.try_next().await? {
-                password = std::str::from_utf8(&chunk).unwrap().to_string();
+
+        let mut key = (pastas[i].encrypt_server && !pastas[i].readonly).then(|| password.clone());
+        if settings.changes(&pastas[i]) {
+            if !settings.authorized(&pastas[i]) {
+                return Ok(incorrect);
             }
+            key = apply_settings(&mut pastas[i], settings, &password)?;
         }
-    }
+        pastas[i].detect_extension(&new_content);
+        let content = match key {
+            Some(key) => encrypt(&new_content, &key),
+            None => new_content,
+        };
+        pastas[i].set_content(content, &editor);
 
-    for (i, pasta) in pastas.iter().enumerate() {
-        if pasta.id == id {
-            if pasta.editable && !pasta.encrypt_client {
-                if pastas[i].readonly || pastas[i].encrypt_server {
-                    if password != *"" {
-                        let res = decrypt(pastas[i].encrypted_key.as_ref().unwrap(), &password);
-                        if res.is_ok() {
-                            pastas[i].content.replace_range(.., &new_content);
-                            // save pasta in database
-                            update(Some(&pastas), Some(&pastas[i]));
-                        } else {
-                            return Ok(HttpResponse::Found()
-                                .append_header((
-                                    "Location",
-                                    format!("{}/edit/{}/incorrect", ARGS.public_path_as_str(), pasta.id_as_animals()),
-                                ))
-                                .finish());
-                        }
-                    } else {
-                        return Ok(HttpResponse::Found()
-                            .append_header((
-                                "Location",
-                                    format!("{}/edit/{}/incorrect", ARGS.public_path_as_str(), pasta.id_as_animals()),
-                            ))
-                            .finish());
-                    }
-                } else {
-                    pastas[i].content.replace_range(.., &new_content);
-                    // save pasta in database
-                    update(Some(&pastas), Some(&pastas[i]));
-                }
+        // Update title if provided
+        if !new_title.is_empty() {
+            pastas[i].title = new_title;
+        }
+        if let Some(tags) = tags {
+            pastas[i].tags = tags;
+        }
 
-                // Update title if provided
-                if !new_title.is_empty() {
-                    pastas[i].title = new_title;
-                }
+        // save pasta in database
+        update(Some(&pastas), Some(&pastas[i]));
+        notify(Event::Edited, &pastas[i]);
 
-                return Ok(HttpResponse::Found()
-                    .append_header((
-                        "Location",
-                        format!(
-                            "{}/upload/{}",
-                            ARGS.public_path_as_str(),
-                            pastas[i].id_as_animals()
-                        ),
-                    ))
-                    .finish());
-            } else {
-                break;
-            }
-        }
+        let location = if pastas[i].encrypt_server {
+            format!("{}/auth/{}/success", ARGS.public_path_as_str(), pastas[i].id_as_animals())
+        } else {
+            format!("{}/upload/{}", ARGS.public_path_as_str(), pastas[i].id_as_animals())
+        };
+
+        return Ok(HttpResponse::Found()
+            .append_header(("Location", location))
+            .finish());
     }
 
-    let lang = req.cookie("lang").map(|c| c.value().to_string()).unwrap_or_else(|| "zh".to_string());
-    let text = get_translation(&lang);
+    let text = request_translation(&req);
 
     Ok(HttpResponse::Ok()
         .content_type("text/html; charset=utf-8")
         .body(ErrorTemplate { args: &ARGS, text }.render().unwrap()))
 }
+
+/// Programmatic update of a pasta's content. The request body replaces the
+/// content. If `If-Match` is given it has to match the current ETag, so a
+/// client can't overwrite changes it hasn't seen. Protected pastas take their
+/// password in the `X-MicroBin-Password` header.
+#[put("/raw/{id}")]
+pub async fn put_raw(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    id: web::Path<String>,
+    body: String,
+) -> HttpResponse {
+    let id = if ARGS.hash_ids {
+        hashid_to_u64(&id).unwrap_or(0)
+    } else {
+        to_u64(&id.into_inner()).unwrap_or(0)
+    };
+
+    let header_value = |name: &str| {
+        req.headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("")
+            .to_string()
+    };
+    let password = header_value("X-MicroBin-Password");
+    let editor = sanitize_editor(&header_value("X-MicroBin-Editor"));
+    let if_match = req.headers().get(header::IF_MATCH).map(|v| v.to_str().unwrap_or(""));
+
+    let mut pastas = data.pastas.lock().unwrap();
+
+    remove_expired(&mut pastas);
+
+    let index = match pastas.iter().position(|p| p.id == id) {
+        Some(index) => index,
+        None => {
+            return HttpResponse::NotFound()
+                .content_type("text/plain; charset=utf-8")
+                .body("Upload not found! :-(")
+        }
+    };
+
+    let pasta = &pastas[index];
+
+    if !pasta.editable || pasta.encrypt_client {
+        return HttpResponse::Forbidden()
+            .content_type("text/plain; charset=utf-8")
+            .body("This upload can not be edited.");
+    }
+
+    if (pasta.readonly || pasta.encrypt_server) && !pasta.edit_password_matches(&password) {
+        return HttpResponse::Forbidden()
+            .content_type("text/plain; charset=utf-8")
+            .body("Incorrect password.");
+    }
+
+    if let Some(if_match) = if_match {
+        if !pasta.etag_matches(if_match) {
+            return HttpResponse::PreconditionFailed()
+                .insert_header((header::ETAG, pasta.etag()))
+                .content_type("text/plain; charset=utf-8")
+                .body("The upload was changed since you last fetched it.");
+        }
+    }
+
+    let encrypted = pasta.encrypt_server && !pasta.readonly;
+    pastas[index].detect_extension(&body);
+    let content = if encrypted {
+        encrypt(&body, &password)
+    } else {
+        body
+    };
+
+    pastas[index].set_content(content, &editor);
+    update(Some(&pastas), Some(&pastas[index]));
+    notify(Event::Edited, &pastas[index]);
+
+    HttpResponse::NoContent()
+        .insert_header((header::ETAG, pastas[index].etag()))
+        .finish()
+}
+
+/// A multipart body with the given text fields, and its content type.
+#[cfg(test)]
+fn test_form(fields: &[(&str, &str)]) -> (String, String) {
+    let mut body = String::new();
+    for (name, value) in fields {
+        body.push_str(&format!(
+            "--boundary\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+            name, value
+        ));
+    }
+    body.push_str("--boundary--\r\n");
+    (String::from("multipart/form-data; boundary=boundary"), body)
+}
+
+#[test]
+fn test_settings_changes_and_authorization() {
+    let mut pasta = crate::pasta::test_pasta(7);
+    pasta.extension = String::from("rs");
+
+    let mut settings = EditSettings::default();
+    assert!(!settings.changes(&pasta));
+    settings.extension = Some(String::from("rs"));
+    settings.privacy = Some(String::from("public"));
+    assert!(!settings.changes(&pasta));
+    settings.privacy = Some(String::from("readonly"));
+    assert!(settings.changes(&pasta));
+
+    assert!(!settings.authorized(&pasta));
+    pasta.private = true;
+    pasta.encrypt_server = true;
+    assert!(!settings.authorized(&pasta));
+    pasta.encrypt_server = false;
+    pasta.readonly = true;
+    assert!(settings.authorized(&pasta));
+}
+
+#[actix_web::test]
+async fn test_edit_rejects_wrong_password() {
+    use actix_web::{test, App};
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Mutex;
+
+    let mut readonly = crate::pasta::test_pasta(1);
+    readonly.private = true;
+    readonly.readonly = true;
+    readonly.encrypted_key = Some(encrypt("1", "secret"));
+    readonly.content = String::from("original");
+
+    // a private pasta that only holds an attachment has no content to
+    // check the password against
+    let mut private = crate::pasta::test_pasta(2);
+    private.private = true;
+    private.encrypt_server = true;
+    private.encrypted_key = Some(encrypt("2", "secret"));
+
+    let data = web::Data::new(AppState {
+        pastas: Mutex::new(vec![readonly, private]),
+        collections: Mutex::new(Vec::new()),
+        shutting_down: AtomicBool::new(false),
+    });
+    let app = test::init_service(
+        App::new()
+            .app_data(data.clone())
+            .service(post_edit)
+            .service(post_submit_edit_private),
+    )
+    .await;
+
+    for (id, path) in [(1, "edit"), (2, "edit"), (2, "submit_edit_private")] {
+        let (content_type, body) = test_form(&[
+            ("content", "changed"),
+            ("password", "wrong"),
+            ("privacy", "public"),
+        ]);
+        let req = test::TestRequest::post()
+            .uri(&format!("/{}/{}", path, crate::util::animalnumbers::to_animal_names(id)))
+            .insert_header((header::CONTENT_TYPE, content_type))
+            .set_payload(body)
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::FOUND);
+        let location = res.headers().get(header::LOCATION).unwrap().to_str().unwrap();
+        assert!(location.ends_with("/incorrect"), "{} {}", path, location);
+    }
+
+    let pastas = data.pastas.lock().unwrap();
+    assert_eq!(pastas[0].content, "original");
+    assert!(pastas[0].readonly);
+    assert!(pastas[1].content.is_empty());
+    assert!(pastas[1].encrypt_server);
+}
+
+#[test]
+fn test_failed_rekey_leaves_pasta_untouched() {
+    // the attachment is missing from disk, so decrypting it fails
+    let mut pasta = crate::pasta::test_pasta(48_151_623);
+    pasta.private = true;
+    pasta.encrypt_server = true;
+    pasta.encrypted_key = Some(encrypt("48151623", "old"));
+    pasta.content = encrypt("hello", "old");
+    pasta.file = Some(PastaFile::from_unsanitized("notes.txt").unwrap());
+    let before = pasta.content.clone();
+
+    let settings = EditSettings {
+        privacy: Some(String::from("public")),
+        ..EditSettings::default()
+    };
+    let err = apply_settings(&mut pasta, settings, "old").unwrap_err();
+    assert_eq!(err.to_string(), "Failed to decrypt the attachment.");
+
+    assert_eq!(pasta.content, before);
+    assert_eq!(decrypt(&pasta.content, "old").unwrap(), "hello");
+    assert!(pasta.encrypt_server);
+    assert_eq!(pasta.encrypted_key, Some(encrypt("48151623", "old")));
+    assert!(pasta.file.is_some());
+}
+
+#[actix_web::test]
+async fn test_conflict_page_shows_decrypted_content() {
+    use actix_web::{test, App};
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Mutex;
+
+    let mut pasta = crate::pasta::test_pasta(1);
+    pasta.private = true;
+    pasta.encrypt_server = true;
+    pasta.encrypted_key = Some(encrypt("1", "secret"));
+    pasta.content = encrypt("their change", "secret");
+    pasta.version = 2;
+    let ciphertext = pasta.content.clone();
+
+    let mut readonly = crate::pasta::test_pasta(2);
+    readonly.private = true;
+    readonly.readonly = true;
+    readonly.encrypted_key = Some(encrypt("2", "secret"));
+    readonly.content = String::from("their readonly change");
+    readonly.version = 2;
+
+    let data = web::Data::new(AppState {
+        pastas: Mutex::new(vec![pasta, readonly]),
+        collections: Mutex::new(Vec::new()),
+        shutting_down: AtomicBool::new(false),
+    });
+    let app = test::init_service(
+        App::new()
+            .app_data(data)
+            .service(post_edit)
+            .service(post_submit_edit_private),
+    )
+    .await;
+
+    let (content_type, body) = test_form(&[
+        ("content", "my change"),
+        ("password", "secret"),
+        ("version", "1"),
+    ]);
+    let req = test::TestRequest::post()
+        .uri(&format!("/edit/{}", crate::util::animalnumbers::to_animal_names(1)))
+        .insert_header((header::CONTENT_TYPE, content_type))
+        .set_payload(body)
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), actix_web::http::StatusCode::CONFLICT);
+    let body = String::from_utf8(test::read_body(res).await.to_vec()).unwrap();
+    assert!(body.contains("their change"));
+    assert!(!body.contains(&ciphertext));
+
+    // read-only pastas edited through the private form are checked too
+    let (content_type, body) = test_form(&[
+        ("content", "my change"),
+        ("password", "secret"),
+        ("version", "1"),
+    ]);
+    let req = test::TestRequest::post()
+        .uri(&format!(
+            "/submit_edit_private/{}",
+            crate::util::animalnumbers::to_animal_names(2)
+        ))
+        .insert_header((header::CONTENT_TYPE, content_type))
+        .set_payload(body)
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), actix_web::http::StatusCode::CONFLICT);
+    let body = String::from_utf8(test::read_body(res).await.to_vec()).unwrap();
+    assert!(body.contains("their readonly change"));
+}
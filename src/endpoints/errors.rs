@@ -2,7 +2,7 @@ use actix_web::{Error, HttpResponse, HttpRequest};
 use askama::Template;
 
 use crate::args::{Args, ARGS};
-use crate::translation::{get_translation, Translation};
+use crate::translation::{request_translation, Translation};
 
 #[derive(Template)]
 #[template(path = "error.html")]
@@ -12,8 +12,7 @@ pub struct ErrorTemplate<'a> {
 }
 
 pub async fn not_found(req: HttpRequest) -> Result<HttpResponse, Error> {
-    let lang = req.cookie("lang").map(|c| c.value().to_string()).unwrap_or_else(|| "zh".to_string());
-    let text = get_translation(&lang);
+    let text = request_translation(&req);
 
     Ok(HttpResponse::Ok()
         .content_type("text/html; charset=utf-8")
@@ -0,0 +1,196 @@
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use askama::Template;
+use chrono::{TimeZone, Utc};
+
+use crate::args::{Args, ARGS};
+use crate::pasta::Pasta;
+use crate::translation::{request_translation, Translation};
+use crate::util::listing::ListQuery;
+use crate::util::misc::{excerpt, remove_expired};
+use crate::AppState;
+
+/// Longest content excerpt in a feed entry, in characters.
+const EXCERPT_LENGTH: usize = 500;
+
+struct FeedEntry {
+    id: String,
+    url: String,
+    title: String,
+    extension: String,
+    tags: Vec<String>,
+    size: String,
+    excerpt: String,
+    published: i64,
+    updated: i64,
+}
+
+impl FeedEntry {
+    fn published_rfc2822(&self) -> String {
+        timestamp(self.published).to_rfc2822()
+    }
+
+    fn updated_rfc3339(&self) -> String {
+        timestamp(self.updated).to_rfc3339()
+    }
+
+    fn published_rfc3339(&self) -> String {
+        timestamp(self.published).to_rfc3339()
+    }
+}
+
+#[derive(Template)]
+#[template(path = "feed.atom.xml")]
+struct AtomTemplate<'a> {
+    base_url: String,
+    self_url: String,
+    updated: String,
+    entries: Vec<FeedEntry>,
+    args: &'a Args,
+}
+
+#[derive(Template)]
+#[template(path = "feed.rss.xml")]
+struct RssTemplate<'a> {
+    base_url: String,
+    self_url: String,
+    entries: Vec<FeedEntry>,
+    args: &'a Args,
+}
+
+fn timestamp(seconds: i64) -> chrono::DateTime<Utc> {
+    Utc.timestamp_opt(seconds, 0).single().unwrap_or_default()
+}
+
+/// Absolute URL of the instance, feeds can't use relative links. Taken from
+/// the public path if one is set, otherwise from the request.
+fn base_url(req: &HttpRequest) -> String {
+    match &ARGS.public_path {
+        Some(path) => path.to_string().trim_end_matches('/').to_string(),
+        None => {
+            let info = req.connection_info();
+            format!("{}://{}", info.scheme(), info.host())
+        }
+    }
+}
+
+/// Whether a pasta may show up in the feeds: only what `/list` shows, minus
+/// anything encrypted.
+fn in_feed(pasta: &Pasta) -> bool {
+    !pasta.private && !pasta.encrypt_server && !pasta.encrypt_client
+}
+
+/// The newest public pastas matching the query's filters, as feed entries.
+fn feed_entries(
+    data: &web::Data<AppState>,
+    query: &ListQuery,
+    base_url: &str,
+    text: &Translation,
+) -> Vec<FeedEntry> {
+    let mut pastas = data.pastas.lock().unwrap();
+
+    remove_expired(&mut pastas);
+
+    // feeds are always newest first, only the filters are taken from the query
+    let query = ListQuery {
+        sort: String::from("created"),
+        order: String::from("desc"),
+        ..query.clone()
+    };
+
+    query
+        .apply(pastas.iter().filter(|p| in_feed(p)))
+        .into_iter()
+        .take(query.per_page())
+        .map(|pasta| FeedEntry {
+            id: pasta.id_as_animals(),
+            url: format!("{}/upload/{}", base_url, pasta.id_as_animals()),
+            title: pasta.title.clone(),
+            extension: pasta.extension.clone(),
+            tags: pasta.tags.clone(),
+            size: text.size(&pasta.total_size()),
+            // reading a burn after read pasta through the feed would not
+            // count, nor show up in the access log of a logged one
+            excerpt: if pasta.burn_after_reads > 0 || pasta.access_log.is_some() {
+                String::new()
+            } else {
+                excerpt(&pasta.content, EXCERPT_LENGTH)
+            },
+            published: pasta.created,
+            updated: pasta.revisions.last().map_or(pasta.created, |r| r.created),
+        })
+        .collect()
+}
+
+fn self_url(req: &HttpRequest, base_url: &str) -> String {
+    let query = req.query_string();
+    if query.is_empty() {
+        format!("{}{}", base_url, req.path())
+    } else {
+        format!("{}{}?{}", base_url, req.path(), query)
+    }
+}
+
+fn feed_disabled() -> HttpResponse {
+    HttpResponse::NotFound()
+        .content_type("text/plain; charset=utf-8")
+        .body("Listing is disabled.")
+}
+
+/// Atom feed of the public pastas. Takes the filters of `/list`, such as
+/// `tag` and `type`, and `limit` for the number of entries.
+#[get("/feed.atom")]
+pub async fn atom(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    query: web::Query<ListQuery>,
+) -> HttpResponse {
+    if ARGS.no_listing {
+        return feed_disabled();
+    }
+
+    let base_url = base_url(&req);
+    let entries = feed_entries(&data, &query, &base_url, &request_translation(&req));
+    let updated = timestamp(entries.iter().map(|e| e.updated).max().unwrap_or(0)).to_rfc3339();
+
+    HttpResponse::Ok()
+        .content_type("application/atom+xml; charset=utf-8")
+        .body(
+            AtomTemplate {
+                self_url: self_url(&req, &base_url),
+                base_url,
+                updated,
+                entries,
+                args: &ARGS,
+            }
+            .render()
+            .unwrap(),
+        )
+}
+
+/// The same feed as RSS 2.0, for readers without Atom support.
+#[get("/feed.rss")]
+pub async fn rss(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    query: web::Query<ListQuery>,
+) -> HttpResponse {
+    if ARGS.no_listing {
+        return feed_disabled();
+    }
+
+    let base_url = base_url(&req);
+    let entries = feed_entries(&data, &query, &base_url, &request_translation(&req));
+
+    HttpResponse::Ok()
+        .content_type("application/rss+xml; charset=utf-8")
+        .body(
+            RssTemplate {
+                self_url: self_url(&req, &base_url),
+                base_url,
+                entries,
+                args: &ARGS,
+            }
+            .render()
+            .unwrap(),
+        )
+}
@@ -2,9 +2,11 @@ use std::fs::File;
 use std::path::PathBuf;
 
 use crate::args::ARGS;
+use crate::util::access_log;
 use crate::util::auth;
+use crate::util::db::update;
 use crate::util::hashids::to_u64 as hashid_to_u64;
-use crate::util::misc::remove_expired;
+use crate::util::misc::{harden_user_content, remove_expired};
 use crate::util::{animalnumbers::to_u64, misc::decrypt_file};
 use crate::AppState;
 use actix_multipart::Multipart;
@@ -13,6 +15,7 @@ use actix_web::{get, post, web, Error, HttpResponse};
 
 #[post("/secure_file/{id}")]
 pub async fn post_secure_file(
+    req: actix_web::HttpRequest,
     data: web::Data<AppState>,
     id: web::Path<String>,
     payload: Multipart,
@@ -43,7 +46,7 @@ pub async fn post_secure_file(
     let password = auth::password_from_multipart(payload).await?;
 
     if found {
-        if let Some(ref pasta_file) = pastas[index].file {
+        if let Some(pasta_file) = pastas[index].file.clone() {
             let file = File::open(format!(
                 "{}/attachments/{}/data.enc",
                 ARGS.data_dir,
@@ -54,20 +57,21 @@ pub async fn post_secure_file(
             // to work therefore secure files do not support streaming
             let decrypted_data: Vec<u8> = decrypt_file(&password, &file)?;
 
-            // Set the content type based on the file extension
-            let content_type = mime_guess::from_path(&pasta_file.name)
-                .first_or_octet_stream()
-                .to_string();
+            if pastas[index].access_log.is_some() {
+                access_log::record(&mut pastas[index], &req, "file");
+                update(Some(&pastas), Some(&pastas[index]));
+            }
 
             // Create a response with the decrypted data
-            let response = HttpResponse::Ok()
-                .content_type(content_type)
+            let mut response = HttpResponse::Ok()
+                .content_type(pasta_file.content_type())
                 .append_header((
                     "Content-Disposition",
                     format!("attachment; filename=\"{}\"", pasta_file.name()),
                 ))
                 // TODO: make streaming <21-10-24, dvdsk>
                 .body(decrypted_data);
+            harden_user_content(&mut response);
             return Ok(response);
         }
     }
@@ -104,7 +108,7 @@ pub async fn get_file(
     }
 
     if found {
-        if let Some(ref pasta_file) = pastas[index].file {
+        if let Some(pasta_file) = pastas[index].file.clone() {
             if pastas[index].encrypt_server {
                 return Ok(HttpResponse::Found()
                     .append_header((
@@ -123,9 +127,15 @@ pub async fn get_file(
             );
             let file_path = PathBuf::from(file_path);
 
-            // This will stream the file and set the content type based on the
-            // file path
-            let file_reponse = actix_files::NamedFile::open(file_path)?;
+            // This will stream the file. The content type comes from the
+            // detected type rather than the user supplied file name.
+            let file_reponse = actix_files::NamedFile::open(file_path)?
+                .set_content_type(
+                    pasta_file
+                        .content_type()
+                        .parse()
+                        .unwrap_or(mime_guess::mime::APPLICATION_OCTET_STREAM),
+                );
             let file_reponse = file_reponse.set_content_disposition(header::ContentDisposition {
                 disposition: header::DispositionType::Attachment,
                 parameters: vec![header::DispositionParam::Filename(
@@ -134,7 +144,14 @@ pub async fn get_file(
             });
             // This takes care of streaming/seeking using the Range
             // header in the request.
-            return Ok(file_reponse.into_response(&request));
+            let mut response = file_reponse.into_response(&request);
+
+            if pastas[index].access_log.is_some() {
+                access_log::record(&mut pastas[index], &request, "file");
+                update(Some(&pastas), Some(&pastas[index]));
+            }
+            harden_user_content(&mut response);
+            return Ok(response);
         }
     }
 
@@ -0,0 +1,264 @@
+use crate::args::ARGS;
+use crate::endpoints::create::{ForkSource, IndexTemplate};
+use crate::endpoints::errors::ErrorTemplate;
+use crate::pasta::Pasta;
+use crate::translation::request_translation;
+use crate::util::access_log;
+use crate::util::animalnumbers::to_u64;
+use crate::util::auth;
+use crate::util::db::update;
+use crate::util::hashids::to_u64 as hashid_to_u64;
+use crate::util::misc::{decrypt, remove_expired};
+use crate::util::security_headers::csp_nonce;
+use crate::AppState;
+use actix_multipart::Multipart;
+use actix_web::{get, post, web, Error, HttpRequest, HttpResponse};
+use askama::Template;
+
+/// Renders the upload form pre-filled with `content`, which is the source
+/// pasta's content already decrypted if needed.
+fn fork_form(req: &HttpRequest, pasta: &Pasta, content: String) -> HttpResponse {
+    let text = request_translation(req);
+
+    // encrypted attachments can't be copied without the key
+    let file_name = match pasta.file.as_ref() {
+        Some(file) if !pasta.encrypt_server => file.name().to_string(),
+        _ => String::from(""),
+    };
+
+    HttpResponse::Ok().content_type("text/html; charset=utf-8").body(
+        IndexTemplate {
+            args: &ARGS,
+            status: String::from(""),
+            text,
+            nonce: csp_nonce(req),
+            fork: ForkSource {
+                id: pasta.id_as_animals(),
+                title: pasta.title.to_owned(),
+                content,
+                extension: pasta.extension.to_owned(),
+                tags: pasta.tags_as_string(),
+                file_name,
+            },
+        }
+        .render()
+        .unwrap(),
+    )
+}
+
+/// Pre-filling the form shows the content, so it counts as an access.
+fn log_access(pastas: &mut Vec<Pasta>, index: usize, req: &HttpRequest) {
+    if pastas[index].access_log.is_some() {
+        access_log::record(&mut pastas[index], req, "fork");
+        update(Some(pastas), Some(&pastas[index]));
+    }
+}
+
+/// Burn after reading pastas can't be forked, the form would show their
+/// content without counting a read. Sends the visitor to the pasta instead.
+fn to_pasta(pasta: &Pasta) -> HttpResponse {
+    HttpResponse::Found()
+        .append_header((
+            "Location",
+            format!("{}/upload/{}", ARGS.public_path_as_str(), pasta.id_as_animals()),
+        ))
+        .finish()
+}
+
+fn not_found(req: &HttpRequest) -> HttpResponse {
+    let text = request_translation(req);
+
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(ErrorTemplate { args: &ARGS, text }.render().unwrap())
+}
+
+#[get("/fork/{id}")]
+pub async fn get_fork(req: HttpRequest, data: web::Data<AppState>, id: web::Path<String>) -> HttpResponse {
+    let mut pastas = data.pastas.lock().unwrap();
+
+    let id = if ARGS.hash_ids {
+        hashid_to_u64(&id).unwrap_or(0)
+    } else {
+        to_u64(&id.into_inner()).unwrap_or(0)
+    };
+
+    remove_expired(&mut pastas);
+
+    let Some(index) = pastas.iter().position(|p| p.id == id) else {
+        return not_found(&req);
+    };
+
+    // the server can't read client side encrypted pastas
+    if pastas[index].encrypt_client || pastas[index].burns_after_reading() {
+        return to_pasta(&pastas[index]);
+    }
+
+    if pastas[index].encrypt_server && !pastas[index].readonly {
+        return HttpResponse::Found()
+            .append_header((
+                "Location",
+                format!("{}/auth_fork/{}", ARGS.public_path_as_str(), pastas[index].id_as_animals()),
+            ))
+            .finish();
+    }
+
+    log_access(&mut pastas, index, &req);
+    fork_form(&req, &pastas[index], pastas[index].content.to_owned())
+}
+
+#[post("/fork/{id}")]
+pub async fn post_fork(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    id: web::Path<String>,
+    payload: Multipart,
+) -> Result<HttpResponse, Error> {
+    let password = auth::password_from_multipart(payload).await?;
+
+    let mut pastas = data.pastas.lock().unwrap();
+
+    let id = if ARGS.hash_ids {
+        hashid_to_u64(&id).unwrap_or(0)
+    } else {
+        to_u64(&id.into_inner()).unwrap_or(0)
+    };
+
+    remove_expired(&mut pastas);
+
+    let Some(index) = pastas.iter().position(|p| p.id == id && !p.encrypt_client) else {
+        return Ok(not_found(&req));
+    };
+
+    let pasta = &pastas[index];
+    if pasta.burns_after_reading() {
+        return Ok(to_pasta(pasta));
+    }
+
+    // the content alone can't tell a wrong password apart, it may be empty
+    let content = if pasta.encrypt_server && !pasta.readonly {
+        match Some(&password)
+            .filter(|password| pasta.edit_password_matches(password))
+            .and_then(|password| decrypt(&pasta.content, password).ok())
+        {
+            Some(content) => content,
+            None => {
+                return Ok(HttpResponse::Found()
+                    .append_header((
+                        "Location",
+                        format!(
+                            "{}/auth_fork/{}/incorrect",
+                            ARGS.public_path_as_str(),
+                            pasta.id_as_animals()
+                        ),
+                    ))
+                    .finish())
+            }
+        }
+    } else {
+        pasta.content.to_owned()
+    };
+
+    log_access(&mut pastas, index, &req);
+    Ok(fork_form(&req, &pastas[index], content))
+}
+
+#[actix_web::test]
+async fn test_fork_prefills_the_upload_form() {
+    use crate::util::animalnumbers::to_animal_names;
+    use crate::util::misc::encrypt;
+    use actix_web::http::{header, StatusCode};
+    use actix_web::{test, App};
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Mutex;
+
+    let mut public = crate::pasta::test_pasta(1);
+    public.title = String::from("Notes");
+    public.content = String::from("fn main() {} <b>");
+    public.extension = String::from("rs");
+    public.tags = vec![String::from("rust")];
+
+    let mut private = crate::pasta::test_pasta(2);
+    private.private = true;
+    private.encrypt_server = true;
+    private.content = encrypt("secret content", "pw");
+    private.encrypted_key = Some(encrypt("2", "pw"));
+
+    let mut burning = crate::pasta::test_pasta(3);
+    burning.content = String::from("read me once");
+    burning.burn_after_reads = 1;
+
+    // only an attachment, so there is no content to check the password with
+    let mut attachment_only = crate::pasta::test_pasta(4);
+    attachment_only.private = true;
+    attachment_only.encrypt_server = true;
+    attachment_only.title = String::from("Secret title");
+    attachment_only.encrypted_key = Some(encrypt("4", "pw"));
+
+    let data = web::Data::new(AppState {
+        pastas: Mutex::new(vec![public, private, burning, attachment_only]),
+        collections: Mutex::new(Vec::new()),
+        shutting_down: AtomicBool::new(false),
+    });
+    let app = test::init_service(
+        App::new()
+            .app_data(data.clone())
+            .service(get_fork)
+            .service(post_fork),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/fork/{}", to_animal_names(1)))
+        .to_request();
+    let body = String::from_utf8(test::call_and_read_body(&app, req).await.to_vec()).unwrap();
+    assert!(body.contains(&format!("name=\"fork_of\" value=\"{}\"", to_animal_names(1))));
+    assert!(body.contains("fn main() {} &lt;b&gt;</textarea>"));
+    assert!(body.contains("value=\"Notes\""));
+    assert!(body.contains("value=\"rust\""));
+
+    // private pastas ask for the password first
+    let req = test::TestRequest::get()
+        .uri(&format!("/fork/{}", to_animal_names(2)))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::FOUND);
+    assert!(res.headers().get(header::LOCATION).unwrap().to_str().unwrap().contains("/auth_fork/"));
+
+    for (id, password, expected) in [
+        (2, "wrong", None),
+        (2, "pw", Some("secret content</textarea>")),
+        (4, "wrong", None),
+        (4, "pw", Some("value=\"Secret title\"")),
+    ] {
+        let req = test::TestRequest::post()
+            .uri(&format!("/fork/{}", to_animal_names(id)))
+            .insert_header((header::CONTENT_TYPE, "multipart/form-data; boundary=boundary"))
+            .set_payload(format!(
+                "--boundary\r\nContent-Disposition: form-data; name=\"password\"\r\n\r\n{}\r\n--boundary--\r\n",
+                password
+            ))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        match expected {
+            Some(content) => {
+                let body = String::from_utf8(test::read_body(res).await.to_vec()).unwrap();
+                assert!(body.contains(content));
+            }
+            None => {
+                let location = res.headers().get(header::LOCATION).unwrap().to_str().unwrap();
+                assert!(location.ends_with("/incorrect"));
+            }
+        }
+    }
+
+    // burn after reading pastas only show their content on the pasta page
+    let req = test::TestRequest::get()
+        .uri(&format!("/fork/{}", to_animal_names(3)))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), StatusCode::FOUND);
+    let location = res.headers().get(header::LOCATION).unwrap().to_str().unwrap();
+    assert!(location.ends_with(&format!("/upload/{}", to_animal_names(3))));
+    assert_eq!(data.pastas.lock().unwrap()[2].read_count, 0);
+}
@@ -1,4 +1,4 @@
-use crate::translation::{get_translation, Translation};
+use crate::translation::{request_translation, Translation};
 use crate::args::{Args, ARGS};
 use actix_web::{get, HttpResponse, HttpRequest};
 use askama::Template;
@@ -13,8 +13,8 @@ struct Guide<'a> {
 
 #[get("/guide")]
 pub async fn guide(req: HttpRequest) -> HttpResponse {
-    let lang = req.cookie("lang").map(|c| c.value().to_string()).unwrap_or_else(|| "zh".to_string());
-    let text = get_translation(&lang);
+    let text = request_translation(&req);
+    let lang = text.lang.clone();
 
     HttpResponse::Ok()
         .content_type("text/html; charset=utf-8")
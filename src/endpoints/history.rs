@@ -0,0 +1,372 @@
+use crate::args::{Args, ARGS};
+use crate::endpoints::edit::sanitize_editor;
+use crate::endpoints::errors::ErrorTemplate;
+use crate::pasta::{Pasta, PastaRevision};
+use crate::translation::{request_translation, Translation};
+use crate::util::access_log;
+use crate::util::animalnumbers::to_u64;
+use crate::util::db::update;
+use crate::util::diff::{side_by_side_diff_html, unified_diff_html};
+use crate::util::hashids::to_u64 as hashid_to_u64;
+use crate::util::misc::{decrypt, remove_expired};
+use crate::util::webhooks::{notify, Event};
+use crate::AppState;
+use actix_multipart::Multipart;
+use actix_web::{get, post, web, Error, HttpRequest, HttpResponse};
+use askama::Template;
+use futures::TryStreamExt;
+use serde::Deserialize;
+
+#[derive(Template)]
+#[template(path = "history.html")]
+struct HistoryTemplate<'a> {
+    pasta: &'a Pasta,
+    /// Newest first, decrypted if the pasta is encrypted.
+    revisions: Vec<PastaRevision>,
+    from: u64,
+    to: u64,
+    view: String,
+    diff: String,
+    encrypted: bool,
+    password: String,
+    status: String,
+    args: &'a Args,
+    text: Translation,
+}
+
+#[derive(Deserialize, Default)]
+pub struct CompareQuery {
+    from: Option<u64>,
+    to: Option<u64>,
+    view: Option<String>,
+}
+
+fn intern_id(id: &str) -> u64 {
+    if ARGS.hash_ids {
+        hashid_to_u64(id).unwrap_or(0)
+    } else {
+        to_u64(id).unwrap_or(0)
+    }
+}
+
+fn redirect(location: String) -> HttpResponse {
+    HttpResponse::Found()
+        .append_header(("Location", location))
+        .finish()
+}
+
+fn error_page(req: &HttpRequest) -> HttpResponse {
+    let text = request_translation(req);
+
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(ErrorTemplate { args: &ARGS, text }.render().unwrap())
+}
+
+fn render_history(
+    req: &HttpRequest,
+    pasta: &Pasta,
+    password: &str,
+    query: CompareQuery,
+    status: String,
+) -> HttpResponse {
+    let encrypted = pasta.encrypt_server && !pasta.readonly;
+
+    let mut revisions = pasta.revision_list();
+    if encrypted {
+        for revision in revisions.iter_mut() {
+            match decrypt(&revision.content, password) {
+                Ok(content) => revision.content = content,
+                Err(_) => {
+                    return redirect(format!(
+                        "{}/auth_history/{}/incorrect",
+                        ARGS.public_path_as_str(),
+                        pasta.id_as_animals()
+                    ))
+                }
+            }
+        }
+    }
+    revisions.reverse();
+
+    // compare the current content with the revision before it by default
+    let to = query.to.unwrap_or(revisions[0].number);
+    let from = query
+        .from
+        .unwrap_or_else(|| revisions.get(1).map_or(to, |r| r.number));
+    let view = query.view.unwrap_or_else(|| String::from("unified"));
+
+    let content_of = |number: u64| {
+        revisions
+            .iter()
+            .find(|r| r.number == number)
+            .map(|r| r.content.as_str())
+    };
+    let diff = match (content_of(from), content_of(to)) {
+        (Some(old), Some(new)) if old != new => {
+            if view == "split" {
+                side_by_side_diff_html(old, new)
+            } else {
+                unified_diff_html(old, new)
+            }
+        }
+        _ => String::from(""),
+    };
+
+    let text = request_translation(req);
+
+    HttpResponse::Ok().content_type("text/html; charset=utf-8").body(
+        HistoryTemplate {
+            pasta,
+            revisions,
+            from,
+            to,
+            view,
+            diff,
+            encrypted,
+            password: password.to_string(),
+            status,
+            args: &ARGS,
+            text,
+        }
+        .render()
+        .unwrap(),
+    )
+}
+
+fn get_history_page(
+    req: &HttpRequest,
+    data: &web::Data<AppState>,
+    id: &str,
+    query: CompareQuery,
+    status: String,
+) -> HttpResponse {
+    let mut pastas = data.pastas.lock().unwrap();
+
+    remove_expired(&mut pastas);
+
+    let intern_id = intern_id(id);
+
+    let Some(index) = pastas.iter().position(|p| p.id == intern_id) else {
+        return error_page(req);
+    };
+    let pasta = &pastas[index];
+
+    // client side encrypted pastas can't be edited, so there is nothing to
+    // show. Burn after reading pastas are only shown where reads are counted.
+    if pasta.encrypt_client || pasta.burns_after_reading() {
+        return redirect(format!(
+            "{}/upload/{}",
+            ARGS.public_path_as_str(),
+            pasta.id_as_animals()
+        ));
+    }
+
+    if pasta.encrypt_server && !pasta.readonly {
+        return redirect(format!(
+            "{}/auth_history/{}",
+            ARGS.public_path_as_str(),
+            pasta.id_as_animals()
+        ));
+    }
+
+    let res = render_history(req, pasta, "", query, status);
+    log_access(&mut pastas, index, req, &res);
+    res
+}
+
+/// Every revision is shown, so an opened history counts as an access.
+/// Password prompts don't.
+fn log_access(pastas: &mut Vec<Pasta>, index: usize, req: &HttpRequest, res: &HttpResponse) {
+    if res.status().is_success() && pastas[index].access_log.is_some() {
+        access_log::record(&mut pastas[index], req, "history");
+        update(Some(pastas), Some(&pastas[index]));
+    }
+}
+
+#[get("/history/{id}")]
+pub async fn get_history(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    id: web::Path<String>,
+    query: web::Query<CompareQuery>,
+) -> HttpResponse {
+    get_history_page(&req, &data, &id, query.into_inner(), String::from(""))
+}
+
+#[get("/history/{id}/{status}")]
+pub async fn get_history_with_status(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    param: web::Path<(String, String)>,
+) -> HttpResponse {
+    let (id, status) = param.into_inner();
+    get_history_page(&req, &data, &id, CompareQuery::default(), status)
+}
+
+/// History of a private pasta, reached through the password prompt.
+#[post("/history/{id}")]
+pub async fn post_history(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    id: web::Path<String>,
+    mut payload: Multipart,
+) -> Result<HttpResponse, Error> {
+    let mut password = String::from("");
+    let mut query = CompareQuery::default();
+
+    while let Some(mut field) = payload.try_next().await? {
+        let mut value = String::from("");
+        while let Some(chunk) = field.try_next().await? {
+            value.push_str(std::str::from_utf8(&chunk).unwrap_or(""));
+        }
+        match field.name() {
+            Some("password") => password = value,
+            Some("from") => query.from = value.parse().ok(),
+            Some("to") => query.to = value.parse().ok(),
+            Some("view") => query.view = Some(value),
+            _ => {}
+        }
+    }
+
+    let mut pastas = data.pastas.lock().unwrap();
+
+    remove_expired(&mut pastas);
+
+    let intern_id = intern_id(&id);
+
+    let Some(index) = pastas.iter().position(|p| p.id == intern_id && !p.encrypt_client) else {
+        return Ok(error_page(&req));
+    };
+
+    if pastas[index].burns_after_reading() {
+        return Ok(redirect(format!(
+            "{}/upload/{}",
+            ARGS.public_path_as_str(),
+            pastas[index].id_as_animals()
+        )));
+    }
+
+    let res = render_history(&req, &pastas[index], &password, query, String::from(""));
+    log_access(&mut pastas, index, &req, &res);
+    Ok(res)
+}
+
+#[post("/history/{id}/restore")]
+pub async fn post_restore(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    id: web::Path<String>,
+    mut payload: Multipart,
+) -> Result<HttpResponse, Error> {
+    let mut password = String::from("");
+    let mut editor = String::from("");
+    let mut number: Option<u64> = None;
+
+    while let Some(mut field) = payload.try_next().await? {
+        let mut value = String::from("");
+        while let Some(chunk) = field.try_next().await? {
+            value.push_str(std::str::from_utf8(&chunk).unwrap_or(""));
+        }
+        match field.name() {
+            Some("password") => password = value,
+            Some("editor") => editor = sanitize_editor(&value),
+            Some("revision") => number = value.parse().ok(),
+            _ => {}
+        }
+    }
+
+    let mut pastas = data.pastas.lock().unwrap();
+
+    remove_expired(&mut pastas);
+
+    let intern_id = intern_id(&id);
+
+    let index = match pastas.iter().position(|p| p.id == intern_id) {
+        Some(index) => index,
+        None => return Ok(error_page(&req)),
+    };
+
+    let pasta = &pastas[index];
+    if !pasta.editable || pasta.encrypt_client {
+        return Ok(error_page(&req));
+    }
+
+    let encrypted = pasta.encrypt_server && !pasta.readonly;
+    let auth_path = if encrypted { "auth_history" } else { "history" };
+
+    if (pasta.readonly || pasta.encrypt_server) && !pasta.edit_password_matches(&password) {
+        return Ok(redirect(format!(
+            "{}/{}/{}/incorrect",
+            ARGS.public_path_as_str(),
+            auth_path,
+            pasta.id_as_animals()
+        )));
+    }
+
+    let content = match pasta
+        .revision_list()
+        .into_iter()
+        .find(|r| Some(r.number) == number)
+    {
+        Some(revision) => revision.content,
+        None => return Ok(error_page(&req)),
+    };
+
+    // revisions of private pastas are stored encrypted with the same
+    // password, so the content can be copied over as is
+    pastas[index].set_content(content, &editor);
+    update(Some(&pastas), Some(&pastas[index]));
+    notify(Event::Edited, &pastas[index]);
+
+    let status = if encrypted { "success" } else { "restored" };
+
+    Ok(redirect(format!(
+        "{}/{}/{}/{}",
+        ARGS.public_path_as_str(),
+        auth_path,
+        pastas[index].id_as_animals(),
+        status
+    )))
+}
+
+#[actix_web::test]
+async fn test_history_of_burn_after_reading_pasta_is_not_shown() {
+    use crate::util::animalnumbers::to_animal_names;
+    use actix_web::http::{header, StatusCode};
+    use actix_web::{test, App};
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Mutex;
+
+    let mut pasta = crate::pasta::test_pasta(1);
+    pasta.content = String::from("read me once");
+    pasta.burn_after_reads = 1;
+
+    let data = web::Data::new(AppState {
+        pastas: Mutex::new(vec![pasta]),
+        collections: Mutex::new(Vec::new()),
+        shutting_down: AtomicBool::new(false),
+    });
+    let app = test::init_service(
+        App::new()
+            .app_data(data.clone())
+            .service(get_history)
+            .service(post_history),
+    )
+    .await;
+
+    let get = test::TestRequest::get().uri(&format!("/history/{}", to_animal_names(1)));
+    let post = test::TestRequest::post()
+        .uri(&format!("/history/{}", to_animal_names(1)))
+        .insert_header((header::CONTENT_TYPE, "multipart/form-data; boundary=boundary"))
+        .set_payload(
+            "--boundary\r\nContent-Disposition: form-data; name=\"password\"\r\n\r\n\r\n--boundary--\r\n",
+        );
+    for req in [get, post] {
+        let res = test::call_service(&app, req.to_request()).await;
+        assert_eq!(res.status(), StatusCode::FOUND);
+        let location = res.headers().get(header::LOCATION).unwrap().to_str().unwrap();
+        assert!(location.ends_with(&format!("/upload/{}", to_animal_names(1))));
+    }
+    assert_eq!(data.pastas.lock().unwrap()[0].read_count, 0);
+}
@@ -1,22 +1,34 @@
 use actix_web::{get, web, HttpResponse, HttpRequest};
 use askama::Template;
+use serde::Serialize;
 
 use crate::args::{Args, ARGS};
 use crate::pasta::Pasta;
-use crate::translation::{get_translation, Translation};
+use crate::translation::{request_translation, Translation};
+use crate::util::listing::ListQuery;
 use crate::util::misc::remove_expired;
+use crate::util::security_headers::csp_nonce;
 use crate::AppState;
 
 #[derive(Template)]
 #[template(path = "list.html")]
 struct ListTemplate<'a> {
-    pastas: &'a Vec<Pasta>,
+    pastas: Vec<&'a Pasta>,
+    query: ListQuery,
+    page: usize,
+    pages: usize,
+    total: usize,
     args: &'a Args,
     text: Translation,
+    nonce: String,
 }
 
 #[get("/list")]
-pub async fn list(req: HttpRequest, data: web::Data<AppState>) -> HttpResponse {
+pub async fn list(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    query: web::Query<ListQuery>,
+) -> HttpResponse {
     if ARGS.no_listing {
         return HttpResponse::Found()
             .append_header(("Location", format!("{}/", ARGS.public_path_as_str())))
@@ -27,19 +39,94 @@ pub async fn list(req: HttpRequest, data: web::Data<AppState>) -> HttpResponse {
 
     remove_expired(&mut pastas);
 
-    // sort pastas in reverse-chronological order of creation time
-    pastas.sort_by(|a, b| b.created.cmp(&a.created));
+    let query = query.into_inner();
+    let page = query.page(query.apply(pastas.iter().filter(|p| !p.private)));
 
-    let lang = req.cookie("lang").map(|c| c.value().to_string()).unwrap_or_else(|| "zh".to_string());
-    let text = get_translation(&lang);
+    let text = request_translation(&req);
 
     HttpResponse::Ok().content_type("text/html; charset=utf-8").body(
         ListTemplate {
-            pastas: &pastas,
+            pastas: page.items,
+            page: page.page,
+            pages: page.pages,
+            total: page.total,
+            query,
             args: &ARGS,
             text,
+            nonce: csp_nonce(&req),
         }
         .render()
         .unwrap(),
     )
 }
+
+#[derive(Serialize)]
+struct ListItem<'a> {
+    id: String,
+    url: String,
+    title: &'a str,
+    pasta_type: &'a str,
+    extension: &'a str,
+    tags: &'a [String],
+    file: Option<&'a str>,
+    size: u64,
+    read_count: u64,
+    created: i64,
+    expiration: i64,
+}
+
+#[derive(Serialize)]
+struct ListResponse<'a> {
+    items: Vec<ListItem<'a>>,
+    /// Pass as `cursor` to get the next page, `null` on the last page.
+    next_cursor: Option<String>,
+}
+
+/// The public listing as JSON. Takes the same sorting and filtering
+/// parameters as `/list`, plus `limit` and `cursor` for paging through it.
+#[get("/api/list")]
+pub async fn list_json(data: web::Data<AppState>, query: web::Query<ListQuery>) -> HttpResponse {
+    if ARGS.no_listing {
+        return HttpResponse::NotFound()
+            .content_type("text/plain; charset=utf-8")
+            .body("Listing is disabled.");
+    }
+
+    let mut pastas = data.pastas.lock().unwrap();
+
+    remove_expired(&mut pastas);
+
+    let items = query.apply(pastas.iter().filter(|p| !p.private));
+    let (items, next_cursor) = match query.cursor_page(items) {
+        Ok(page) => page,
+        Err(message) => {
+            return HttpResponse::BadRequest()
+                .content_type("text/plain; charset=utf-8")
+                .body(message)
+        }
+    };
+
+    let response = ListResponse {
+        items: items
+            .iter()
+            .map(|pasta| ListItem {
+                id: pasta.id_as_animals(),
+                url: format!("{}/upload/{}", ARGS.public_path_as_str(), pasta.id_as_animals()),
+                title: &pasta.title,
+                pasta_type: &pasta.pasta_type,
+                extension: &pasta.extension,
+                tags: &pasta.tags,
+                file: pasta.file.as_ref().map(|f| f.name()),
+                size: pasta.total_size(),
+                read_count: pasta.read_count,
+                created: pasta.created,
+                expiration: pasta.expiration,
+            })
+            .collect(),
+        next_cursor,
+    };
+
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(serde_json::to_string(&response).unwrap())
+}
@@ -1,18 +1,27 @@
-use crate::translation::{get_translation, Translation};
+use crate::translation::{request_translation, Translation};
 use crate::args::{Args, ARGS};
 use crate::endpoints::errors::ErrorTemplate;
-use crate::pasta::Pasta;
+use crate::pasta::{Link, Pasta};
 use crate::util::animalnumbers::to_u64;
+use crate::util::access_log;
 use crate::util::auth;
 use crate::util::db::update;
 use crate::util::hashids::to_u64 as hashid_to_u64;
-use crate::util::misc::remove_expired;
+use crate::util::misc::{harden_user_content, remove_expired};
+use crate::util::security_headers::csp_nonce;
+use crate::util::lines::{LineRange, LinesQuery};
+use crate::util::links::{find_link, record_click};
+use crate::util::syntaxhighlighter::{highlight, mark_lines, request_theme, theme_stylesheets, Highlight, View};
+use crate::util::webhooks::notify_read;
 use crate::AppState;
 use actix_multipart::Multipart;
+use actix_web::http::{header, StatusCode};
 use actix_web::{get, post, web, Error, HttpRequest, HttpResponse};
 use askama::Template;
+use serde::Deserialize;
 use magic_crypt::{new_magic_crypt, MagicCryptTrait};
 
+use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 #[derive(Template)]
@@ -21,109 +30,197 @@ struct PastaTemplate<'a> {
     pasta: &'a Pasta,
     args: &'a Args,
     text: Translation,
+    nonce: String,
+    code: Arc<String>,
+    /// Whether `code` is highlighted source, rendered Markdown or a diff.
+    view: View,
+    /// The viewer's highlighting theme, `auto` to follow the colour scheme.
+    theme: String,
+    stylesheets: Vec<(String, String)>,
 }
 
-fn pastaresponse(
+async fn pastaresponse(
+    req: &HttpRequest,
     data: web::Data<AppState>,
     id: web::Path<String>,
     password: String,
     skip_increment: bool,
     text: Translation,
+    nonce: String,
 ) -> HttpResponse {
-    // get access to the pasta collection
-    let mut pastas = data.pastas.lock().unwrap();
-
     let id = if ARGS.hash_ids {
         hashid_to_u64(&id).unwrap_or(0)
     } else {
         to_u64(&id.into_inner()).unwrap_or(0)
     };
 
-    // remove expired pastas (including this one if needed)
-    remove_expired(&mut pastas);
+    // everything the page shows is taken while the lock is held, a burn
+    // after reading pasta may be gone by the time it is rendered
+    let (pasta, job, view) = {
+        // get access to the pasta collection
+        let mut pastas = data.pastas.lock().unwrap();
 
-    // find the index of the pasta in the collection based on u64 id
-    let mut index: usize = 0;
-    let mut found: bool = false;
-    for (i, pasta) in pastas.iter().enumerate() {
-        if pasta.id == id {
-            index = i;
-            found = true;
-            break;
-        }
-    }
+        // remove expired pastas (including this one if needed)
+        remove_expired(&mut pastas);
+
+        let index = match pastas.iter().position(|pasta| pasta.id == id) {
+            Some(index) => index,
+            None => return not_found(text),
+        };
 
-    if found {
         if pastas[index].encrypt_server && password == *"" {
             return HttpResponse::Found()
                 .append_header((
                     "Location",
-                    format!("{}/auth/{}", ARGS.public_path_as_str(), pastas[index].id_as_animals()),
+                    format!(
+                        "{}/auth/{}",
+                        ARGS.public_path_as_str(),
+                        pastas[index].id_as_animals()
+                    ),
                 ))
                 .finish();
         }
 
+        // decrypt content temporarily
+        let mut content = pastas[index].content.to_owned();
+        if password != *"" && !content.is_empty() {
+            match decrypt(&content, &password) {
+                Ok(decrypted) => content = decrypted,
+                Err(_) => {
+                    return HttpResponse::Found()
+                        .append_header((
+                            "Location",
+                            format!(
+                                "{}/auth/{}/incorrect",
+                                ARGS.public_path_as_str(),
+                                pastas[index].id_as_animals()
+                            ),
+                        ))
+                        .finish();
+                }
+            }
+        }
+
+        // only readers who got to see the content count
         if !skip_increment {
             // increment read count
             pastas[index].read_count += 1;
+            access_log::record(&mut pastas[index], req, "view");
 
             // save the updated read count
             update(Some(&pastas), Some(&pastas[index]));
+            notify_read(&pastas[index]);
         }
 
-        let original_content = pastas[index].content.to_owned();
+        let mut pasta = pastas[index].clone();
+        pasta.content = content;
+        let view = pasta_view(req, &pasta);
+        let job = (!pasta.content.is_empty()
+            && (pasta.extension != "auto" || view != View::Source)
+            && !pasta.encrypt_client)
+            .then(|| Highlight {
+                id: pasta.id,
+                version: pasta.version,
+                extension: if ARGS.highlightsyntax {
+                    pasta.extension.to_owned()
+                } else {
+                    String::from("txt")
+                },
+                view,
+                content: pasta.content.to_owned(),
+                cacheable: password.is_empty(),
+            });
+        (pasta, job, view)
+    };
 
-        // decrypt content temporarily
-        if password != *"" && !original_content.is_empty() {
-            let res = decrypt(&original_content, &password);
-            if let Ok(..) = res {
-                pastas[index]
-                    .content
-                    .replace_range(.., res.unwrap().as_str());
-            } else {
-                return HttpResponse::Found()
-                    .append_header((
-                        "Location",
-                        format!("{}/auth/{}/incorrect", ARGS.public_path_as_str(), pastas[index].id_as_animals()),
-                    ))
-                    .finish();
-            }
-        }
+    // highlight without holding the lock, large pastas can take a while
+    let code = match job {
+        Some(job) => highlight(job).await,
+        None => Arc::new(String::new()),
+    };
+
+    // lines linked with ?lines=42-57 stand out even without javascript
+    let code = match requested_lines(req) {
+        Ok(Some(range)) if view == View::Source => Arc::new(mark_lines(&code, range)),
+        _ => code,
+    };
+
+    let theme = request_theme(req);
 
-        // serve pasta in template
-        let response = HttpResponse::Ok().content_type("text/html; charset=utf-8").body(
+    // serve pasta in template
+    let response = HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header((header::ETAG, pasta.etag()))
+        .body(
             PastaTemplate {
-                pasta: &pastas[index],
+                pasta: &pasta,
                 args: &ARGS,
                 text,
+                nonce,
+                code,
+                view,
+                stylesheets: theme_stylesheets(theme.as_deref()),
+                theme: theme.unwrap_or_else(|| String::from("auto")),
             }
             .render()
             .unwrap(),
         );
 
-        if pastas[index].content != original_content {
-            pastas[index].content = original_content;
+    // get current unix time in seconds
+    let timenow: i64 = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(n) => n.as_secs(),
+        Err(_) => {
+            log::error!("SystemTime before UNIX EPOCH!");
+            0
         }
+    } as i64;
 
-        // get current unix time in seconds
-        let timenow: i64 = match SystemTime::now().duration_since(UNIX_EPOCH) {
-            Ok(n) => n.as_secs(),
-            Err(_) => {
-                log::error!("SystemTime before UNIX EPOCH!");
-                0
-            }
-        } as i64;
-
-        // update last read time
+    // update last read time, unless the pasta was burnt in the meantime
+    let mut pastas = data.pastas.lock().unwrap();
+    if let Some(index) = pastas.iter().position(|pasta| pasta.id == id) {
         pastas[index].last_read = timenow;
-
-        // save the updated read count
         update(Some(&pastas), Some(&pastas[index]));
+    }
+
+    response
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct ViewQuery {
+    /// rendered, split, inline or source
+    view: String,
+}
 
-        return response;
+/// How to show a pasta: diffs side by side unless `?view=inline` or
+/// `?view=source` asks otherwise, text as rendered Markdown by default for
+/// `md` pastas, or as asked for with `?view=rendered` and `?view=source`.
+fn pasta_view(req: &HttpRequest, pasta: &Pasta) -> View {
+    if pasta.encrypt_client {
+        return View::Source;
+    }
+    let query = web::Query::<ViewQuery>::from_query(req.query_string()).unwrap_or_else(|_| web::Query(ViewQuery::default()));
+    match (pasta.pasta_type.as_str(), query.view.as_str()) {
+        ("diff", "inline") => View::InlineDiff,
+        ("diff", "source") => View::Source,
+        ("diff", _) => View::SplitDiff,
+        ("text", "rendered") => View::Markdown,
+        ("text", "source") => View::Source,
+        ("text", _) if pasta.extension == "md" => View::Markdown,
+        _ => View::Source,
     }
+}
 
-    // otherwise send pasta not found error
+/// The range from `?lines=`, if there is one.
+fn requested_lines(req: &HttpRequest) -> Result<Option<LineRange>, String> {
+    let query = web::Query::<LinesQuery>::from_query(req.query_string()).unwrap_or_else(|_| web::Query(LinesQuery::default()));
+    if query.lines.is_empty() {
+        return Ok(None);
+    }
+    query.lines.parse().map(Some)
+}
+
+fn not_found(text: Translation) -> HttpResponse {
     HttpResponse::Ok()
         .content_type("text/html; charset=utf-8")
         .body(ErrorTemplate { args: &ARGS, text }.render().unwrap())
@@ -137,9 +234,8 @@ pub async fn postpasta(
     payload: Multipart,
 ) -> Result<HttpResponse, Error> {
     let password = auth::password_from_multipart(payload).await?;
-    let lang = req.cookie("lang").map(|c| c.value().to_string()).unwrap_or_else(|| "zh".to_string());
-    let text = get_translation(&lang);
-    Ok(pastaresponse(data, id, password, false, text))
+    let text = request_translation(&req);
+    Ok(pastaresponse(&req, data, id, password, false, text, csp_nonce(&req)).await)
 }
 
 #[post("/p/{id}")]
@@ -150,9 +246,8 @@ pub async fn postshortpasta(
     payload: Multipart,
 ) -> Result<HttpResponse, Error> {
     let password = auth::password_from_multipart(payload).await?;
-    let lang = req.cookie("lang").map(|c| c.value().to_string()).unwrap_or_else(|| "zh".to_string());
-    let text = get_translation(&lang);
-    Ok(pastaresponse(data, id, password, false, text))
+    let text = request_translation(&req);
+    Ok(pastaresponse(&req, data, id, password, false, text, csp_nonce(&req)).await)
 }
 
 #[get("/upload/{id}")]
@@ -173,10 +268,9 @@ pub async fn getpasta(
         }
     }
 
-    let lang = req.cookie("lang").map(|c| c.value().to_string()).unwrap_or_else(|| "zh".to_string());
-    let text = get_translation(&lang);
+    let text = request_translation(&req);
 
-    pastaresponse(data, id, String::from(""), skip_increment, text)
+    pastaresponse(&req, data, id, String::from(""), skip_increment, text, csp_nonce(&req)).await
 }
 
 // when creating a pasta, the owner is issued a token with a 15-second expiration
@@ -207,46 +301,75 @@ fn verify_owner_token(token: &str, id: &str) -> bool {
 
 #[get("/p/{id}")]
 pub async fn getshortpasta(req: HttpRequest, data: web::Data<AppState>, id: web::Path<String>) -> HttpResponse {
-    let lang = req.cookie("lang").map(|c| c.value().to_string()).unwrap_or_else(|| "zh".to_string());
-    let text = get_translation(&lang);
-    pastaresponse(data, id, String::from(""), false, text)
+    let text = request_translation(&req);
+    pastaresponse(&req, data, id, String::from(""), false, text, csp_nonce(&req)).await
 }
 
-fn urlresponse(data: web::Data<AppState>, id: web::Path<String>, text: Translation) -> HttpResponse {
+#[derive(Template)]
+#[template(path = "link_preview.html")]
+struct LinkPreviewTemplate<'a> {
+    pasta: &'a Pasta,
+    args: &'a Args,
+    text: Translation,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct LinkQuery {
+    /// set by the preview page to go on to the destination
+    go: String,
+}
+
+fn urlresponse(
+    req: &HttpRequest,
+    data: web::Data<AppState>,
+    id: web::Path<String>,
+    text: Translation,
+) -> HttpResponse {
     // get access to the pasta collection
     let mut pastas = data.pastas.lock().unwrap();
 
+    let slug = id.into_inner();
     let id = if ARGS.hash_ids {
-        hashid_to_u64(&id).unwrap_or(0)
+        hashid_to_u64(&slug).ok()
     } else {
-        to_u64(&id.into_inner()).unwrap_or(0)
+        to_u64(&slug).ok()
     };
 
     // remove expired pastas (including this one if needed)
     remove_expired(&mut pastas);
 
-    // find the index of the pasta in the collection based on u64 id
-    let mut index: usize = 0;
-    let mut found: bool = false;
+    // find the link by its custom slug, or else by id
+    if let Some(index) = find_link(&pastas, &slug.to_lowercase(), id) {
+        let link = pastas[index].link.clone().unwrap_or_default();
 
-    for (i, pasta) in pastas.iter().enumerate() {
-        if pasta.id == id {
-            index = i;
-            found = true;
-            break;
+        // show where the link leads first if the creator asked for it
+        let query = web::Query::<LinkQuery>::from_query(req.query_string()).unwrap_or_else(|_| web::Query(LinkQuery::default()));
+        if pastas[index].pasta_type == "url" && link.preview && query.go.is_empty() {
+            return HttpResponse::Ok()
+                .content_type("text/html; charset=utf-8")
+                .body(
+                    LinkPreviewTemplate {
+                        pasta: &pastas[index],
+                        args: &ARGS,
+                        text,
+                    }
+                    .render()
+                    .unwrap(),
+                );
         }
-    }
 
-    if found {
         // increment read count
         pastas[index].read_count += 1;
+        access_log::record(&mut pastas[index], req, "redirect");
 
         // save the updated read count
         update(Some(&pastas), Some(&pastas[index]));
+        notify_read(&pastas[index]);
 
         // send redirect if it's a url pasta
         if pastas[index].pasta_type == "url" {
-            let response = HttpResponse::Found()
+            let response = HttpResponse::build(StatusCode::from_u16(link.status).unwrap_or(StatusCode::FOUND))
                 .append_header(("Location", String::from(&pastas[index].content)))
                 .finish();
 
@@ -259,8 +382,9 @@ fn urlresponse(data: web::Data<AppState>, id: web::Path<String>, text: Translati
                 }
             } as i64;
 
-            // update last read time
+            // update last read time and the click statistics
             pastas[index].last_read = timenow;
+            record_click(pastas[index].link.get_or_insert_with(Link::default), timenow);
 
             // save the updated read count
             update(Some(&pastas), Some(&pastas[index]));
@@ -282,31 +406,26 @@ fn urlresponse(data: web::Data<AppState>, id: web::Path<String>, text: Translati
 
 #[get("/url/{id}")]
 pub async fn redirecturl(req: HttpRequest, data: web::Data<AppState>, id: web::Path<String>) -> HttpResponse {
-    let lang = req.cookie("lang").map(|c| c.value().to_string()).unwrap_or_else(|| "zh".to_string());
-    let text = get_translation(&lang);
-    urlresponse(data, id, text)
+    let text = request_translation(&req);
+    urlresponse(&req, data, id, text)
 }
 
 #[get("/u/{id}")]
 pub async fn shortredirecturl(req: HttpRequest, data: web::Data<AppState>, id: web::Path<String>) -> HttpResponse {
-    let lang = req.cookie("lang").map(|c| c.value().to_string()).unwrap_or_else(|| "zh".to_string());
-    let text = get_translation(&lang);
-    urlresponse(data, id, text)
+    let text = request_translation(&req);
+    urlresponse(&req, data, id, text)
 }
 
 #[get("/raw/{id}")]
 pub async fn getrawpasta(
+    req: HttpRequest,
     data: web::Data<AppState>,
     id: web::Path<String>,
 ) -> Result<HttpResponse, Error> {
     // get access to the pasta collection
     let mut pastas = data.pastas.lock().unwrap();
 
-    let id = if ARGS.hash_ids {
-        hashid_to_u64(&id).unwrap_or(0)
-    } else {
-        to_u64(&id.into_inner()).unwrap_or(0)
-    };
+    let (id, patch) = raw_id(&id);
 
     // remove expired pastas (including this one if needed)
     remove_expired(&mut pastas);
@@ -332,11 +451,18 @@ pub async fn getrawpasta(
                 .finish());
         }
 
+        let lines = match requested_lines(&req) {
+            Ok(lines) => lines,
+            Err(message) => return Ok(HttpResponse::BadRequest().content_type("text/plain; charset=utf-8").body(message)),
+        };
+
         // increment read count
         pastas[index].read_count += 1;
+        access_log::record(&mut pastas[index], &req, "raw");
 
         // save the updated read count
         update(Some(&pastas), Some(&pastas[index]));
+        notify_read(&pastas[index]);
 
         // get current unix time in seconds
         let timenow: i64 = match SystemTime::now().duration_since(UNIX_EPOCH) {
@@ -350,12 +476,12 @@ pub async fn getrawpasta(
         // update last read time
         pastas[index].last_read = timenow;
 
-        // send raw content of pasta
-        let response = Ok(HttpResponse::Ok()
-            .content_type("text/plain; charset=utf-8")
-            .body(pastas[index].content.to_owned()));
+        // send raw content of pasta, or just the requested lines
+        let content = &pastas[index].content;
+        let body = lines.map_or(content.as_str(), |range| range.slice(content)).to_owned();
+        let response = raw_response(&pastas[index], body, patch);
 
-        return response;
+        return Ok(response);
     }
 
     // otherwise send pasta not found error as raw text
@@ -366,6 +492,7 @@ pub async fn getrawpasta(
 
 #[post("/raw/{id}")]
 pub async fn postrawpasta(
+    req: HttpRequest,
     data: web::Data<AppState>,
     id: web::Path<String>,
     payload: Multipart,
@@ -375,11 +502,7 @@ pub async fn postrawpasta(
     // get access to the pasta collection
     let mut pastas = data.pastas.lock().unwrap();
 
-    let id = if ARGS.hash_ids {
-        hashid_to_u64(&id).unwrap_or(0)
-    } else {
-        to_u64(&id.into_inner()).unwrap_or(0)
-    };
+    let (id, patch) = raw_id(&id);
 
     // remove expired pastas (including this one if needed)
     remove_expired(&mut pastas);
@@ -405,31 +528,32 @@ pub async fn postrawpasta(
                 .finish());
         }
 
-        // increment read count
-        pastas[index].read_count += 1;
+        let lines = match requested_lines(&req) {
+            Ok(lines) => lines,
+            Err(message) => return Ok(HttpResponse::BadRequest().content_type("text/plain; charset=utf-8").body(message)),
+        };
 
-        // save the updated read count
-        update(Some(&pastas), Some(&pastas[index]));
-
-        let original_content = pastas[index].content.to_owned();
-
-        // decrypt content temporarily
+        // decrypt content temporarily, the stored pasta stays encrypted
+        let mut content = pastas[index].content.to_owned();
         if password != *"" {
-            let res = decrypt(&original_content, &password);
-            if res.is_ok() {
-                pastas[index]
-                    .content
-                    .replace_range(.., res.unwrap().as_str());
-            } else {
-                return Ok(HttpResponse::Found()
-                    .append_header((
-                        "Location",
-                        format!("{}/auth/{}/incorrect", ARGS.public_path_as_str(), pastas[index].id_as_animals()),
-                    ))
-                    .finish());
+            match decrypt(&content, &password) {
+                Ok(decrypted) => content = decrypted,
+                Err(_) => {
+                    return Ok(HttpResponse::Found()
+                        .append_header((
+                            "Location",
+                            format!("{}/auth/{}/incorrect", ARGS.public_path_as_str(), pastas[index].id_as_animals()),
+                        ))
+                        .finish());
+                }
             }
         }
 
+        // only readers who got to see the content count
+        pastas[index].read_count += 1;
+        access_log::record(&mut pastas[index], &req, "raw");
+        notify_read(&pastas[index]);
+
         // get current unix time in seconds
         let timenow: i64 = match SystemTime::now().duration_since(UNIX_EPOCH) {
             Ok(n) => n.as_secs(),
@@ -445,16 +569,11 @@ pub async fn postrawpasta(
         // save the updated read count
         update(Some(&pastas), Some(&pastas[index]));
 
-        // send raw content of pasta
-        let response = Ok(HttpResponse::Ok()
-            .content_type("text/plain; charset=utf-8")
-            .body(pastas[index].content.to_owned()));
-
-        if pastas[index].content != original_content {
-            pastas[index].content = original_content;
-        }
+        // send raw content of pasta, or just the requested lines
+        let body = lines.map_or(content.as_str(), |range| range.slice(&content)).to_owned();
+        let response = raw_response(&pastas[index], body, patch);
 
-        return response;
+        return Ok(response);
     }
 
     // otherwise send pasta not found error as raw text
@@ -463,6 +582,41 @@ pub async fn postrawpasta(
         .body(String::from("Upload not found! :-(")))
 }
 
+/// The id from `/raw/{id}`, and whether it ends in `.patch` to download the
+/// content as a patch file.
+fn raw_id(id: &str) -> (u64, bool) {
+    let (id, patch) = match id.strip_suffix(".patch") {
+        Some(id) => (id, true),
+        None => (id, false),
+    };
+    let id = if ARGS.hash_ids {
+        hashid_to_u64(id).unwrap_or(0)
+    } else {
+        to_u64(id).unwrap_or(0)
+    };
+    (id, patch)
+}
+
+fn raw_response(pasta: &Pasta, body: String, patch: bool) -> HttpResponse {
+    let mut response = if patch {
+        HttpResponse::Ok()
+            .content_type("text/x-diff; charset=utf-8")
+            .insert_header((
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}.patch\"", pasta.id_as_animals()),
+            ))
+            .insert_header((header::ETAG, pasta.etag()))
+            .body(body)
+    } else {
+        HttpResponse::Ok()
+            .content_type("text/plain; charset=utf-8")
+            .insert_header((header::ETAG, pasta.etag()))
+            .body(body)
+    };
+    harden_user_content(&mut response);
+    response
+}
+
 fn decrypt(text_str: &str, key_str: &str) -> Result<String, magic_crypt::MagicCryptError> {
     let mc = new_magic_crypt!(key_str, 256);
 
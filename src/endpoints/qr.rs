@@ -1,8 +1,10 @@
-use crate::translation::{get_translation, Translation};
+use crate::translation::{request_translation, Translation};
 use crate::args::{Args, ARGS};
 use crate::endpoints::errors::ErrorTemplate;
 use crate::pasta::Pasta;
+use crate::util::access_log;
 use crate::util::animalnumbers::to_u64;
+use crate::util::db::update;
 use crate::util::hashids::to_u64 as hashid_to_u64;
 use crate::util::misc::{self, remove_expired};
 use crate::AppState;
@@ -31,8 +33,7 @@ pub async fn getqr(req: HttpRequest, data: web::Data<AppState>, id: web::Path<St
 
     remove_expired(&mut pastas);
     
-    let lang = req.cookie("lang").map(|c| c.value().to_string()).unwrap_or_else(|| "zh".to_string());
-    let text = get_translation(&lang);
+    let text = request_translation(&req);
 
     // find the index of the pasta in the collection based on u64 id
     let mut index: usize = 0;
@@ -46,11 +47,16 @@ pub async fn getqr(req: HttpRequest, data: web::Data<AppState>, id: web::Path<St
     }
 
     if found {
+        if pastas[index].access_log.is_some() {
+            access_log::record(&mut pastas[index], &req, "qr");
+            update(Some(&pastas), Some(&pastas[index]));
+        }
+
         // generate the QR code as an SVG - if its a file or text pastas, this will point to the /upload endpoint, otherwise to the /url endpoint, essentially directly taking the user to the url stored in the pasta
         let svg = misc::string_to_qr_svg(&match pastas[index].pasta_type.as_str() {
             "url" => match ARGS.short_path.as_ref() {
-                Some(short) => format!("{short}/u/{id}"),
-                _ => format!("{}/url/{}", &ARGS.public_path_as_str(), &id),
+                Some(short) => format!("{short}/u/{}", pastas[index].link_id()),
+                _ => format!("{}/url/{}", &ARGS.public_path_as_str(), pastas[index].link_id()),
             },
             _ => match ARGS.short_path.as_ref() {
                 Some(short) => format!("{short}/p/{id}"),
@@ -9,7 +9,7 @@ use crate::util::auth;
 use crate::util::db::delete;
 use crate::util::hashids::to_u64 as hashid_to_u64;
 use crate::util::misc::remove_expired;
-use crate::translation::get_translation;
+use crate::translation::request_translation;
 use crate::AppState;
 use askama::Template;
 use std::fs;
@@ -75,8 +75,7 @@ pub async fn remove(req: HttpRequest, data: web::Data<AppState>, id: web::Path<S
 
     remove_expired(&mut pastas);
     
-    let lang = req.cookie("lang").map(|c| c.value().to_string()).unwrap_or_else(|| "zh".to_string());
-    let text = get_translation(&lang);
+    let text = request_translation(&req);
 
     HttpResponse::Ok()
         .content_type("text/html; charset=utf-8")
@@ -102,8 +101,7 @@ pub async fn post_remove(
 
     let password = auth::password_from_multipart(payload).await?;
     
-    let lang = req.cookie("lang").map(|c| c.value().to_string()).unwrap_or_else(|| "zh".to_string());
-    let text = get_translation(&lang);
+    let text = request_translation(&req);
 
     for (i, pasta) in pastas.iter().enumerate() {
         if pasta.id == id {
@@ -184,8 +182,7 @@ pub async fn post_remove(
         }
     }
 
-    let lang = req.cookie("lang").map(|c| c.value().to_string()).unwrap_or_else(|| "zh".to_string());
-    let text = get_translation(&lang);
+    let text = request_translation(&req);
     
     Ok(HttpResponse::Ok()
         .content_type("text/html; charset=utf-8")
@@ -0,0 +1,142 @@
+use actix_multipart::Multipart;
+use actix_web::{get, post, web, Error, HttpRequest, HttpResponse};
+use askama::Template;
+use futures::TryStreamExt;
+use serde::Deserialize;
+
+use crate::args::{Args, ARGS};
+use crate::pasta::Pasta;
+use crate::translation::{request_translation, Translation};
+use crate::util::db;
+use crate::util::misc::remove_expired;
+use crate::util::search::snippet;
+use crate::AppState;
+
+/// Most results shown for one query.
+const MAX_RESULTS: usize = 100;
+
+struct SearchResult<'a> {
+    pasta: &'a Pasta,
+    snippet: String,
+}
+
+#[derive(Template)]
+#[template(path = "search.html")]
+struct SearchTemplate<'a> {
+    query: String,
+    results: Vec<SearchResult<'a>>,
+    admin: bool,
+    username: String,
+    password: String,
+    status: String,
+    args: &'a Args,
+    text: Translation,
+}
+
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    q: Option<String>,
+}
+
+/// Runs the search and renders the results. Private and encrypted pastas are
+/// only included for the admin, and nothing is listed to others if listing
+/// is turned off.
+fn render_search(
+    req: &HttpRequest,
+    data: &web::Data<AppState>,
+    query: String,
+    admin: bool,
+    credentials: (String, String),
+    status: String,
+) -> HttpResponse {
+    let mut pastas = data.pastas.lock().unwrap();
+
+    remove_expired(&mut pastas);
+
+    let ids = if query.trim().is_empty() || (ARGS.no_listing && !admin) {
+        Vec::new()
+    } else {
+        db::search(&query)
+    };
+
+    let results = ids
+        .iter()
+        .filter_map(|id| pastas.iter().find(|p| p.id == *id))
+        .filter(|pasta| admin || !pasta.private)
+        .take(MAX_RESULTS)
+        .map(|pasta| SearchResult {
+            pasta,
+            snippet: if pasta.encrypt_server || pasta.encrypt_client {
+                String::from("")
+            } else {
+                snippet(&pasta.content, &query)
+            },
+        })
+        .collect();
+
+    let text = request_translation(req);
+
+    let (username, password) = if admin { credentials } else { Default::default() };
+
+    HttpResponse::Ok().content_type("text/html; charset=utf-8").body(
+        SearchTemplate {
+            query,
+            results,
+            admin,
+            username,
+            password,
+            status,
+            args: &ARGS,
+            text,
+        }
+        .render()
+        .unwrap(),
+    )
+}
+
+#[get("/search")]
+pub async fn get_search(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    query: web::Query<SearchQuery>,
+) -> HttpResponse {
+    let query = query.into_inner().q.unwrap_or_default();
+    render_search(&req, &data, query, false, Default::default(), String::from(""))
+}
+
+/// Search with the admin login, which also finds private uploads.
+#[post("/search")]
+pub async fn post_search(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+    mut payload: Multipart,
+) -> Result<HttpResponse, Error> {
+    let mut query = String::from("");
+    let mut username = String::from("");
+    let mut password = String::from("");
+
+    while let Some(mut field) = payload.try_next().await? {
+        let mut value = String::from("");
+        while let Some(chunk) = field.try_next().await? {
+            value.push_str(std::str::from_utf8(&chunk).unwrap_or(""));
+        }
+        match field.name() {
+            Some("q") => query = value,
+            Some("username") => username = value,
+            Some("password") => password = value,
+            _ => {}
+        }
+    }
+
+    let admin = username == ARGS.auth_admin_username && password == ARGS.auth_admin_password;
+    let status = if admin { "" } else { "incorrect" };
+
+    Ok(render_search(
+        &req,
+        &data,
+        query,
+        admin,
+        (username, password),
+        String::from(status),
+    ))
+}
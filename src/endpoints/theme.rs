@@ -0,0 +1,41 @@
+use actix_web::cookie::Cookie;
+use actix_web::http::header;
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+
+use crate::util::syntaxhighlighter::{find_theme, theme_css};
+
+#[get("/highlight/{theme}.css")]
+pub async fn get_theme_css(path: web::Path<String>) -> HttpResponse {
+    match theme_css(&path.into_inner()) {
+        Some(css) => HttpResponse::Ok()
+            .content_type("text/css; charset=utf-8")
+            .insert_header((header::CACHE_CONTROL, "public, max-age=86400"))
+            .body(css),
+        None => HttpResponse::NotFound().body("404 Not Found"),
+    }
+}
+
+/// Remembers the viewer's highlighting theme, `auto` goes back to following
+/// the colour scheme.
+#[get("/set_theme/{theme}")]
+pub async fn set_theme(req: HttpRequest, path: web::Path<String>) -> impl Responder {
+    let redirect_to = req
+        .headers()
+        .get("referer")
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or("/");
+
+    let cookie = match find_theme(&path.into_inner()) {
+        Some(slug) => Cookie::build("highlight_theme", slug).path("/").finish(),
+        None => {
+            let mut cookie = Cookie::build("highlight_theme", "").path("/").finish();
+            cookie.make_removal();
+            cookie
+        }
+    };
+
+    HttpResponse::Found()
+        .append_header(("Location", redirect_to))
+        .cookie(cookie)
+        .finish()
+}
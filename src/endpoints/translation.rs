@@ -1,15 +1,14 @@
 use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
 use actix_web::cookie::Cookie;
 
+use crate::translation::is_available;
+
 #[get("/set_lang/{lang}")]
 pub async fn set_lang(req: HttpRequest, path: web::Path<String>) -> impl Responder {
-    let lang = path.into_inner();
+    let lang = path.into_inner().to_lowercase();
     
     // Validate lang
-    let lang_val = match lang.as_str() {
-        "zh" => "zh",
-        _ => "en",
-    };
+    let lang_val = if is_available(&lang) { lang } else { String::from("en") };
     
     // Get referer or default to "/"
     let redirect_to = req
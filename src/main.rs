@@ -1,14 +1,18 @@
 extern crate core;
 
 use crate::args::ARGS;
+use crate::collection::Collection;
 use crate::endpoints::{
-    admin, auth_admin, auth_upload, create, edit, errors, file, guide, list,
-    pasta as pasta_endpoint, qr, remove, static_resources,
-    translation as translation_endpoint,
+    access_log, admin, auth_admin, auth_upload, collection as collection_endpoint, create, edit,
+    errors, feed, file, fork, guide, history, list, pasta as pasta_endpoint, qr, remove, search,
+    static_resources, theme, translation as translation_endpoint,
 };
 use crate::pasta::Pasta;
-use crate::util::db::read_all;
+use crate::util::db::{read_all, read_collections};
+use crate::util::listeners::{listeners, Listener};
+use crate::util::shutdown::{flush_state, remove_orphaned_attachments, wait_for_signal};
 use crate::util::telemetry::start_telemetry_thread;
+use crate::util::webhooks;
 use actix_web::middleware::Condition;
 use actix_web::{middleware, web, App, HttpServer};
 use actix_web_httpauth::middleware::HttpAuthentication;
@@ -17,46 +21,73 @@ use env_logger::Builder;
 use log::LevelFilter;
 use std::fs;
 use std::io::Write;
+use std::sync::atomic::AtomicBool;
 use std::sync::Mutex;
 
 pub mod args;
+pub mod collection;
 pub mod pasta;
 pub mod translation;
 
 pub mod util {
+    pub mod access_log;
     pub mod animalnumbers;
     pub mod auth;
+    pub mod catalog;
     pub mod db;
     pub mod db_json;
+    pub mod diff;
+    pub mod expiration;
     #[cfg(feature = "default")]
     pub mod db_sqlite;
     pub mod hashids;
+    pub mod lines;
+    pub mod links;
+    pub mod markdown;
     pub mod misc;
+    pub mod search;
+    pub mod security_headers;
+    pub mod shutdown;
     pub mod syntaxhighlighter;
     pub mod telemetry;
+    #[cfg(feature = "__rustcrypto-tls")]
+    pub mod tls;
     pub mod version;
+    pub mod webhooks;
     pub mod http_client;
+    pub mod listing;
+    pub mod listeners;
 }
 
 pub mod endpoints {
+    pub mod access_log;
     pub mod admin;
     pub mod auth_admin;
     pub mod auth_upload;
+    pub mod collection;
     pub mod create;
     pub mod edit;
     pub mod errors;
+    pub mod feed;
     pub mod file;
+    pub mod fork;
     pub mod guide;
+    pub mod history;
     pub mod list;
     pub mod pasta;
     pub mod qr;
     pub mod remove;
+    pub mod search;
     pub mod static_resources;
+    pub mod theme;
     pub mod translation;
 }
 
 pub struct AppState {
     pub pastas: Mutex<Vec<Pasta>>,
+    pub collections: Mutex<Vec<Collection>>,
+    /// Set once a shutdown signal arrives, new uploads are refused from then on.
+    pub shutting_down: AtomicBool,
 }
 
 #[actix_web::main]
@@ -74,11 +105,8 @@ async fn main() -> std::io::Result<()> {
         .filter(None, LevelFilter::Info)
         .init();
 
-    log::info!(
-        "MicroBin starting on http://{}:{}",
-        ARGS.bind.to_string(),
-        ARGS.port.to_string()
-    );
+    log::info!("MicroBin starting");
+    ARGS.check_default_expiry();
 
     match fs::create_dir_all(format!("{}/public", ARGS.data_dir)) {
         Ok(dir) => dir,
@@ -95,18 +123,30 @@ async fn main() -> std::io::Result<()> {
         }
     };
 
+    let pastas = read_all();
+    remove_orphaned_attachments(&pastas);
+
     let data = web::Data::new(AppState {
-        pastas: Mutex::new(read_all()),
+        pastas: Mutex::new(pastas),
+        collections: Mutex::new(read_collections()),
+        shutting_down: AtomicBool::new(false),
     });
+    let shutdown_data = data.clone();
 
     if !ARGS.disable_telemetry {
         start_telemetry_thread();
     }
 
-    HttpServer::new(move || {
+    webhooks::start_worker();
+
+    let mut server = HttpServer::new(move || {
         App::new()
             .app_data(data.clone())
             .wrap(middleware::NormalizePath::trim())
+            .wrap(Condition::new(
+                !ARGS.disable_security_headers,
+                middleware::from_fn(util::security_headers::security_headers),
+            ))
             .wrap(middleware::Logger::default())
             // Conditional / Public Services
             .service(pasta_endpoint::getpasta)
@@ -114,6 +154,8 @@ async fn main() -> std::io::Result<()> {
             .service(pasta_endpoint::getshortpasta)
             .service(pasta_endpoint::postshortpasta)
             .service(translation_endpoint::set_lang)
+            .service(theme::set_theme)
+            .service(theme::get_theme_css)
             .service(pasta_endpoint::getrawpasta)
             .service(pasta_endpoint::postrawpasta)
             .service(pasta_endpoint::redirecturl)
@@ -121,6 +163,8 @@ async fn main() -> std::io::Result<()> {
             .service(qr::getqr)
             .service(file::get_file)
             .service(file::post_secure_file)
+            .service(access_log::get_access_log)
+            .service(access_log::post_access_log)
             .service(static_resources::static_resources)
             .service(guide::guide)
             .service(auth_upload::auth_file_with_status)
@@ -128,11 +172,15 @@ async fn main() -> std::io::Result<()> {
             .service(auth_upload::auth_raw_pasta_with_status)
             .service(auth_upload::auth_edit_private_with_status)
             .service(auth_upload::auth_remove_private_with_status)
+            .service(auth_upload::auth_history_with_status)
+            .service(auth_upload::auth_fork_with_status)
             .service(auth_upload::auth_file)
             .service(auth_upload::auth_upload)
             .service(auth_upload::auth_raw_pasta)
             .service(auth_upload::auth_edit_private)
             .service(auth_upload::auth_remove_private)
+            .service(auth_upload::auth_history)
+            .service(auth_upload::auth_fork)
             // Protected Services (Require Login)
             .service(
                 web::scope("")
@@ -150,18 +198,88 @@ async fn main() -> std::io::Result<()> {
                     .service(edit::post_edit)
                     .service(edit::post_edit_private)
                     .service(edit::post_submit_edit_private)
+                    .service(edit::put_raw)
+                    .service(history::get_history)
+                    .service(history::get_history_with_status)
+                    .service(history::post_history)
+                    .service(history::post_restore)
+                    .service(fork::get_fork)
+                    .service(fork::post_fork)
                     .service(admin::get_admin)
                     .service(admin::post_admin)
                     .service(remove::remove)
                     .service(remove::post_remove)
                     .service(list::list)
+                    .service(list::list_json)
+                    .service(feed::atom)
+                    .service(feed::rss)
+                    .service(search::get_search)
+                    .service(collection_endpoint::get_collections)
+                    .service(collection_endpoint::post_collections)
+                    .service(collection_endpoint::get_collection)
+                    .service(collection_endpoint::get_collection_with_status)
+                    .service(collection_endpoint::post_collection)
+                    .service(search::post_search)
                     .service(web::resource("/upload").route(web::post().to(create::create)))
                     .service(create::index_with_status)
             )
             .default_service(web::route().to(errors::not_found))
     })
-    .bind((ARGS.bind, ARGS.port))?
     .workers(ARGS.threads as usize)
-    .run()
-    .await
+    .shutdown_timeout(ARGS.shutdown_timeout)
+    .disable_signals();
+
+    #[cfg(feature = "__rustcrypto-tls")]
+    let tls_config = util::tls::server_config()?;
+
+    #[cfg(not(feature = "__rustcrypto-tls"))]
+    if ARGS.tls_cert.is_some() || ARGS.tls_key.is_some() {
+        panic!("HTTPS support requires MicroBin to be compiled with the __rustcrypto-tls feature");
+    }
+
+    for listener in listeners()? {
+        server = match listener {
+            #[cfg(feature = "__rustcrypto-tls")]
+            Listener::Tcp(lst) if tls_config.is_some() => {
+                server.listen_rustls_0_23(lst, tls_config.clone().unwrap())?
+            }
+            Listener::Tcp(lst) => server.listen(lst)?,
+            #[cfg(unix)]
+            Listener::Unix(lst) => server.listen_uds(lst)?,
+        };
+    }
+
+    let server = server.run();
+
+    #[cfg(feature = "__rustcrypto-tls")]
+    let redirect = if let Some(redirect_port) = ARGS.tls_redirect_port.filter(|_| ARGS.tls_cert.is_some()) {
+        log::info!("Redirecting HTTP on port {} to HTTPS", redirect_port);
+
+        let mut redirect = HttpServer::new(|| {
+            App::new().default_service(web::route().to(util::tls::redirect_to_https))
+        })
+        .workers(1)
+        .disable_signals();
+
+        for addr in ARGS.bind.iter() {
+            let addr = std::net::SocketAddr::new(*addr, redirect_port);
+            redirect = redirect.listen(util::listeners::tcp_listener(addr)?)?;
+        }
+
+        Some(redirect.run())
+    } else {
+        None
+    };
+    #[cfg(not(feature = "__rustcrypto-tls"))]
+    let redirect: Option<actix_web::dev::Server> = None;
+
+    let servers: Vec<_> = std::iter::once(server).chain(redirect).collect();
+    let handles = servers.iter().map(|s| s.handle()).collect();
+
+    actix_web::rt::spawn(wait_for_signal(shutdown_data.clone(), handles));
+
+    futures::future::try_join_all(servers).await?;
+    flush_state(&shutdown_data);
+
+    Ok(())
 }
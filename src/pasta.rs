@@ -1,5 +1,5 @@
 use bytesize::ByteSize;
-use chrono::{Datelike, Local, TimeZone, Timelike};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::path::Path;
@@ -8,12 +8,33 @@ use std::time::{SystemTime, UNIX_EPOCH};
 use crate::args::ARGS;
 use crate::util::animalnumbers::to_animal_names;
 use crate::util::hashids::to_hashids;
-use crate::util::syntaxhighlighter::html_highlight;
-
-#[derive(Serialize, Deserialize, PartialEq, Debug, Eq)]
+use crate::util::links::daily_clicks;
+use crate::util::misc::decrypt;
+use crate::util::syntaxhighlighter::detect_syntax;
+
+/// MIME types that are safe to render inline in the pasta view. Anything not
+/// on these lists (notably SVG and HTML) is only ever offered as a download.
+const INLINE_IMAGE_TYPES: &[&str] = &[
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/webp",
+    "image/bmp",
+    "image/avif",
+    "image/x-icon",
+    "image/vnd.microsoft.icon",
+];
+
+const INLINE_VIDEO_TYPES: &[&str] = &["video/mp4", "video/webm", "video/quicktime"];
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Eq, Clone)]
 pub struct PastaFile {
     pub name: String,
     pub size: ByteSize,
+    /// MIME type detected from the file's leading bytes at upload time. Empty
+    /// for attachments uploaded before detection existed.
+    #[serde(default)]
+    pub mime: String,
 }
 
 impl PastaFile {
@@ -24,6 +45,7 @@ impl PastaFile {
         Ok(Self {
             name,
             size: ByteSize::b(0),
+            mime: String::from(""),
         })
     }
 
@@ -31,29 +53,103 @@ impl PastaFile {
         &self.name
     }
 
+    /// The detected MIME type, falling back to a guess from the file name for
+    /// attachments that were stored without one.
+    fn effective_mime(&self) -> String {
+        if self.mime.is_empty() {
+            mime_guess::from_path(&self.name)
+                .first_or_octet_stream()
+                .essence_str()
+                .to_string()
+        } else {
+            self.mime.to_owned()
+        }
+    }
+
     pub fn is_image(&self) -> bool {
-        let lowercase_name = self.name.to_lowercase();
-        let extensions = [
-            ".jpg", ".jpeg", ".png", ".gif", ".bmp", ".webp", ".ico", ".svg", ".tiff", ".tif",
-            ".jfif", ".pjpeg", ".pjp", ".avif", ".jxl", ".heif",
-        ];
-        extensions.iter().any(|&ext| lowercase_name.ends_with(ext))
+        INLINE_IMAGE_TYPES.contains(&self.effective_mime().as_str())
     }
 
     pub fn is_video(&self) -> bool {
-        let lowercase_name = self.name.to_lowercase();
-        let extensions = [
-            ".mp4", ".mov", ".wmv", ".webm", ".avi", ".flv", ".mkv", ".mts",
-        ];
-        extensions.iter().any(|&ext| lowercase_name.ends_with(ext))
+        INLINE_VIDEO_TYPES.contains(&self.effective_mime().as_str())
     }
 
     pub fn embeddable(&self) -> bool {
         self.is_image() || self.is_video()
     }
+
+    /// Content-Type to serve the attachment with. Only allowlisted media
+    /// types are passed through, everything else is sent as opaque bytes.
+    pub fn content_type(&self) -> String {
+        if self.embeddable() {
+            self.effective_mime()
+        } else {
+            String::from("application/octet-stream")
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Eq, Clone)]
+pub struct PastaRevision {
+    /// Sequence number, kept stable when old revisions are pruned.
+    pub number: u64,
+    pub created: i64,
+    /// Name given in the edit form, empty if the editor left it blank.
+    pub editor: String,
+    /// Content exactly as stored, i.e. still encrypted for private pastas.
+    pub content: String,
+}
+
+/// One entry of a pasta's access log.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Eq, Clone)]
+pub struct Access {
+    pub time: i64,
+    /// The client's address with the host part zeroed, see
+    /// `access_log::truncate_ip`.
+    pub client: String,
+    pub user_agent: String,
+    /// view, raw, file, qr or redirect
+    pub route: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Redirects through a URL pasta on one day.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Eq, Clone)]
+pub struct DailyClicks {
+    /// Days since the Unix epoch, in UTC.
+    pub day: i64,
+    pub count: u64,
+}
+
+/// How a URL pasta redirects, and how often it did.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Eq, Clone)]
+pub struct Link {
+    /// Name chosen by the creator, used in `/url/` and `/u/` links instead of
+    /// the id.
+    #[serde(default)]
+    pub slug: Option<String>,
+    /// 301, 302 or 307.
+    pub status: u16,
+    /// Show the destination on a page of its own before redirecting.
+    #[serde(default)]
+    pub preview: bool,
+    /// Oldest first, days without clicks are left out. See
+    /// `links::record_click`.
+    #[serde(default)]
+    pub clicks: Vec<DailyClicks>,
+}
+
+impl Default for Link {
+    fn default() -> Self {
+        Link {
+            slug: None,
+            status: 302,
+            preview: false,
+            clicks: Vec::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Pasta {
     pub id: u64,
     #[serde(default)]
@@ -73,6 +169,31 @@ pub struct Pasta {
     pub read_count: u64,
     pub burn_after_reads: u64,
     pub pasta_type: String,
+    /// Every saved version of the content, oldest first. The last entry is
+    /// the current content. Empty until the pasta is edited for the first time.
+    #[serde(default)]
+    pub revisions: Vec<PastaRevision>,
+    /// Bumped on every content change, used to detect conflicting edits.
+    #[serde(default = "first_version")]
+    pub version: u64,
+    /// Id of the pasta this one was forked from.
+    #[serde(default)]
+    pub parent: Option<u64>,
+    /// Free-form labels, normalized by `parse_tags`.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Who opened the pasta, oldest first. `None` if the creator didn't ask
+    /// for a log.
+    #[serde(default)]
+    pub access_log: Option<Vec<Access>>,
+    /// Redirect settings and click counts of URL pastas. `None` for other
+    /// pastas and for URL pastas from before links had settings.
+    #[serde(default)]
+    pub link: Option<Link>,
+}
+
+fn first_version() -> u64 {
+    1
 }
 
 impl Pasta {
@@ -84,70 +205,49 @@ impl Pasta {
         }
     }
 
-    pub fn has_file(&self) -> bool {
-        self.file.is_some()
+    /// The slug of a URL pasta if it has one, otherwise its id. Used in
+    /// redirect links.
+    pub fn link_id(&self) -> String {
+        match self.link.as_ref().and_then(|link| link.slug.as_ref()) {
+            Some(slug) => slug.to_owned(),
+            None => self.id_as_animals(),
+        }
     }
 
-    pub fn total_size_as_string(&self) -> String {
-        let total_size_bytes = if self.has_file() {
-            self.file.as_ref().unwrap().size.as_u64() as usize + self.content.as_bytes().len()
-        } else {
-            self.content.as_bytes().len()
+    /// Clicks per day over the last weeks as (date, count, percentage of the
+    /// busiest day), oldest first. Empty for pastas that aren't links.
+    pub fn click_history(&self) -> Vec<(String, u64, u64)> {
+        let Some(link) = self.link.as_ref() else {
+            return Vec::new();
         };
-
-        if total_size_bytes < 1024 {
-            format!("{} B", total_size_bytes)
-        } else if total_size_bytes < 1024 * 1024 {
-            format!("{} KB", total_size_bytes / 1024)
-        } else if total_size_bytes < 1024 * 1024 * 1024 {
-            format!("{} MB", total_size_bytes / (1024 * 1024))
-        } else {
-            format!("{} GB", total_size_bytes / (1024 * 1024 * 1024))
-        }
+        let today = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |n| n.as_secs() as i64)
+            / 86400;
+        daily_clicks(&link.clicks, today)
     }
 
-    pub fn file_embeddable(&self) -> bool {
-        return self.has_file()
-            && self.file.as_ref().unwrap().embeddable()
-            && !(self.encrypt_server || self.encrypt_client);
+    /// Clicks over the whole lifetime of a link.
+    pub fn total_clicks(&self) -> u64 {
+        self.link
+            .as_ref()
+            .map_or(0, |link| link.clicks.iter().map(|day| day.count).sum())
     }
 
-    pub fn created_as_string(&self) -> String {
-        Local.timestamp_opt(self.created, 0).map(|date| {
-            format!(
-                "{:02}-{:02} {:02}:{:02}",
-                date.month(),
-                date.day(),
-                date.hour(),
-                date.minute(),
-            )
-        }).earliest().unwrap_or_else(|| {
-            log::error!("Failed to process created date");
-            String::from("Unknow")
-        })
-    }
+    /// Replaces the content and records the new version in the revision
+    /// history. On the first edit the original content is kept as revision 1.
+    pub fn set_content(&mut self, content: String, editor: &str) {
+        if content == self.content {
+            return;
+        }
 
-    pub fn expiration_as_string(&self) -> String {
-        if self.expiration == 0 {
-            String::from("Never")
-        } else {
-            Local.timestamp_opt(self.expiration, 0).map(|date| {
-                format!(
-                    "{:02}-{:02} {:02}:{:02}",
-                    date.month(),
-                    date.day(),
-                    date.hour(),
-                    date.minute(),
-                )
-            }).earliest().unwrap_or_else(|| {
-                log::error!("Failed to process expiration");
-                String::from("Never")
-            })
+        if ARGS.max_revisions == 0 {
+            self.content = content;
+            self.revisions.clear();
+            self.version += 1;
+            return;
         }
-    }
 
-    pub fn last_read_time_ago_as_string(&self) -> String {
-        // get current unix time in seconds
         let timenow: i64 = match SystemTime::now().duration_since(UNIX_EPOCH) {
             Ok(n) => n.as_secs(),
             Err(_) => {
@@ -156,70 +256,144 @@ impl Pasta {
             }
         } as i64;
 
-        // get seconds since last read and convert it to days
-        let days = ((timenow - self.last_read) / 86400) as u16;
-        if days > 1 {
-            return format!("{} days ago", days);
-        };
+        if self.revisions.is_empty() {
+            self.revisions.push(PastaRevision {
+                number: self.version,
+                created: self.created,
+                editor: String::from(""),
+                content: self.content.to_owned(),
+            });
+        }
 
-        // it's less than 1 day, let's do hours then
-        let hours = ((timenow - self.last_read) / 3600) as u16;
-        if hours > 1 {
-            return format!("{} hours ago", hours);
-        };
+        self.version = self.version.max(self.revisions.last().map_or(0, |r| r.number)) + 1;
+        self.revisions.push(PastaRevision {
+            number: self.version,
+            created: timenow,
+            editor: editor.to_string(),
+            content: content.to_owned(),
+        });
+        self.content = content;
+
+        if self.revisions.len() > ARGS.max_revisions {
+            let excess = self.revisions.len() - ARGS.max_revisions;
+            self.revisions.drain(..excess);
+        }
+    }
 
-        // it's less than 1 hour, let's do minutes then
-        let minutes = ((timenow - self.last_read) / 60) as u16;
-        if minutes > 1 {
-            return format!("{} minutes ago", minutes);
-        };
+    /// The revision history, or just the original content as revision 1 if
+    /// the pasta was never edited.
+    pub fn revision_list(&self) -> Vec<PastaRevision> {
+        if self.revisions.is_empty() {
+            vec![PastaRevision {
+                number: self.version,
+                created: self.created,
+                editor: String::from(""),
+                content: self.content.to_owned(),
+            }]
+        } else {
+            self.revisions.to_vec()
+        }
+    }
 
-        // it's less than 1 minute, let's do seconds then
-        let seconds = (timenow - self.last_read) as u16;
-        if seconds > 1 {
-            return format!("{} seconds ago", seconds);
-        };
+    /// Entity tag for the current content.
+    pub fn etag(&self) -> String {
+        format!("\"{}\"", self.version)
+    }
 
-        // it's less than 1 second?????
-        String::from("just now")
+    /// Whether an editor loaded at `version` is out of date. Forms from
+    /// before versions were tracked send none and never conflict.
+    pub fn changed_since(&self, version: Option<u64>) -> bool {
+        version.is_some_and(|version| version != self.version)
     }
 
-    pub fn short_last_read_time_ago_as_string(&self) -> String {
-        // get current unix time in seconds
-        let timenow: i64 = match SystemTime::now().duration_since(UNIX_EPOCH) {
-            Ok(n) => n.as_secs(),
-            Err(_) => {
-                log::error!("SystemTime before UNIX EPOCH!");
-                0
+    /// Whether an `If-Match` header value lets a write go through.
+    pub fn etag_matches(&self, if_match: &str) -> bool {
+        let etag = self.etag();
+        if_match
+            .split(',')
+            .map(|tag| tag.trim())
+            .any(|tag| tag == "*" || tag == etag)
+    }
+
+    /// Checks an edit password against the encrypted key of read-only and
+    /// private pastas, which holds the id encrypted with the password.
+    /// Private pastas from before they had one fall back to decrypting
+    /// their stored revisions, and match nothing if those are all empty.
+    pub fn edit_password_matches(&self, password: &str) -> bool {
+        if self.encrypt_client {
+            return false;
+        }
+        if !self.readonly && !self.encrypt_server {
+            return true;
+        }
+        match self.encrypted_key.as_deref() {
+            Some(key) if !key.is_empty() => {
+                decrypt(key, password).is_ok_and(|id| id == self.id.to_string())
             }
-        } as i64;
+            _ if self.readonly => false,
+            _ => {
+                let revisions = self.revision_list();
+                let encrypted: Vec<&str> = revisions
+                    .iter()
+                    .map(|r| r.content.as_str())
+                    .filter(|content| !content.is_empty())
+                    .collect();
+                !encrypted.is_empty()
+                    && encrypted
+                        .iter()
+                        .all(|content| decrypt(content, password).is_ok())
+            }
+        }
+    }
 
-        // get seconds since last read and convert it to days
-        let days = ((timenow - self.last_read) / 86400) as u16;
-        if days > 1 {
-            return format!("{} d ago", days);
-        };
+    /// The privacy option this pasta corresponds to in the upload form.
+    pub fn privacy(&self) -> &'static str {
+        if self.encrypt_client {
+            "secret"
+        } else if self.readonly {
+            "readonly"
+        } else if self.encrypt_server {
+            "private"
+        } else if self.private {
+            "unlisted"
+        } else {
+            "public"
+        }
+    }
 
-        // it's less than 1 day, let's do hours then
-        let hours = ((timenow - self.last_read) / 3600) as u16;
-        if hours > 1 {
-            return format!("{} h ago", hours);
-        };
+    /// Whether the pasta is deleted after a number of reads. Anything that
+    /// shows its content has to count as one of those, or stay out of reach.
+    pub fn burns_after_reading(&self) -> bool {
+        self.burn_after_reads > 0
+    }
 
-        // it's less than 1 hour, let's do minutes then
-        let minutes = ((timenow - self.last_read) / 60) as u16;
-        if minutes > 1 {
-            return format!("{} m ago", minutes);
-        };
+    pub fn tags_as_string(&self) -> String {
+        self.tags.join(", ")
+    }
 
-        // it's less than 1 minute, let's do seconds then
-        let seconds = (timenow - self.last_read) as u16;
-        if seconds > 1 {
-            return format!("{} s ago", seconds);
-        };
+    pub fn parent_as_animals(&self) -> Option<String> {
+        self.parent.map(|id| {
+            if ARGS.hash_ids {
+                to_hashids(id)
+            } else {
+                to_animal_names(id)
+            }
+        })
+    }
+
+    pub fn has_file(&self) -> bool {
+        self.file.is_some()
+    }
 
-        // it's less than 1 second?????
-        String::from("just now")
+    /// Size of the content plus the attachment, in bytes.
+    pub fn total_size(&self) -> u64 {
+        self.file.as_ref().map_or(0, |f| f.size.as_u64()) + self.content.len() as u64
+    }
+
+    pub fn file_embeddable(&self) -> bool {
+        return self.has_file()
+            && self.file.as_ref().unwrap().embeddable()
+            && !(self.encrypt_server || self.encrypt_client);
     }
 
     pub fn last_read_days_ago(&self) -> u16 {
@@ -236,12 +410,16 @@ impl Pasta {
         ((timenow - self.last_read) / 86400) as u16
     }
 
-    pub fn content_syntax_highlighted(&self) -> String {
-        html_highlight(&self.content, &self.extension)
-    }
-
-    pub fn content_not_highlighted(&self) -> String {
-        html_highlight(&self.content, "txt")
+    /// Replaces the `auto` syntax with the detected one, if there is one.
+    /// Takes the content before it is encrypted. Uploads encrypted in the
+    /// browser stay `auto` and are highlighted there.
+    pub fn detect_extension(&mut self, plain_content: &str) {
+        if self.extension != "auto" || self.encrypt_client {
+            return;
+        }
+        if let Some(extension) = detect_syntax(plain_content, self.file.as_ref().map(|f| f.name())) {
+            self.extension = extension;
+        }
     }
 
     pub fn content_escaped(&self) -> String {
@@ -255,6 +433,24 @@ impl Pasta {
         .to_string()
     }
 
+    /// Each tag as (HTML safe label, query string value), for linking to the
+    /// list filtered by that tag.
+    pub fn tag_links(&self) -> Vec<(String, String)> {
+        self.tags
+            .iter()
+            .map(|tag| {
+                (
+                    html_escape::encode_text(tag).to_string(),
+                    utf8_percent_encode(tag, NON_ALPHANUMERIC).to_string(),
+                )
+            })
+            .collect()
+    }
+
+    pub fn tags_attribute_safe(&self) -> String {
+        html_escape::encode_double_quoted_attribute(&self.tags_as_string()).to_string()
+    }
+
     pub fn content_textarea_safe(&self) -> String {
         html_escape::encode_text(&self.content).to_string()
     }
@@ -265,3 +461,98 @@ impl fmt::Display for Pasta {
         write!(f, "{}", self.content)
     }
 }
+
+/// A public text pasta created just now, for tests to adjust.
+#[cfg(test)]
+pub fn test_pasta(id: u64) -> Pasta {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |n| n.as_secs() as i64);
+    Pasta {
+        id,
+        title: String::from(""),
+        content: String::from(""),
+        file: None,
+        extension: String::from(""),
+        private: false,
+        readonly: false,
+        editable: true,
+        encrypt_server: false,
+        encrypt_client: false,
+        encrypted_key: Some(String::from("")),
+        created: now,
+        expiration: 0,
+        last_read: now,
+        read_count: 0,
+        burn_after_reads: 0,
+        pasta_type: String::from("text"),
+        revisions: Vec::new(),
+        version: 1,
+        parent: None,
+        tags: Vec::new(),
+        access_log: None,
+        link: None,
+    }
+}
+
+#[test]
+fn test_edit_password_matches() {
+    use crate::util::misc::encrypt;
+
+    let mut pasta = test_pasta(7);
+    assert!(pasta.edit_password_matches(""));
+
+    pasta.private = true;
+    pasta.readonly = true;
+    pasta.encrypted_key = Some(encrypt("7", "secret"));
+    assert!(pasta.edit_password_matches("secret"));
+    assert!(!pasta.edit_password_matches("wrong"));
+
+    // a private pasta holding nothing but an encrypted attachment
+    pasta.readonly = false;
+    pasta.encrypt_server = true;
+    assert!(pasta.edit_password_matches("secret"));
+    assert!(!pasta.edit_password_matches("wrong"));
+    assert!(!pasta.edit_password_matches(""));
+
+    // from before private pastas had a key, with no content to check against
+    pasta.encrypted_key = Some(String::from(""));
+    assert!(!pasta.edit_password_matches("secret"));
+    pasta.content = encrypt("hello", "secret");
+    assert!(pasta.edit_password_matches("secret"));
+    assert!(!pasta.edit_password_matches("wrong"));
+}
+
+#[test]
+fn test_version_conflicts_and_etag() {
+    let mut pasta = test_pasta(7);
+    pasta.version = 3;
+    assert_eq!(pasta.etag(), "\"3\"");
+
+    assert!(!pasta.changed_since(None));
+    assert!(!pasta.changed_since(Some(3)));
+    assert!(pasta.changed_since(Some(2)));
+
+    assert!(pasta.etag_matches("\"3\""));
+    assert!(pasta.etag_matches("\"1\", \"3\""));
+    assert!(pasta.etag_matches("*"));
+    assert!(!pasta.etag_matches("\"2\""));
+    assert!(!pasta.etag_matches("3"));
+}
+
+#[test]
+fn test_attachment_content_type() {
+    let file = |name: &str, mime: &str| PastaFile {
+        name: name.to_string(),
+        size: ByteSize::b(0),
+        mime: mime.to_string(),
+    };
+
+    // the sniffed type wins over the file name
+    assert_eq!(file("cat.txt", "image/png").content_type(), "image/png");
+    assert_eq!(file("page.png", "text/html").content_type(), "application/octet-stream");
+    assert_eq!(file("page.html", "").content_type(), "application/octet-stream");
+    assert_eq!(file("script.svg", "").content_type(), "application/octet-stream");
+    // attachments from before sniffing fall back to the extension
+    assert_eq!(file("cat.jpg", "").content_type(), "image/jpeg");
+}
@@ -1,15 +1,165 @@
-use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-#[derive(Debug, Clone, Serialize)]
+use actix_web::http::header;
+use actix_web::HttpRequest;
+use chrono::{DateTime, FixedOffset, Local};
+use once_cell::sync::Lazy;
+use rust_embed::RustEmbed;
+use serde::{Deserialize, Serialize};
+
+use crate::args::ARGS;
+use crate::util::catalog::Catalog;
+use crate::util::expiration::parse_duration;
+
+/// Catalog every other one falls back to for messages it doesn't have.
+const FALLBACK: &str = "en";
+
+/// The built-in catalogs, one `<code>.ftl` per language.
+#[derive(RustEmbed)]
+#[folder = "translations/"]
+struct BuiltinCatalogs;
+
+/// The built-in catalogs with the ones from `{data_dir}/translations/` on
+/// top. Those replace single messages of a built-in language or add new
+/// languages.
+static CATALOGS: Lazy<BTreeMap<String, Catalog>> = Lazy::new(|| {
+    let mut catalogs = builtin_catalogs();
+
+    let dir = format!("{}/translations", ARGS.data_dir);
+    for entry in fs::read_dir(&dir).into_iter().flatten().flatten() {
+        let path = entry.path();
+        let Some(lang) = catalog_language(&path.file_name().unwrap_or_default().to_string_lossy())
+        else {
+            continue;
+        };
+        match fs::read_to_string(&path).map_err(|e| e.to_string()).and_then(|s| Catalog::parse(&s)) {
+            Ok(catalog) => {
+                log::info!("Loaded translations for '{}' from {}", lang, path.display());
+                catalogs.entry(lang).or_default().merge(catalog);
+            }
+            Err(error) => log::error!("Skipping {}: {}", path.display(), error),
+        }
+    }
+
+    catalogs
+});
+
+static TRANSLATIONS: Lazy<HashMap<String, Translation>> = Lazy::new(|| build_translations(&CATALOGS));
+
+/// The language code of a catalog file name, `de.ftl` or `pt-BR.ftl`.
+fn catalog_language(file_name: &str) -> Option<String> {
+    let lang = file_name.strip_suffix(".ftl")?;
+    if lang.is_empty() || !lang.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return None;
+    }
+    Some(lang.to_lowercase())
+}
+
+fn builtin_catalogs() -> BTreeMap<String, Catalog> {
+    let mut catalogs = BTreeMap::new();
+    for file_name in BuiltinCatalogs::iter() {
+        let Some(lang) = catalog_language(&file_name) else {
+            continue;
+        };
+        let file = BuiltinCatalogs::get(&file_name).unwrap();
+        match Catalog::parse(&String::from_utf8_lossy(&file.data)) {
+            Ok(catalog) => {
+                catalogs.insert(lang, catalog);
+            }
+            Err(error) => log::error!("Skipping built-in translations {}: {}", file_name, error),
+        }
+    }
+    catalogs
+}
+
+/// A `Translation` for every catalog that, together with the fallback, has
+/// all the messages.
+fn build_translations(catalogs: &BTreeMap<String, Catalog>) -> HashMap<String, Translation> {
+    let mut translations = HashMap::new();
+
+    for lang in catalogs.keys() {
+        let mut messages = serde_json::Map::new();
+        for code in [FALLBACK, lang.as_str()] {
+            let Some(catalog) = catalogs.get(code) else {
+                continue;
+            };
+            for key in catalog.keys() {
+                let message = catalog.format(key, code, &[]).unwrap_or_default();
+                messages.insert(key.clone(), serde_json::Value::String(message));
+            }
+        }
+
+        match serde_json::from_value::<Translation>(serde_json::Value::Object(messages)) {
+            Ok(mut translation) => {
+                translation.lang = lang.clone();
+                translations.insert(lang.clone(), translation);
+            }
+            Err(error) => log::error!("Translations for '{}' are incomplete: {}", lang, error),
+        }
+    }
+
+    let mut languages: Vec<(String, String)> = translations
+        .values()
+        .map(|t| (t.lang.clone(), t.language_name.clone()))
+        .collect();
+    languages.sort();
+    for translation in translations.values_mut() {
+        translation.languages = languages.clone();
+    }
+
+    translations
+}
+
+/// The messages of one language, read from its catalog with English filling
+/// the gaps. Fields are looked up by name, so every field needs a message of
+/// the same key in `translations/en.ftl`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Translation {
+    /// Name of the language in itself, for the language menu.
+    pub language_name: String,
     // Header & Footer
     pub title: String,
     pub upload_new: String,
     pub list: String,
+    pub tags_label: String,
+    pub tags_placeholder: String,
+    pub access_log_enable: String,
+    pub view_access_log: String,
+    pub access_log_title: String,
+    pub access_log_password_hint: String,
+    pub access_log_show: String,
+    pub access_log_empty: String,
+    pub access_log_route: String,
+    pub access_log_client: String,
+    pub access_log_user_agent: String,
+    pub access_log_retention: String,
+    pub collections: String,
+    pub collections_title: String,
+    pub collection_new: String,
+    pub collection_name: String,
+    pub collection_description: String,
+    pub collection_members: String,
+    pub collection_members_hint: String,
+    pub collection_password: String,
+    pub collection_create: String,
+    pub collection_saved: String,
+    pub collection_empty: String,
+    pub collection_open_separately: String,
+    pub collection_edit: String,
+    pub collection_delete: String,
+    pub search: String,
+    pub search_title: String,
+    pub search_placeholder: String,
+    pub search_button: String,
+    pub search_no_results: String,
+    pub search_as_admin: String,
+    pub search_admin_username: String,
+    pub search_admin_active: String,
     pub admin: String,
     pub guide: String,
-    pub current_lang: String,
-    pub switch_lang: String,
     pub footer_text: String,
     
     // Table Headers
@@ -31,23 +181,67 @@ pub struct Translation {
     // Edit Page
     pub edit_title: String,
     pub edit_password_prompt: String,
+    pub edit_editor_label: String,
+    pub edit_settings: String,
+    pub edit_settings_hint: String,
+    pub edit_unchanged: String,
+    pub edit_new_password: String,
+    pub edit_replace_file: String,
+    pub edit_remove_file: String,
+    pub edit_admin_password: String,
+
+    // History Page
+    pub history_title: String,
+    pub history_revision: String,
+    pub history_editor: String,
+    pub history_anonymous: String,
+    pub history_current: String,
+    pub history_old: String,
+    pub history_new: String,
+    pub history_unified: String,
+    pub history_side_by_side: String,
+    pub history_compare: String,
+    pub history_no_changes: String,
+    pub history_restore: String,
+    pub history_restored: String,
+
+    // Edit Conflict Page
+    pub conflict_title: String,
+    pub conflict_explanation: String,
+    pub conflict_changes: String,
+    pub conflict_theirs: String,
+    pub conflict_yours: String,
     
     // Error Page
     pub error_404: String,
     pub go_home: String,
     
+    // Link Preview Page
+    pub link_preview_title: String,
+    pub link_preview_intro: String,
+    pub link_preview_continue: String,
+    pub link_preview_details: String,
+    
     // View Page
     pub view_copy_text: String,
     pub view_copy_redirect: String,
     pub view_raw: String,
     pub view_qr: String,
     pub view_edit: String,
+    pub view_history: String,
+    pub view_fork: String,
+    pub view_forked_from: String,
     pub view_remove: String,
     pub view_copy_url: String,
+    pub view_theme_auto: String,
+    pub view_rendered: String,
+    pub view_source: String,
+    pub view_split: String,
+    pub view_inline: String,
+    pub view_patch: String,
     pub view_decrypt_prompt: String,
     pub view_decrypt_button: String,
     pub view_download: String,
-    pub view_read: String,
     pub view_last: String,
     pub view_copied: String,
     
@@ -57,8 +251,23 @@ pub struct Translation {
     pub list_remove: String,
     pub list_text: String,
     pub list_redirect: String,
+    pub list_diff: String,
     pub list_empty: String,
     pub list_empty_link: String,
+    pub list_sort: String,
+    pub list_descending: String,
+    pub list_ascending: String,
+    pub list_any_type: String,
+    pub list_any_file: String,
+    pub list_with_file: String,
+    pub list_without_file: String,
+    pub list_any_privacy: String,
+    pub list_per_page: String,
+    pub list_apply: String,
+    pub list_feeds: String,
+    pub list_previous: String,
+    pub list_next: String,
+    pub list_page: String,
     
     // Remove/Delete Confirmation
     pub remove_confirm_prompt: String,
@@ -72,238 +281,308 @@ pub remove_confirm_word: String,
     pub burn_after: String,
     pub syntax_highlight: String,
     pub privacy: String,
+    pub pasta_type: String,
     pub password: String,
     pub title_label: String,
     pub title_placeholder: String,
     pub content_label: String,
     pub content_placeholder: String,
+    pub diff_new_label: String,
+    pub diff_new_placeholder: String,
+    pub link_slug_label: String,
+    pub link_slug_placeholder: String,
+    pub redirect_status: String,
+    pub redirect_302: String,
+    pub redirect_301: String,
+    pub redirect_307: String,
+    pub link_preview_enable: String,
     pub select_file: String,
     pub save_button: String,
     pub uploader_password_placeholder: String,
     pub incorrect_password: String,
+    pub fork_from: String,
+    pub fork_attachment: String,
     
     // Dropdowns
-    pub expire_1min: String,
-    pub expire_10min: String,
-    pub expire_1hour: String,
-    pub expire_24hour: String,
-    pub expire_3days: String,
-    pub expire_1week: String,
     pub expire_never: String,
-    
+    pub expire_custom_placeholder: String,
     pub burn_no_limit: String,
-    pub burn_1: String,
-    pub burn_10: String,
-    pub burn_100: String,
-    pub burn_1000: String,
-    pub burn_10000: String,
+    pub burn_custom_placeholder: String,
     
     pub privacy_public: String,
     pub privacy_unlisted: String,
     pub privacy_readonly: String,
     pub privacy_private: String,
     pub privacy_secret: String,
+    pub type_text: String,
+    pub type_diff: String,
+    pub type_compare: String,
+    /// Code of the catalog, e.g. `en`.
+    #[serde(skip)]
+    pub lang: String,
+    /// Code and name of every language there is a catalog for.
+    #[serde(skip)]
+    pub languages: Vec<(String, String)>,
+    /// The viewer's offset from UTC in minutes, from the `tz` cookie.
+    #[serde(skip)]
+    pub utc_offset: Option<i32>,
 }
 
 impl Translation {
-    pub fn en() -> Self {
-        Self {
-            title: "MicroBin".to_string(),
-            upload_new: "Upload New".to_string(),
-            list: "List".to_string(),
-            admin: "Admin".to_string(),
-            guide: "Guide".to_string(),
-            current_lang: "EN".to_string(),
-            switch_lang: "中文".to_string(),
-            footer_text: "Powered by MicroBin".to_string(),
-            
-            table_file: "Filename".to_string(),
-            table_date: "Date".to_string(),
-            table_size: "Size".to_string(),
-            table_hits: "Hits".to_string(),
-            table_expires: "Expires".to_string(),
-            table_ip: "IP".to_string(),
-            table_actions: "Actions".to_string(),
-            table_key: "Key".to_string(),
-            table_title: "Title".to_string(),
-            table_content: "Content".to_string(),
-            table_valid: "Valid".to_string(),
-            table_encryption: "Encryption".to_string(),
-            table_privacy: "Priv.".to_string(),
-            table_editable: "Edit.".to_string(),
-            
-            edit_title: "Editing upload".to_string(),
-            edit_password_prompt: "Re-enter Password".to_string(),
-            
-            error_404: "404 not found :(".to_string(),
-            go_home: "Go Home".to_string(),
-            
-            view_copy_text: "Copy Text".to_string(),
-            view_copy_redirect: "Copy Redirect".to_string(),
-            view_raw: "Raw Text".to_string(),
-            view_qr: "QR".to_string(),
-            view_edit: "Edit".to_string(),
-            view_remove: "Remove".to_string(),
-            view_copy_url: "Copy URL".to_string(),
-            view_decrypt_prompt: "Please enter your key to decrypt this upload.".to_string(),
-            view_decrypt_button: "Decrypt text".to_string(),
-            view_download: "Download".to_string(),
-            view_read: "Read".to_string(),
-            view_last: "last".to_string(),
-            view_copied: "Copied".to_string(),
-            
-            list_copy: "Copy".to_string(),
-            list_edit: "Edit".to_string(),
-            list_remove: "Remove".to_string(),
-            list_text: "Text".to_string(),
-            list_redirect: "Redirect".to_string(),
-            list_empty: "No uploads yet. 😔 Create one".to_string(),
-            list_empty_link: "here".to_string(),
-            
-            remove_confirm_prompt: "Please type \"confirm\" to delete this upload.".to_string(),
-            remove_confirm_placeholder: "Type \"confirm\"".to_string(),
-            remove_confirm_button: "Delete".to_string(),
-            remove_confirm_word: "confirm".to_string(),
-            remove_incorrect: "Incorrect. Please type \"confirm\" to delete.".to_string(),
-            
-            expiration: "Expiration".to_string(),
-            burn_after: "Burn After".to_string(),
-            syntax_highlight: "Syntax".to_string(),
-            privacy: "Privacy".to_string(),
-            password: "Password".to_string(),
-            title_label: "Title".to_string(),
-            title_placeholder: "Enter a title for easy reference...".to_string(),
-            content_label: "Content".to_string(),
-            content_placeholder: "Type something here.".to_string(),
-            select_file: "Select or drop file attachment".to_string(),
-            save_button: "Save".to_string(),
-            uploader_password_placeholder: "Uploader Password".to_string(),
-            incorrect_password: "Incorrect password!".to_string(),
-            
-            expire_1min: "1 minute".to_string(),
-            expire_10min: "10 minutes".to_string(),
-            expire_1hour: "1 hour".to_string(),
-            expire_24hour: "24 hours".to_string(),
-            expire_3days: "3 days".to_string(),
-            expire_1week: "1 week".to_string(),
-            expire_never: "Never Expire".to_string(),
-            
-            burn_no_limit: "No Limit".to_string(),
-            burn_1: "First Read".to_string(),
-            burn_10: "10th Read".to_string(),
-            burn_100: "100th Read".to_string(),
-            burn_1000: "1000th Read".to_string(),
-            burn_10000: "10000th Read".to_string(),
-            
-            privacy_public: "Public".to_string(),
-            privacy_unlisted: "Unlisted".to_string(),
-            privacy_readonly: "Read-only".to_string(),
-            privacy_private: "Private".to_string(),
-            privacy_secret: "Secret".to_string(),
+    /// A catalog message with its placeables filled in, for the messages that
+    /// aren't fields because they need arguments.
+    fn message(&self, key: &str, args: &[(&str, u64)]) -> String {
+        [self.lang.as_str(), FALLBACK]
+            .iter()
+            .find_map(|lang| CATALOGS.get(*lang)?.format(key, lang, args))
+            .unwrap_or_else(|| key.to_string())
+    }
+
+    /// A message that depends on a number, like `view_read_count`, with
+    /// `$count` filled in and the variant chosen by the plural rules of the
+    /// language. Takes the count by reference as that is how templates pass
+    /// arguments.
+    pub fn plural(&self, key: &str, count: &u64) -> String {
+        self.message(key, &[("count", *count)])
+    }
+
+    /// The time in the viewer's timezone if they sent one, else the server's.
+    fn local_time(&self, timestamp: i64) -> Option<DateTime<FixedOffset>> {
+        let time = DateTime::from_timestamp(timestamp, 0)?;
+        let offset = self
+            .utc_offset
+            .and_then(|minutes| FixedOffset::east_opt(minutes * 60))
+            .unwrap_or_else(|| *time.with_timezone(&Local).offset());
+        Some(time.with_timezone(&offset))
+    }
+
+    /// Formats with the strftime pattern of the catalog, falling back to the
+    /// English one if a translator's pattern is invalid.
+    fn format_time(&self, timestamp: i64, key: &str) -> String {
+        let Some(time) = self.local_time(timestamp) else {
+            return self.message("date_unknown", &[]);
+        };
+        let mut result = String::new();
+        if write!(result, "{}", time.format(&self.message(key, &[]))).is_err() {
+            result.clear();
+            write!(result, "{}", time.format("%Y-%m-%d %H:%M:%S")).unwrap_or_default();
         }
+        result
+    }
+
+    /// Month, day and time, for tables.
+    pub fn date(&self, timestamp: &i64) -> String {
+        self.format_time(*timestamp, "date_format")
     }
 
-    pub fn zh() -> Self {
-        Self {
-            title: "MicroBin".to_string(),
-            upload_new: "新建上传".to_string(),
-            list: "列表".to_string(),
-            admin: "管理".to_string(),
-            guide: "指南".to_string(),
-            current_lang: "中文".to_string(),
-            switch_lang: "English".to_string(),
-            footer_text: "由 MicroBin 驱动".to_string(),
-            
-            table_file: "文件名".to_string(),
-            table_date: "日期".to_string(),
-            table_size: "大小".to_string(),
-            table_hits: "点击".to_string(),
-            table_expires: "过期".to_string(),
-            table_ip: "IP".to_string(),
-            table_actions: "操作".to_string(),
-            table_key: "键值".to_string(),
-            table_title: "标题".to_string(),
-            table_content: "内容".to_string(),
-            table_valid: "有效".to_string(),
-            table_encryption: "加密".to_string(),
-            table_privacy: "隐私".to_string(),
-            table_editable: "编辑".to_string(),
-            
-            edit_title: "正在编辑".to_string(),
-            edit_password_prompt: "重新输入密码".to_string(),
-            
-            error_404: "未找到页面".to_string(),
-            go_home: "返回首页".to_string(),
-            
-            view_copy_text: "复制文本".to_string(),
-            view_copy_redirect: "复制跳转".to_string(),
-            view_raw: "原始内容".to_string(),
-            view_qr: "二维码".to_string(),
-            view_edit: "编辑".to_string(),
-            view_remove: "删除".to_string(),
-            view_copy_url: "复制链接".to_string(),
-            view_decrypt_prompt: "请输入密钥以解密内容。".to_string(),
-            view_decrypt_button: "解密文本".to_string(),
-            view_download: "下载".to_string(),
-            view_read: "阅读".to_string(),
-            view_last: "上次".to_string(),
-            view_copied: "已复制".to_string(),
-            
-            list_copy: "复制".to_string(),
-            list_edit: "编辑".to_string(),
-            list_remove: "删除".to_string(),
-            list_text: "文本".to_string(),
-            list_redirect: "跳转".to_string(),
-            list_empty: "还没有上传内容。😔 在这里创建一个".to_string(),
-            list_empty_link: "点击这里".to_string(),
-            
-            remove_confirm_prompt: "请输入\"确认\"以删除此上传。".to_string(),
-            remove_confirm_placeholder: "输入\"确认\"".to_string(),
-            remove_confirm_button: "删除".to_string(),
-            remove_confirm_word: "确认".to_string(),
-            remove_incorrect: "输入错误。请输入\"确认\"以删除。".to_string(),
-            
-            expiration: "过期时间".to_string(),
-            burn_after: "阅后即焚".to_string(),
-            syntax_highlight: "语法高亮".to_string(),
-            privacy: "隐私设置".to_string(),
-            password: "密码保护".to_string(),
-            title_label: "标题".to_string(),
-            title_placeholder: "输入标题以便查找...".to_string(),
-            content_label: "已发布内容".to_string(),
-            content_placeholder: "在此输入内容...".to_string(),
-            select_file: "选择或拖放文件附件".to_string(),
-            save_button: "保存".to_string(),
-            uploader_password_placeholder: "上传者密码".to_string(),
-            incorrect_password: "密码错误!".to_string(),
-            
-            expire_1min: "1 分钟".to_string(),
-            expire_10min: "10 分钟".to_string(),
-            expire_1hour: "1 小时".to_string(),
-            expire_24hour: "24 小时".to_string(),
-            expire_3days: "3 天".to_string(),
-            expire_1week: "1 周".to_string(),
-            expire_never: "永不过期".to_string(),
-            
-            burn_no_limit: "无限制".to_string(),
-            burn_1: "阅读 1 次后".to_string(),
-            burn_10: "阅读 10 次后".to_string(),
-            burn_100: "阅读 100 次后".to_string(),
-            burn_1000: "阅读 1000 次后".to_string(),
-            burn_10000: "阅读 10000 次后".to_string(),
-            
-            privacy_public: "公开".to_string(),
-            privacy_unlisted: "未列出 (Unlisted)".to_string(),
-            privacy_readonly: "只读".to_string(),
-            privacy_private: "私有 (Private)".to_string(),
-            privacy_secret: "加密 (Secret)".to_string(),
+    pub fn datetime(&self, timestamp: &i64) -> String {
+        self.format_time(*timestamp, "datetime_format")
+    }
+
+    /// RFC 3339 in the viewer's timezone, for `datetime` and hover titles.
+    pub fn iso_datetime(&self, timestamp: &i64) -> String {
+        self.local_time(*timestamp)
+            .map(|time| time.to_rfc3339())
+            .unwrap_or_default()
+    }
+
+    /// Like `date`, with 0 meaning the pasta never expires.
+    pub fn expiration(&self, timestamp: &i64) -> String {
+        if *timestamp == 0 {
+            self.message("date_never", &[])
+        } else {
+            self.date(timestamp)
         }
     }
+
+    /// How long ago `timestamp` was, e.g. "3 days ago".
+    pub fn time_ago(&self, timestamp: &i64) -> String {
+        match time_ago_unit(now() - timestamp) {
+            Some((key, count)) => self.message(key, &[("count", count)]),
+            None => self.message("time_just_now", &[]),
+        }
+    }
+
+    /// Like `time_ago`, abbreviated for narrow table columns.
+    pub fn time_ago_short(&self, timestamp: &i64) -> String {
+        match time_ago_unit(now() - timestamp) {
+            Some((key, count)) => self.message(&format!("{}_short", key), &[("count", count)]),
+            None => self.message("time_just_now", &[]),
+        }
+    }
+
+    /// The label of an expiry preset of the upload form.
+    pub fn expiry_label(&self, preset: &str) -> String {
+        if preset == "never" {
+            return self.expire_never.clone();
+        }
+        match parse_duration(preset) {
+            Some(seconds) => self.duration(seconds),
+            None => preset.to_string(),
+        }
+    }
+
+    /// A lifetime in the largest unit it is a whole number of, e.g. "3 days".
+    fn duration(&self, seconds: i64) -> String {
+        let (key, unit) = [
+            ("duration_weeks", 604800),
+            ("duration_days", 86400),
+            ("duration_hours", 3600),
+            ("duration_minutes", 60),
+            ("duration_seconds", 1),
+        ]
+        .into_iter()
+        .find(|(_, unit)| seconds % unit == 0)
+        .unwrap_or(("duration_seconds", 1));
+        self.message(key, &[("count", (seconds / unit) as u64)])
+    }
+
+    /// A size in bytes with the largest binary unit that fits.
+    pub fn size(&self, bytes: &u64) -> String {
+        let units = ["size_unit_b", "size_unit_kb", "size_unit_mb", "size_unit_gb"]
+            .map(|key| self.message(key, &[]));
+        format_size(*bytes, &self.message("decimal_separator", &[]), &units)
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |n| n.as_secs() as i64)
+}
+
+/// The message and count for something `seconds` ago, using the largest unit
+/// with a count above one, or `None` for just now.
+fn time_ago_unit(seconds: i64) -> Option<(&'static str, u64)> {
+    [
+        ("time_days_ago", 86400),
+        ("time_hours_ago", 3600),
+        ("time_minutes_ago", 60),
+        ("time_seconds_ago", 1),
+    ]
+    .into_iter()
+    .map(|(key, unit)| (key, (seconds / unit).max(0) as u64))
+    .find(|(_, count)| *count > 1)
+}
+
+/// Sizes under ten units get one decimal, so 1536 bytes are 1.5 KB.
+fn format_size(bytes: u64, decimal_separator: &str, units: &[String; 4]) -> String {
+    let mut unit = 0;
+    let mut divisor = 1;
+    while unit < units.len() - 1 && bytes >= divisor * 1024 {
+        unit += 1;
+        divisor *= 1024;
+    }
+
+    let tenths = bytes * 10 / divisor;
+    if unit > 0 && tenths < 100 && tenths % 10 != 0 {
+        format!("{}{}{} {}", tenths / 10, decimal_separator, tenths % 10, units[unit])
+    } else {
+        format!("{} {}", bytes / divisor, units[unit])
+    }
+}
+
+pub fn is_available(lang: &str) -> bool {
+    TRANSLATIONS.contains_key(lang)
 }
 
 pub fn get_translation(lang: &str) -> Translation {
-    match lang {
-        "zh" => Translation::zh(),
-        _ => Translation::en(),
+    TRANSLATIONS
+        .get(lang)
+        .or_else(|| TRANSLATIONS.get(&ARGS.default_lang))
+        .or_else(|| TRANSLATIONS.get(FALLBACK))
+        .cloned()
+        .expect("The English translations are built in")
+}
+
+/// The language for a request: the one picked with the language menu, else
+/// the best match for the browser's `Accept-Language`, else the default.
+pub fn request_language(req: &HttpRequest) -> String {
+    if let Some(cookie) = req.cookie("lang") {
+        if is_available(cookie.value()) {
+            return cookie.value().to_string();
+        }
     }
+
+    req.headers()
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| negotiate(value, is_available))
+        .unwrap_or_else(|| ARGS.default_lang.clone())
+}
+
+pub fn request_translation(req: &HttpRequest) -> Translation {
+    let mut translation = get_translation(&request_language(req));
+    translation.utc_offset = req
+        .cookie("tz")
+        .and_then(|cookie| cookie.value().parse::<i32>().ok())
+        .filter(|minutes| minutes.abs() <= 14 * 60);
+    translation
+}
+
+/// The most preferred language of an `Accept-Language` header that is
+/// available, trying `zh` for `zh-CN` if there is no exact match.
+pub fn negotiate(accept_language: &str, available: impl Fn(&str) -> bool) -> Option<String> {
+    let mut ranges: Vec<(String, f32)> = accept_language
+        .split(',')
+        .filter_map(|range| {
+            let mut parts = range.split(';');
+            let tag = parts.next()?.trim().to_lowercase();
+            let quality = parts
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok())?;
+            (!tag.is_empty() && tag != "*" && quality > 0.0).then_some((tag, quality))
+        })
+        .collect();
+    // stable, so equally preferred languages keep the header's order
+    ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    ranges.iter().find_map(|(tag, _)| {
+        let primary = tag.split('-').next().unwrap_or(tag);
+        [tag.as_str(), primary]
+            .into_iter()
+            .find(|lang| available(lang))
+            .map(str::to_string)
+    })
+}
+
+#[test]
+fn test_builtin_catalogs_are_complete() {
+    let catalogs = builtin_catalogs();
+    let translations = build_translations(&catalogs);
+    assert!(translations.contains_key("en"));
+    assert!(translations.contains_key("zh"));
+
+    // the fallback hides gaps, so compare the keys directly
+    let english: Vec<&String> = catalogs["en"].keys().collect();
+    for (lang, catalog) in &catalogs {
+        for key in &english {
+            assert!(catalog.keys().any(|k| k == *key), "{} is missing {}", lang, key);
+        }
+    }
+}
+
+#[test]
+fn test_negotiate_accept_language() {
+    let available = |lang: &str| lang == "en" || lang == "zh";
+    assert_eq!(negotiate("zh-CN,zh;q=0.9,en;q=0.8", available).as_deref(), Some("zh"));
+    assert_eq!(negotiate("de-DE, en;q=0.5, zh;q=0.7", available).as_deref(), Some("zh"));
+    assert_eq!(negotiate("fr, *;q=0.1", available), None);
+    assert_eq!(negotiate("en;q=0, zh;q=0.1", available).as_deref(), Some("zh"));
+}
+
+#[test]
+fn test_time_ago_and_size() {
+    assert_eq!(time_ago_unit(1), None);
+    assert_eq!(time_ago_unit(-30), None);
+    assert_eq!(time_ago_unit(45), Some(("time_seconds_ago", 45)));
+    assert_eq!(time_ago_unit(3 * 3600 + 5), Some(("time_hours_ago", 3)));
+    assert_eq!(time_ago_unit(86400 + 3600), Some(("time_hours_ago", 25)));
+
+    let units = ["B", "KB", "MB", "GB"].map(String::from);
+    assert_eq!(format_size(512, ".", &units), "512 B");
+    assert_eq!(format_size(1536, ",", &units), "1,5 KB");
+    assert_eq!(format_size(2048, ".", &units), "2 KB");
+    assert_eq!(format_size(150 * 1024 * 1024, ".", &units), "150 MB");
+    assert_eq!(format_size(5 << 40, ".", &units), "5120 GB");
 }
@@ -0,0 +1,111 @@
+use std::net::{IpAddr, SocketAddr};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use actix_web::http::header;
+use actix_web::HttpRequest;
+
+use crate::args::ARGS;
+use crate::pasta::{Access, Pasta};
+
+/// Most entries kept per pasta, the oldest are dropped first.
+const MAX_ENTRIES: usize = 1000;
+const MAX_USER_AGENT_LENGTH: usize = 200;
+
+/// Adds an entry to the pasta's access log, if it keeps one. The caller
+/// saves the pasta.
+pub fn record(pasta: &mut Pasta, req: &HttpRequest, route: &str) {
+    let Some(log) = pasta.access_log.as_mut() else {
+        return;
+    };
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |n| n.as_secs() as i64);
+
+    log.push(Access {
+        time: now,
+        client: truncate_ip(&client_address(req, ARGS.trust_proxy_headers)),
+        user_agent: req
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|agent| agent.to_str().ok())
+            .unwrap_or("")
+            .chars()
+            .take(MAX_USER_AGENT_LENGTH)
+            .collect(),
+        route: route.to_string(),
+    });
+
+    prune(log, now);
+}
+
+/// Address the request came from. `Forwarded` and `X-Forwarded-For` can be
+/// set by any client, so they are only used behind a proxy that overwrites
+/// them, as `--trust-proxy-headers` says.
+fn client_address(req: &HttpRequest, trust_proxy_headers: bool) -> String {
+    if trust_proxy_headers {
+        req.connection_info()
+            .realip_remote_addr()
+            .unwrap_or("")
+            .to_string()
+    } else {
+        req.peer_addr().map_or_else(String::new, |addr| addr.to_string())
+    }
+}
+
+/// Drops entries older than `--access-log-days`, 0 keeps them for as long as
+/// the pasta exists, and the oldest beyond `MAX_ENTRIES`.
+pub fn prune(log: &mut Vec<Access>, now: i64) {
+    if ARGS.access_log_days > 0 {
+        let cutoff = now - (ARGS.access_log_days * 24 * 60 * 60) as i64;
+        log.retain(|access| access.time >= cutoff);
+    }
+    if log.len() > MAX_ENTRIES {
+        log.drain(..log.len() - MAX_ENTRIES);
+    }
+}
+
+/// Keeps only the network part of an address, enough to tell roughly where
+/// a pasta was opened from without storing who opened it: the last octet of
+/// IPv4 and everything after the first 48 bits of IPv6 are zeroed.
+pub fn truncate_ip(address: &str) -> String {
+    let ip = match address.parse::<SocketAddr>() {
+        Ok(socket) => socket.ip(),
+        Err(_) => match address.parse::<IpAddr>() {
+            Ok(ip) => ip,
+            Err(_) => return String::from("unknown"),
+        },
+    };
+
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            format!("{}.{}.{}.0/24", a, b, c)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => truncate_ip(&ip.to_string()),
+            None => {
+                let s = ip.segments();
+                format!("{:x}:{:x}:{:x}::/48", s[0], s[1], s[2])
+            }
+        },
+    }
+}
+
+#[test]
+fn test_truncate_ip() {
+    assert_eq!(truncate_ip("203.0.113.57:41234"), "203.0.113.0/24");
+    assert_eq!(truncate_ip("2001:db8:85a3:8d3:1319:8a2e:370:7348"), "2001:db8:85a3::/48");
+    assert_eq!(truncate_ip("[::ffff:192.0.2.128]:80"), "192.0.2.0/24");
+    assert_eq!(truncate_ip("not an address"), "unknown");
+}
+
+#[test]
+fn test_client_address_ignores_forwarded_headers() {
+    let req = actix_web::test::TestRequest::default()
+        .peer_addr("198.51.100.7:5000".parse().unwrap())
+        .insert_header(("X-Forwarded-For", "203.0.113.57"))
+        .to_http_request();
+    assert_eq!(client_address(&req, false), "198.51.100.7:5000");
+    assert_eq!(client_address(&req, true), "203.0.113.57");
+}
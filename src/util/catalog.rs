@@ -0,0 +1,266 @@
+use std::collections::HashMap;
+
+/// A message catalog in a small subset of the Fluent syntax: `key = text`
+/// messages, indented continuation lines, `{ $name }` and `{ "text" }`
+/// placeables, and select expressions on a number with plural categories or
+/// exact values as variant keys.
+#[derive(Default, Debug)]
+pub struct Catalog {
+    messages: HashMap<String, Message>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Message {
+    Pattern(String),
+    Select {
+        variable: String,
+        /// variant key and pattern, in file order
+        variants: Vec<(String, String)>,
+        default: usize,
+    },
+}
+
+impl Catalog {
+    /// Reads a catalog, failing with the line number of the first thing it
+    /// doesn't understand.
+    pub fn parse(source: &str) -> Result<Catalog, String> {
+        let mut catalog = Catalog::default();
+        let mut lines = source.lines().enumerate().peekable();
+
+        while let Some((number, line)) = lines.next() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            if line.starts_with(char::is_whitespace) {
+                return Err(format!("line {}: indented line outside a message", number + 1));
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(format!("line {}: expected `key = text`", number + 1));
+            };
+            let key = key.trim();
+            if key.is_empty()
+                || !key
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+            {
+                return Err(format!("line {}: invalid key `{}`", number + 1, key));
+            }
+
+            // the value goes on for as long as the lines are indented, select
+            // expressions up to their closing brace
+            let value = value.trim();
+            let is_select = value.starts_with('{') && value.ends_with("->");
+            let mut continuation: Vec<&str> = Vec::new();
+            while let Some((_, next)) = lines.peek() {
+                let closes_select = is_select && next.trim() == "}";
+                if (next.starts_with(char::is_whitespace) && !next.trim().is_empty()) || closes_select {
+                    continuation.push(next.trim());
+                    lines.next();
+                    if closes_select {
+                        break;
+                    }
+                } else {
+                    break;
+                }
+            }
+
+            let message = parse_message(value, &continuation)
+                .map_err(|error| format!("line {}: {}", number + 1, error))?;
+            catalog.messages.insert(key.to_string(), message);
+        }
+
+        Ok(catalog)
+    }
+
+    /// Adds the messages of `other`, replacing those with the same key.
+    pub fn merge(&mut self, other: Catalog) {
+        self.messages.extend(other.messages);
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.messages.keys()
+    }
+
+    /// The message with its placeables filled in from `args`. Select
+    /// expressions pick the variant by exact value first and then by the
+    /// plural category of the number in `lang`.
+    pub fn format(&self, key: &str, lang: &str, args: &[(&str, u64)]) -> Option<String> {
+        let pattern = match self.messages.get(key)? {
+            Message::Pattern(pattern) => pattern,
+            Message::Select {
+                variable,
+                variants,
+                default,
+            } => {
+                let value = args.iter().find(|(name, _)| name == variable).map(|(_, v)| *v);
+                let chosen = value.and_then(|value| {
+                    let category = plural_category(lang, value);
+                    variants
+                        .iter()
+                        .find(|(key, _)| key.parse::<u64>() == Ok(value))
+                        .or_else(|| variants.iter().find(|(key, _)| key == category))
+                });
+                &chosen.unwrap_or(&variants[*default]).1
+            }
+        };
+        Some(fill(pattern, args))
+    }
+}
+
+fn parse_message(value: &str, continuation: &[&str]) -> Result<Message, String> {
+    let Some(header) = value
+        .strip_prefix('{')
+        .and_then(|rest| rest.strip_suffix("->"))
+        .map(str::trim)
+    else {
+        let mut pattern = String::from(value);
+        for line in continuation {
+            if !pattern.is_empty() {
+                pattern.push('\n');
+            }
+            pattern.push_str(line);
+        }
+        return Ok(Message::Pattern(pattern));
+    };
+
+    let variable = header
+        .strip_prefix('$')
+        .ok_or("select expressions need a `$variable`")?
+        .to_string();
+
+    let mut variants = Vec::new();
+    let mut default = None;
+    for line in continuation {
+        if *line == "}" {
+            break;
+        }
+        let (is_default, line) = match line.strip_prefix('*') {
+            Some(rest) => (true, rest),
+            None => (false, *line),
+        };
+        let (key, pattern) = line
+            .strip_prefix('[')
+            .and_then(|rest| rest.split_once(']'))
+            .ok_or("expected a `[key] text` variant")?;
+        if is_default {
+            default = Some(variants.len());
+        }
+        variants.push((key.trim().to_string(), pattern.trim().to_string()));
+    }
+
+    Ok(Message::Select {
+        variable,
+        default: default.ok_or("select expressions need a `*[default]` variant")?,
+        variants,
+    })
+}
+
+/// Replaces the placeables in `pattern`. Unknown variables are left as they
+/// are so that mistakes show up on the page.
+fn fill(pattern: &str, args: &[(&str, u64)]) -> String {
+    let mut result = String::with_capacity(pattern.len());
+    let mut rest = pattern;
+
+    while let Some(start) = rest.find('{') {
+        // a string literal may itself contain braces
+        let body = rest[start + 1..].trim_start();
+        let search_from = match body.strip_prefix('"').and_then(|b| b.find('"')) {
+            Some(quote) => rest.len() - body.len() + quote + 2 - start,
+            None => 0,
+        };
+        let Some(length) = rest[start + search_from..].find('}').map(|l| l + search_from) else {
+            break;
+        };
+        result.push_str(&rest[..start]);
+        let placeable = &rest[start..start + length + 1];
+        let inner = placeable[1..placeable.len() - 1].trim();
+
+        if let Some(name) = inner.strip_prefix('$') {
+            match args.iter().find(|(arg, _)| *arg == name) {
+                Some((_, value)) => result.push_str(&value.to_string()),
+                None => result.push_str(placeable),
+            }
+        } else if let Some(literal) = inner.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+            result.push_str(literal);
+        } else {
+            result.push_str(placeable);
+        }
+
+        rest = &rest[start + length + 1..];
+    }
+
+    result.push_str(rest);
+    result
+}
+
+/// The CLDR plural category of `n` for the common language families. Other
+/// languages get the English rule.
+pub fn plural_category(lang: &str, n: u64) -> &'static str {
+    let primary = lang.split('-').next().unwrap_or(lang);
+    let (ones, tens) = (n % 10, n % 100);
+
+    match primary {
+        "zh" | "ja" | "ko" | "vi" | "th" | "id" | "ms" => "other",
+        "fr" | "pt" => {
+            if n <= 1 {
+                "one"
+            } else {
+                "other"
+            }
+        }
+        "ru" | "uk" | "be" => {
+            if ones == 1 && tens != 11 {
+                "one"
+            } else if (2..=4).contains(&ones) && !(12..=14).contains(&tens) {
+                "few"
+            } else {
+                "many"
+            }
+        }
+        "pl" => {
+            if n == 1 {
+                "one"
+            } else if (2..=4).contains(&ones) && !(12..=14).contains(&tens) {
+                "few"
+            } else {
+                "many"
+            }
+        }
+        "cs" | "sk" => match n {
+            1 => "one",
+            2..=4 => "few",
+            _ => "other",
+        },
+        _ => {
+            if n == 1 {
+                "one"
+            } else {
+                "other"
+            }
+        }
+    }
+}
+
+#[test]
+fn test_catalog_plurals_and_placeables() {
+    let catalog = Catalog::parse(
+        "# comment\n\
+         greeting = Hello { \"{\" }world{ \"}\" }\n\
+         long =\n    first line\n    second line\n\
+         files = { $count ->\n    [0] No files\n    [one] { $count } file\n   *[other] { $count } files\n}\n\
+         after = still read\n",
+    )
+    .unwrap();
+
+    assert_eq!(catalog.format("greeting", "en", &[]).unwrap(), "Hello {world}");
+    assert_eq!(catalog.format("long", "en", &[]).unwrap(), "first line\nsecond line");
+    assert_eq!(catalog.format("files", "en", &[("count", 0)]).unwrap(), "No files");
+    assert_eq!(catalog.format("files", "en", &[("count", 1)]).unwrap(), "1 file");
+    assert_eq!(catalog.format("files", "en", &[("count", 7)]).unwrap(), "7 files");
+    assert_eq!(catalog.format("files", "zh", &[("count", 1)]).unwrap(), "1 files");
+    assert_eq!(catalog.format("after", "en", &[]).unwrap(), "still read");
+    assert!(Catalog::parse("  stray = indented").is_err());
+    assert!(Catalog::parse("x = { $n ->\n    [one] a\n}").is_err());
+}
@@ -1,4 +1,6 @@
-use crate::{args::ARGS, pasta::Pasta};
+use crate::{args::ARGS, collection::Collection, pasta::Pasta};
+
+use super::{search, syntaxhighlighter};
 
 #[cfg(not(feature = "default"))]
 const PANIC_MSG: &'static str = "Can not run without argument json-db, this version of microbin was compiled without rusqlite support. Make sure you do not pass in no-default-features during compilation";
@@ -6,7 +8,7 @@ const PANIC_MSG: &'static str = "Can not run without argument json-db, this vers
 #[cfg(feature = "default")]
 pub fn read_all() -> Vec<Pasta> {
     if ARGS.json_db {
-        super::db_json::read_all()
+        read_all_json()
     } else {
         super::db_sqlite::read_all()
     }
@@ -15,16 +17,24 @@ pub fn read_all() -> Vec<Pasta> {
 #[cfg(not(feature = "default"))]
 pub fn read_all() -> Vec<Pasta> {
     if ARGS.json_db {
-        super::db_json::read_all()
+        read_all_json()
     } else {
         panic!("{}", PANIC_MSG);
     }
 }
 
+fn read_all_json() -> Vec<Pasta> {
+    let pastas = super::db_json::read_all();
+    search::INDEX.lock().unwrap().rebuild(&pastas);
+    pastas
+}
+
 #[allow(unused)]
 pub fn insert(pastas: Option<&Vec<Pasta>>, pasta: Option<&Pasta>) {
     if ARGS.json_db {
-        super::db_json::update_all(pastas.expect("Called insert() without passing Pasta vector"));
+        let pastas = pastas.expect("Called insert() without passing Pasta vector");
+        super::db_json::update_all(pastas);
+        sync_json_index(pastas, pasta.map(|p| p.id));
     } else {
         #[cfg(feature = "default")]
         super::db_sqlite::insert(pasta.expect("Called insert() without passing new Pasta"));
@@ -36,7 +46,9 @@ pub fn insert(pastas: Option<&Vec<Pasta>>, pasta: Option<&Pasta>) {
 #[allow(unused)]
 pub fn update(pastas: Option<&Vec<Pasta>>, pasta: Option<&Pasta>) {
     if ARGS.json_db {
-        super::db_json::update_all(pastas.expect("Called update() without passing Pasta vector"));
+        let pastas = pastas.expect("Called update() without passing Pasta vector");
+        super::db_json::update_all(pastas);
+        sync_json_index(pastas, pasta.map(|p| p.id));
     } else {
         #[cfg(feature = "default")]
         super::db_sqlite::update(pasta.expect("Called insert() without passing Pasta to update"));
@@ -49,6 +61,7 @@ pub fn update(pastas: Option<&Vec<Pasta>>, pasta: Option<&Pasta>) {
 pub fn update_all(pastas: &Vec<Pasta>) {
     if ARGS.json_db {
         super::db_json::update_all(pastas);
+        search::INDEX.lock().unwrap().rebuild(pastas);
     } else {
         #[cfg(feature = "default")]
         super::db_sqlite::update_all(pastas);
@@ -59,8 +72,13 @@ pub fn update_all(pastas: &Vec<Pasta>) {
 
 #[allow(unused)]
 pub fn delete(pastas: Option<&Vec<Pasta>>, id: Option<u64>) {
+    if let Some(id) = id {
+        syntaxhighlighter::forget(id);
+    }
     if ARGS.json_db {
-        super::db_json::update_all(pastas.expect("Called delete() without passing Pasta vector"));
+        let pastas = pastas.expect("Called delete() without passing Pasta vector");
+        super::db_json::update_all(pastas);
+        sync_json_index(pastas, id);
     } else {
         #[cfg(feature = "default")]
         super::db_sqlite::delete_by_id(id.expect("Called delete() without passing Pasta id"));
@@ -68,3 +86,75 @@ pub fn delete(pastas: Option<&Vec<Pasta>>, id: Option<u64>) {
         panic!("{}", PANIC_MSG);
     }
 }
+
+/// Brings the in-memory search index of the JSON backend up to date after
+/// pasta `id` was written, or after unknown changes if there is no id.
+fn sync_json_index(pastas: &[Pasta], id: Option<u64>) {
+    let mut index = search::INDEX.lock().unwrap();
+    match id {
+        Some(id) => match pastas.iter().find(|p| p.id == id) {
+            Some(pasta) => index.put(pasta),
+            None => index.remove(id),
+        },
+        None => index.rebuild(pastas),
+    }
+}
+
+/// Ids of the pastas matching the query, best matches first. Private and
+/// encrypted pastas are included, callers filter what may be shown.
+pub fn search(query: &str) -> Vec<u64> {
+    if ARGS.json_db {
+        search::INDEX.lock().unwrap().search(query)
+    } else {
+        #[cfg(feature = "default")]
+        return super::db_sqlite::search(query);
+        #[cfg(not(feature = "default"))]
+        panic!("{}", PANIC_MSG);
+    }
+}
+
+pub fn read_collections() -> Vec<Collection> {
+    if ARGS.json_db {
+        super::db_json::read_collections()
+    } else {
+        #[cfg(feature = "default")]
+        return super::db_sqlite::read_collections();
+        #[cfg(not(feature = "default"))]
+        panic!("{}", PANIC_MSG);
+    }
+}
+
+/// Stores a new or changed collection. `collections` is the whole set, which
+/// the JSON backend rewrites.
+#[allow(unused)]
+pub fn save_collection(collections: &Vec<Collection>, collection: &Collection) {
+    if ARGS.json_db {
+        super::db_json::update_collections(collections);
+    } else {
+        #[cfg(feature = "default")]
+        super::db_sqlite::save_collection(collection);
+        #[cfg(not(feature = "default"))]
+        panic!("{}", PANIC_MSG);
+    }
+}
+
+/// Removes a collection, `collections` being what is left.
+#[allow(unused)]
+pub fn delete_collection(collections: &Vec<Collection>, id: u64) {
+    if ARGS.json_db {
+        super::db_json::update_collections(collections);
+    } else {
+        #[cfg(feature = "default")]
+        super::db_sqlite::delete_collection(id);
+        #[cfg(not(feature = "default"))]
+        panic!("{}", PANIC_MSG);
+    }
+}
+
+/// Persists everything that is only held in memory. The JSON store is
+/// rewritten in full, SQLite already commits every change as it happens.
+pub fn flush(pastas: &Vec<Pasta>) {
+    if ARGS.json_db {
+        super::db_json::update_all(pastas);
+    }
+}
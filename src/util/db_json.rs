@@ -2,9 +2,14 @@ use std::fs::File;
 use std::io;
 use std::io::{BufReader, BufWriter};
 
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::collection::Collection;
 use crate::Pasta;
 
 static DATABASE_PATH: &str = "pasta_data/database.json";
+static COLLECTIONS_PATH: &str = "pasta_data/collections.json";
 
 pub fn read_all() -> Vec<Pasta> {
     load_from_file().expect("Failed to load pastas from JSON")
@@ -14,20 +19,44 @@ pub fn update_all(pastas: &Vec<Pasta>) {
     save_to_file(pastas);
 }
 
+pub fn read_collections() -> Vec<Collection> {
+    read_json(COLLECTIONS_PATH).expect("Failed to load collections from JSON")
+}
+
+pub fn update_collections(collections: &Vec<Collection>) {
+    write_json(COLLECTIONS_PATH, collections);
+}
+
 fn save_to_file(pasta_data: &Vec<Pasta>) {
+    write_json(DATABASE_PATH, pasta_data);
+}
+
+fn write_json<T: Serialize>(path: &str, data: &T) {
     // This uses a two stage write. First we write to a new file, if this fails
     // only the new pasta's are lost. Then we replace the current database with
     // the new file. This either succeeds or fails. The database is never left
     // in an undefined state.
-    let tmp_file_path = DATABASE_PATH.to_string() + ".tmp";
+    let tmp_file_path = path.to_string() + ".tmp";
     let tmp_file = File::create(&tmp_file_path).expect(&format!(
         "failed to create temporary database file for writing. path: {tmp_file_path}"
     ));
 
     let writer = BufWriter::new(tmp_file);
-    serde_json::to_writer(writer, &pasta_data)
+    serde_json::to_writer(writer, data)
         .expect("Should be able to write out data to database file");
-    std::fs::rename(tmp_file_path, DATABASE_PATH).expect("Could not update database");
+    std::fs::rename(tmp_file_path, path).expect("Could not update database");
+}
+
+/// Reads a JSON file written by `write_json`, empty if there is none yet. A
+/// file that doesn't parse is an error, so it isn't overwritten on the next
+/// save.
+fn read_json<T: DeserializeOwned + Default>(path: &str) -> io::Result<T> {
+    match File::open(path) {
+        Ok(file) => serde_json::from_reader(BufReader::new(file))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{path}: {e}"))),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(T::default()),
+        Err(e) => Err(e),
+    }
 }
 
 fn load_from_file() -> io::Result<Vec<Pasta>> {
@@ -50,3 +79,21 @@ fn load_from_file() -> io::Result<Vec<Pasta>> {
         }
     }
 }
+
+#[test]
+fn test_read_json_rejects_corrupt_file() {
+    let dir = std::env::temp_dir().join(format!("microbin-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("collections.json");
+    let path = path.to_str().unwrap();
+
+    assert_eq!(read_json::<Vec<u64>>(path).unwrap(), Vec::<u64>::new());
+    write_json(path, &vec![1u64, 2]);
+    assert_eq!(read_json::<Vec<u64>>(path).unwrap(), vec![1, 2]);
+
+    std::fs::write(path, "[1, 2").unwrap();
+    let err = read_json::<Vec<u64>>(path).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
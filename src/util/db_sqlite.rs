@@ -1,6 +1,8 @@
 use bytesize::ByteSize;
 use rusqlite::{params, Connection};
 
+use crate::collection::Collection;
+use crate::util::search::{searchable_fields, tokenize};
 use crate::{args::ARGS, pasta::PastaFile, Pasta};
 
 pub fn read_all() -> Vec<Pasta> {
@@ -43,12 +45,23 @@ pub fn rewrite_all_to_db(pasta_data: &[Pasta]) {
             last_read INTEGER NOT NULL,
             read_count INTEGER NOT NULL,
             burn_after_reads INTEGER NOT NULL,
-            pasta_type TEXT NOT NULL
+            pasta_type TEXT NOT NULL,
+            file_mime TEXT,
+            revisions TEXT,
+            version INTEGER NOT NULL DEFAULT 1,
+            parent INTEGER,
+            tags TEXT,
+            access_log TEXT,
+            link TEXT
         );",
         params![],
     )
     .expect("Failed to create SQLite table for Pasta!");
 
+    create_search_table(&conn);
+    conn.execute("DELETE FROM pasta_search", params![])
+        .expect("Failed to clear search index!");
+
     for pasta in pasta_data.iter() {
         conn.execute(
             "INSERT INTO pasta (
@@ -69,8 +82,15 @@ pub fn rewrite_all_to_db(pasta_data: &[Pasta]) {
                 last_read,
                 read_count,
                 burn_after_reads,
-                pasta_type
-            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)",
+                pasta_type,
+                file_mime,
+                revisions,
+                version,
+                parent,
+                tags,
+                access_log,
+                link
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25)",
             params![
                 pasta.id,
                 pasta.title,
@@ -90,9 +110,145 @@ pub fn rewrite_all_to_db(pasta_data: &[Pasta]) {
                 pasta.read_count,
                 pasta.burn_after_reads,
                 pasta.pasta_type,
+                pasta.file.as_ref().map_or("", |f| f.mime.as_str()),
+                revisions_to_json(pasta),
+                pasta.version,
+                pasta.parent,
+                tags_to_json(pasta),
+                access_log_to_json(pasta),
+                link_to_json(pasta),
             ],
         )
         .expect("Failed to insert pasta.");
+
+        index_pasta(&conn, pasta);
+    }
+}
+
+/// Adds a column that was introduced after the table was first created.
+fn add_column_if_missing(conn: &Connection, name: &str, definition: &str) {
+    let column_exists: Result<i32, _> = conn.query_row(
+        "SELECT COUNT(*) FROM pragma_table_info('pasta') WHERE name=?1",
+        [name],
+        |row| row.get(0),
+    );
+
+    if let Ok(0) = column_exists {
+        log::info!("Migrating database: Adding '{}' column to pasta table", name);
+        conn.execute(
+            &format!("ALTER TABLE pasta ADD COLUMN {} {}", name, definition),
+            params![],
+        )
+        .unwrap_or_else(|_| panic!("Failed to add {} column to pasta table", name));
+    }
+}
+
+/// Full-text index over the searchable fields, with the pasta id as rowid.
+/// Created when the pastas are loaded at startup.
+fn create_search_table(conn: &Connection) {
+    conn.execute(
+        "CREATE VIRTUAL TABLE IF NOT EXISTS pasta_search USING fts5(
+            title,
+            content,
+            file_name,
+            extension,
+            tokenize = 'unicode61 remove_diacritics 2'
+        );",
+        params![],
+    )
+    .expect("Failed to create SQLite search index!");
+}
+
+fn index_pasta(conn: &Connection, pasta: &Pasta) {
+    let [title, content, file_name, extension] = searchable_fields(pasta);
+
+    conn.execute("DELETE FROM pasta_search WHERE rowid = ?1", params![pasta.id])
+        .expect("Failed to update search index.");
+    conn.execute(
+        "INSERT INTO pasta_search (rowid, title, content, file_name, extension)
+            VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![pasta.id, title, content, file_name, extension],
+    )
+    .expect("Failed to update search index.");
+}
+
+/// Whether the search index already holds the pasta's current searchable
+/// fields.
+fn is_indexed(conn: &Connection, pasta: &Pasta) -> bool {
+    conn.query_row(
+        "SELECT title, content, file_name, extension FROM pasta_search WHERE rowid = ?1",
+        params![pasta.id],
+        |row| {
+            let fields = searchable_fields(pasta);
+            for (i, field) in fields.iter().enumerate() {
+                if row.get::<_, String>(i)? != *field {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        },
+    )
+    .unwrap_or(false)
+}
+
+/// Ids of the pastas matching every word of the query, each word also
+/// matching as a prefix, best matches first.
+pub fn search(query: &str) -> Vec<u64> {
+    // quote every word so nothing in the query is read as FTS5 syntax
+    let words = tokenize(query);
+    if words.is_empty() {
+        return Vec::new();
+    }
+    let match_query = words
+        .iter()
+        .map(|word| format!("\"{}\"*", word))
+        .collect::<Vec<String>>()
+        .join(" ");
+
+    let conn = Connection::open(format!("{}/database.sqlite", ARGS.data_dir))
+        .expect("Failed to open SQLite database!");
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT rowid FROM pasta_search WHERE pasta_search MATCH ?1
+            ORDER BY bm25(pasta_search, 3.0, 1.0, 1.0, 1.0)",
+        )
+        .expect("Failed to prepare SQL statement to search pastas");
+
+    let ids = stmt
+        .query_map([match_query], |row| row.get(0))
+        .expect("Failed to search pastas in SQLite database.");
+
+    ids.filter_map(|id| id.ok()).collect()
+}
+
+fn tags_to_json(pasta: &Pasta) -> Option<String> {
+    if pasta.tags.is_empty() {
+        None
+    } else {
+        serde_json::to_string(&pasta.tags).ok()
+    }
+}
+
+fn access_log_to_json(pasta: &Pasta) -> Option<String> {
+    pasta
+        .access_log
+        .as_ref()
+        .and_then(|log| serde_json::to_string(log).ok())
+}
+
+fn link_to_json(pasta: &Pasta) -> Option<String> {
+    pasta
+        .link
+        .as_ref()
+        .and_then(|link| serde_json::to_string(link).ok())
+}
+
+fn revisions_to_json(pasta: &Pasta) -> Option<String> {
+    if pasta.revisions.is_empty() {
+        None
+    } else {
+        serde_json::to_string(&pasta.revisions).ok()
     }
 }
 
@@ -120,7 +276,14 @@ pub fn select_all_from_db() -> Vec<Pasta> {
             last_read INTEGER NOT NULL,
             read_count INTEGER NOT NULL,
             burn_after_reads INTEGER NOT NULL,
-            pasta_type TEXT NOT NULL
+            pasta_type TEXT NOT NULL,
+            file_mime TEXT,
+            revisions TEXT,
+            version INTEGER NOT NULL DEFAULT 1,
+            parent INTEGER,
+            tags TEXT,
+            access_log TEXT,
+            link TEXT
         );",
         params![],
     )
@@ -141,6 +304,14 @@ pub fn select_all_from_db() -> Vec<Pasta> {
         }
     }
 
+    add_column_if_missing(&conn, "file_mime", "TEXT");
+    add_column_if_missing(&conn, "revisions", "TEXT");
+    add_column_if_missing(&conn, "version", "INTEGER NOT NULL DEFAULT 1");
+    add_column_if_missing(&conn, "parent", "INTEGER");
+    add_column_if_missing(&conn, "tags", "TEXT");
+    add_column_if_missing(&conn, "access_log", "TEXT");
+    add_column_if_missing(&conn, "link", "TEXT");
+
     let mut stmt = conn
         .prepare("SELECT * FROM pasta ORDER BY created ASC")
         .expect("Failed to prepare SQL statement to load pastas");
@@ -160,6 +331,9 @@ pub fn select_all_from_db() -> Vec<Pasta> {
                             Some(PastaFile {
                                 name,
                                 size: ByteSize::b(size as u64),
+                                mime: row
+                                    .get::<_, Option<String>>("file_mime")?
+                                    .unwrap_or_default(),
                             })
                         } else {
                             None
@@ -181,13 +355,45 @@ pub fn select_all_from_db() -> Vec<Pasta> {
                 read_count: row.get(15)?,
                 burn_after_reads: row.get(16)?,
                 pasta_type: row.get(17)?,
+                revisions: row
+                    .get::<_, Option<String>>("revisions")?
+                    .and_then(|json| serde_json::from_str(&json).ok())
+                    .unwrap_or_default(),
+                version: row.get("version")?,
+                parent: row.get("parent")?,
+                tags: row
+                    .get::<_, Option<String>>("tags")?
+                    .and_then(|json| serde_json::from_str(&json).ok())
+                    .unwrap_or_default(),
+                access_log: row
+                    .get::<_, Option<String>>("access_log")?
+                    .and_then(|json| serde_json::from_str(&json).ok()),
+                link: row
+                    .get::<_, Option<String>>("link")?
+                    .and_then(|json| serde_json::from_str(&json).ok()),
             })
         })
         .expect("Failed to select Pastas from SQLite database.");
 
-    pasta_iter
+    let pastas = pasta_iter
         .map(|r| r.expect("Failed to get pasta"))
-        .collect::<Vec<Pasta>>()
+        .collect::<Vec<Pasta>>();
+
+    // databases from before search existed start out without an index
+    create_search_table(&conn);
+    let indexed: i64 = conn
+        .query_row("SELECT COUNT(*) FROM pasta_search", [], |row| row.get(0))
+        .unwrap_or(0);
+    if indexed != pastas.len() as i64 {
+        log::info!("Rebuilding search index for {} pastas", pastas.len());
+        conn.execute("DELETE FROM pasta_search", params![])
+            .expect("Failed to clear search index!");
+        for pasta in pastas.iter() {
+            index_pasta(&conn, pasta);
+        }
+    }
+
+    pastas
 }
 
 pub fn insert(pasta: &Pasta) {
@@ -214,7 +420,14 @@ pub fn insert(pasta: &Pasta) {
             last_read INTEGER NOT NULL,
             read_count INTEGER NOT NULL,
             burn_after_reads INTEGER NOT NULL,
-            pasta_type TEXT NOT NULL
+            pasta_type TEXT NOT NULL,
+            file_mime TEXT,
+            revisions TEXT,
+            version INTEGER NOT NULL DEFAULT 1,
+            parent INTEGER,
+            tags TEXT,
+            access_log TEXT,
+            link TEXT
         );",
         params![],
     )
@@ -239,8 +452,15 @@ pub fn insert(pasta: &Pasta) {
                 last_read,
                 read_count,
                 burn_after_reads,
-                pasta_type
-            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)",
+                pasta_type,
+                file_mime,
+                revisions,
+                version,
+                parent,
+                tags,
+                access_log,
+                link
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25)",
         params![
             pasta.id,
             pasta.title,
@@ -260,9 +480,18 @@ pub fn insert(pasta: &Pasta) {
             pasta.read_count,
             pasta.burn_after_reads,
             pasta.pasta_type,
+            pasta.file.as_ref().map_or("", |f| f.mime.as_str()),
+            revisions_to_json(pasta),
+            pasta.version,
+            pasta.parent,
+            tags_to_json(pasta),
+            access_log_to_json(pasta),
+            link_to_json(pasta),
         ],
     )
     .expect("Failed to insert pasta.");
+
+    index_pasta(&conn, pasta);
 }
 
 pub fn update(pasta: &Pasta) {
@@ -287,7 +516,14 @@ pub fn update(pasta: &Pasta) {
             last_read = ?15,
             read_count = ?16,
             burn_after_reads = ?17,
-            pasta_type = ?18
+            pasta_type = ?18,
+            file_mime = ?19,
+            revisions = ?20,
+            version = ?21,
+            parent = ?22,
+            tags = ?23,
+            access_log = ?24,
+            link = ?25
         WHERE id = ?1;",
         params![
             pasta.id,
@@ -308,9 +544,21 @@ pub fn update(pasta: &Pasta) {
             pasta.read_count,
             pasta.burn_after_reads,
             pasta.pasta_type,
+            pasta.file.as_ref().map_or("", |f| f.mime.as_str()),
+            revisions_to_json(pasta),
+            pasta.version,
+            pasta.parent,
+            tags_to_json(pasta),
+            access_log_to_json(pasta),
+            link_to_json(pasta),
         ],
     )
     .expect("Failed to update pasta.");
+
+    // most updates only bump the read count or the access log
+    if !is_indexed(&conn, pasta) {
+        index_pasta(&conn, pasta);
+    }
 }
 
 pub fn delete_by_id(id: u64) {
@@ -323,4 +571,79 @@ pub fn delete_by_id(id: u64) {
         params![id],
     )
     .expect("Failed to delete pasta.");
+
+    conn.execute("DELETE FROM pasta_search WHERE rowid = ?1", params![id])
+        .expect("Failed to update search index.");
+}
+
+fn create_collection_table(conn: &Connection) {
+    conn.execute(
+        "
+        CREATE TABLE IF NOT EXISTS collection (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            description TEXT NOT NULL,
+            members TEXT NOT NULL,
+            encrypted_key TEXT,
+            created INTEGER NOT NULL
+        );",
+        params![],
+    )
+    .expect("Failed to create SQLite table for Collection!");
+}
+
+pub fn read_collections() -> Vec<Collection> {
+    let conn = Connection::open(format!("{}/database.sqlite", ARGS.data_dir))
+        .expect("Failed to open SQLite database!");
+    create_collection_table(&conn);
+
+    let mut stmt = conn
+        .prepare("SELECT id, name, description, members, encrypted_key, created FROM collection ORDER BY created ASC")
+        .expect("Failed to prepare SQL statement to load collections");
+
+    let collection_iter = stmt
+        .query_map([], |row| {
+            Ok(Collection {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                description: row.get(2)?,
+                members: serde_json::from_str(&row.get::<_, String>(3)?).unwrap_or_default(),
+                encrypted_key: row.get(4)?,
+                created: row.get(5)?,
+            })
+        })
+        .expect("Failed to select Collections from SQLite database.");
+
+    collection_iter
+        .map(|r| r.expect("Failed to get collection"))
+        .collect::<Vec<Collection>>()
+}
+
+pub fn save_collection(collection: &Collection) {
+    let conn = Connection::open(format!("{}/database.sqlite", ARGS.data_dir))
+        .expect("Failed to open SQLite database!");
+    create_collection_table(&conn);
+
+    conn.execute(
+        "INSERT OR REPLACE INTO collection (id, name, description, members, encrypted_key, created)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            collection.id,
+            collection.name,
+            collection.description,
+            serde_json::to_string(&collection.members).unwrap_or_default(),
+            collection.encrypted_key.as_deref(),
+            collection.created,
+        ],
+    )
+    .expect("Failed to save collection.");
+}
+
+pub fn delete_collection(id: u64) {
+    let conn = Connection::open(format!("{}/database.sqlite", ARGS.data_dir))
+        .expect("Failed to open SQLite database!");
+    create_collection_table(&conn);
+
+    conn.execute("DELETE FROM collection WHERE id = ?1;", params![id])
+        .expect("Failed to delete collection.");
 }
@@ -0,0 +1,438 @@
+use similar::{ChangeTag, DiffOp, TextDiff};
+
+use crate::util::syntaxhighlighter::{detect_syntax, highlight_lines};
+
+/// Lines of unchanged context shown around each change.
+const CONTEXT_LINES: usize = 3;
+
+fn escape(line: &str) -> String {
+    html_escape::encode_text(line.trim_end_matches(['\n', '\r'])).to_string()
+}
+
+fn line_number(n: Option<usize>) -> String {
+    n.map_or(String::from(""), |n| (n + 1).to_string())
+}
+
+/// Renders a unified diff of two texts as an HTML table.
+pub fn unified_diff_html(old: &str, new: &str) -> String {
+    let diff = TextDiff::from_lines(old, new);
+    let mut html = String::from("<table class=\"diff diff-unified\">");
+
+    for (i, group) in diff.grouped_ops(CONTEXT_LINES).iter().enumerate() {
+        if i > 0 {
+            html.push_str("<tr class=\"diff-skip\"><td></td><td></td><td>&hellip;</td></tr>");
+        }
+        for op in group {
+            for change in diff.iter_changes(op) {
+                let (class, sign) = match change.tag() {
+                    ChangeTag::Delete => ("diff-del", "-"),
+                    ChangeTag::Insert => ("diff-ins", "+"),
+                    ChangeTag::Equal => ("", " "),
+                };
+                html.push_str(&format!(
+                    "<tr class=\"{}\"><td class=\"diff-ln\">{}</td><td class=\"diff-ln\">{}</td><td>{} {}</td></tr>",
+                    class,
+                    line_number(change.old_index()),
+                    line_number(change.new_index()),
+                    sign,
+                    escape(change.value()),
+                ));
+            }
+        }
+    }
+
+    html.push_str("</table>");
+    html
+}
+
+/// Renders a side-by-side diff of two texts as an HTML table, with replaced
+/// lines lined up next to each other.
+pub fn side_by_side_diff_html(old: &str, new: &str) -> String {
+    let diff = TextDiff::from_lines(old, new);
+    let old_lines = diff.old_slices();
+    let new_lines = diff.new_slices();
+    let mut html = String::from("<table class=\"diff diff-split\">");
+
+    let cell = |lines: &[&str], index: Option<usize>, class: &str| -> String {
+        match index {
+            Some(i) => format!(
+                "<td class=\"diff-ln\">{}</td><td class=\"{}\">{}</td>",
+                i + 1,
+                class,
+                escape(lines[i])
+            ),
+            None => String::from("<td class=\"diff-ln\"></td><td class=\"diff-empty\"></td>"),
+        }
+    };
+
+    for (i, group) in diff.grouped_ops(CONTEXT_LINES).iter().enumerate() {
+        if i > 0 {
+            html.push_str(
+                "<tr class=\"diff-skip\"><td></td><td>&hellip;</td><td></td><td>&hellip;</td></tr>",
+            );
+        }
+        for op in group {
+            let (old_range, new_range) = (op.old_range(), op.new_range());
+            let (old_class, new_class) = match op {
+                DiffOp::Equal { .. } => ("", ""),
+                _ => ("diff-del", "diff-ins"),
+            };
+            let rows = old_range.len().max(new_range.len());
+            for row in 0..rows {
+                let old_index = (row < old_range.len()).then(|| old_range.start + row);
+                let new_index = (row < new_range.len()).then(|| new_range.start + row);
+                html.push_str("<tr>");
+                html.push_str(&cell(old_lines, old_index, old_class));
+                html.push_str(&cell(new_lines, new_index, new_class));
+                html.push_str("</tr>");
+            }
+        }
+    }
+
+    html.push_str("</table>");
+    html
+}
+
+/// One file of a unified diff.
+#[derive(Debug, Default)]
+pub struct FilePatch {
+    pub old_name: String,
+    pub new_name: String,
+    pub hunks: Vec<Hunk>,
+}
+
+/// An `@@ -a,b +c,d @@` section with its lines, without the leading sign.
+#[derive(Debug)]
+pub struct Hunk {
+    pub header: String,
+    pub old_start: usize,
+    pub new_start: usize,
+    pub lines: Vec<(ChangeTag, String)>,
+}
+
+impl FilePatch {
+    /// The name to show, with renames as `old → new`.
+    fn display_name(&self) -> String {
+        let old = self.old_name.as_str();
+        let new = self.new_name.as_str();
+        if new.is_empty() || new == "/dev/null" {
+            html_escape::encode_text(old).to_string()
+        } else if old.is_empty() || old == "/dev/null" || old == new {
+            html_escape::encode_text(new).to_string()
+        } else {
+            format!(
+                "{} &rarr; {}",
+                html_escape::encode_text(old),
+                html_escape::encode_text(new)
+            )
+        }
+    }
+
+    /// The lines of the old and of the new side as HTML, highlighted in the
+    /// language of the file name or else of the new content.
+    fn sides(&self, highlight: bool) -> (Vec<String>, Vec<String>) {
+        let side = |skip: ChangeTag| -> String {
+            let mut text = String::new();
+            for (tag, line) in self.hunks.iter().flat_map(|hunk| hunk.lines.iter()) {
+                if *tag != skip {
+                    text.push_str(line);
+                    text.push('\n');
+                }
+            }
+            text
+        };
+        let (old, new) = (side(ChangeTag::Insert), side(ChangeTag::Delete));
+
+        let name = match self.new_name.as_str() {
+            "" | "/dev/null" => &self.old_name,
+            name => name,
+        };
+        let extension = detect_syntax("", Some(name)).or_else(|| detect_syntax(&new, None));
+        let lines = |text: &str| -> Vec<String> {
+            let count = text.lines().count();
+            if let Some(extension) = extension.as_deref().filter(|_| highlight) {
+                let lines = highlight_lines(text, extension);
+                if lines.len() == count {
+                    return lines;
+                }
+            }
+            text.lines().map(escape).collect()
+        };
+        (lines(&old), lines(&new))
+    }
+}
+
+/// The line numbers and counts from a hunk header.
+fn hunk_header(line: &str) -> Option<(usize, usize, usize, usize)> {
+    let rest = line.strip_prefix("@@ -")?;
+    let (ranges, _) = rest.split_once(" @@")?;
+    let (old, new) = ranges.split_once(" +")?;
+    let range = |range: &str| -> Option<(usize, usize)> {
+        match range.split_once(',') {
+            Some((start, count)) => Some((start.parse().ok()?, count.parse().ok()?)),
+            None => Some((range.parse().ok()?, 1)),
+        }
+    };
+    let (old_start, old_count) = range(old)?;
+    let (new_start, new_count) = range(new)?;
+    Some((old_start, old_count, new_start, new_count))
+}
+
+/// `a/src/main.rs\t2024-01-01 10:00` becomes `src/main.rs`.
+fn patch_file_name(name: &str) -> String {
+    let name = name.split('\t').next().unwrap_or("").trim();
+    name.strip_prefix("a/")
+        .or_else(|| name.strip_prefix("b/"))
+        .unwrap_or(name)
+        .to_string()
+}
+
+/// Reads the files and hunks of a unified diff, as written by `diff -u`,
+/// `git diff` or `git format-patch`. Anything outside the hunks, like commit
+/// messages and index lines, is skipped.
+pub fn parse_patch(patch: &str) -> Vec<FilePatch> {
+    let mut files: Vec<FilePatch> = Vec::new();
+    let (mut old_left, mut new_left) = (0, 0);
+
+    for line in patch.lines() {
+        if old_left > 0 || new_left > 0 {
+            let change = match line.as_bytes().first() {
+                None | Some(b' ') => Some(ChangeTag::Equal),
+                Some(b'-') => Some(ChangeTag::Delete),
+                Some(b'+') => Some(ChangeTag::Insert),
+                _ => None,
+            };
+            if let (Some(tag), Some(hunk)) = (change, files.last_mut().and_then(|f| f.hunks.last_mut())) {
+                if tag != ChangeTag::Insert {
+                    old_left -= 1.min(old_left);
+                }
+                if tag != ChangeTag::Delete {
+                    new_left -= 1.min(new_left);
+                }
+                hunk.lines.push((tag, line.get(1..).unwrap_or("").to_string()));
+                continue;
+            }
+            if !line.starts_with('\\') {
+                old_left = 0;
+                new_left = 0;
+            }
+        }
+
+        if let Some((old_start, old_count, new_start, new_count)) = hunk_header(line) {
+            if files.is_empty() {
+                files.push(FilePatch::default());
+            }
+            if let Some(file) = files.last_mut() {
+                file.hunks.push(Hunk {
+                    header: line.to_string(),
+                    old_start,
+                    new_start,
+                    lines: Vec::new(),
+                });
+            }
+            old_left = old_count;
+            new_left = new_count;
+        } else if let Some(names) = line.strip_prefix("diff --git ") {
+            let (old, new) = names.split_once(" b/").unwrap_or((names, names));
+            files.push(FilePatch {
+                old_name: patch_file_name(old),
+                new_name: patch_file_name(new),
+                hunks: Vec::new(),
+            });
+        } else if let Some(name) = line.strip_prefix("--- ") {
+            if files.last().map_or(true, |f| !f.hunks.is_empty()) {
+                files.push(FilePatch::default());
+            }
+            if let Some(file) = files.last_mut() {
+                file.old_name = patch_file_name(name);
+            }
+        } else if let Some(name) = line.strip_prefix("+++ ") {
+            if files.is_empty() {
+                files.push(FilePatch::default());
+            }
+            if let Some(file) = files.last_mut() {
+                file.new_name = patch_file_name(name);
+            }
+        }
+    }
+
+    files
+}
+
+/// Whether any file of the patch has changes to show.
+pub fn has_hunks(files: &[FilePatch]) -> bool {
+    files.iter().any(|file| !file.hunks.is_empty())
+}
+
+/// The unified diff from one text to the other, as stored for diff pastas.
+pub fn compute_patch(old: &str, new: &str) -> String {
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(CONTEXT_LINES)
+        .header("old", "new")
+        .to_string()
+}
+
+/// Renders a parsed patch as a section per file, either side by side or
+/// inline. Lines are highlighted with the theme classes unless `highlight`
+/// is off.
+pub fn patch_html(files: &[FilePatch], split: bool, highlight: bool) -> String {
+    let mut html = String::new();
+
+    for file in files {
+        html.push_str("<div class=\"diff-file\"><div class=\"diff-file-name\">");
+        html.push_str(&file.display_name());
+        html.push_str("</div>");
+        if file.hunks.is_empty() {
+            html.push_str("</div>");
+            continue;
+        }
+
+        let (old_lines, new_lines) = file.sides(highlight);
+        let (mut old_index, mut new_index) = (0, 0);
+        html.push_str(if split {
+            "<table class=\"diff diff-split hl-code\">"
+        } else {
+            "<table class=\"diff diff-unified hl-code\">"
+        });
+
+        for hunk in &file.hunks {
+            let header = escape(&hunk.header);
+            if split {
+                html.push_str(&format!(
+                    "<tr class=\"diff-skip\"><td></td><td colspan=\"3\">{}</td></tr>",
+                    header
+                ));
+            } else {
+                html.push_str(&format!(
+                    "<tr class=\"diff-skip\"><td></td><td></td><td>{}</td></tr>",
+                    header
+                ));
+            }
+
+            let (mut old_n, mut new_n) = (hunk.old_start, hunk.new_start);
+            let mut deleted: Vec<(usize, &str)> = Vec::new();
+            let mut inserted: Vec<(usize, &str)> = Vec::new();
+            for (tag, _) in &hunk.lines {
+                let old_line = old_lines.get(old_index).map_or("", String::as_str);
+                let new_line = new_lines.get(new_index).map_or("", String::as_str);
+                match tag {
+                    ChangeTag::Delete => {
+                        deleted.push((old_n, old_line));
+                        old_index += 1;
+                        old_n += 1;
+                    }
+                    ChangeTag::Insert => {
+                        inserted.push((new_n, new_line));
+                        new_index += 1;
+                        new_n += 1;
+                    }
+                    ChangeTag::Equal => {
+                        push_changes(&mut html, split, &mut deleted, &mut inserted);
+                        if split {
+                            html.push_str(&format!(
+                                "<tr><td class=\"diff-ln\">{}</td><td>{}</td><td class=\"diff-ln\">{}</td><td>{}</td></tr>",
+                                old_n, old_line, new_n, new_line
+                            ));
+                        } else {
+                            html.push_str(&format!(
+                                "<tr class=\"\"><td class=\"diff-ln\">{}</td><td class=\"diff-ln\">{}</td><td>  {}</td></tr>",
+                                old_n, new_n, new_line
+                            ));
+                        }
+                        old_index += 1;
+                        new_index += 1;
+                        old_n += 1;
+                        new_n += 1;
+                    }
+                }
+            }
+            push_changes(&mut html, split, &mut deleted, &mut inserted);
+        }
+
+        html.push_str("</table></div>");
+    }
+
+    html
+}
+
+/// Writes a run of deleted and inserted lines, next to each other in the
+/// side by side view, and empties both.
+fn push_changes(html: &mut String, split: bool, deleted: &mut Vec<(usize, &str)>, inserted: &mut Vec<(usize, &str)>) {
+    if split {
+        let cell = |line: Option<&(usize, &str)>, class: &str| -> String {
+            match line {
+                Some((n, text)) => format!(
+                    "<td class=\"diff-ln\">{}</td><td class=\"{}\">{}</td>",
+                    n, class, text
+                ),
+                None => String::from("<td class=\"diff-ln\"></td><td class=\"diff-empty\"></td>"),
+            }
+        };
+        for row in 0..deleted.len().max(inserted.len()) {
+            html.push_str("<tr>");
+            html.push_str(&cell(deleted.get(row), "diff-del"));
+            html.push_str(&cell(inserted.get(row), "diff-ins"));
+            html.push_str("</tr>");
+        }
+    } else {
+        for (n, text) in deleted.iter() {
+            html.push_str(&format!(
+                "<tr class=\"diff-del\"><td class=\"diff-ln\">{}</td><td class=\"diff-ln\"></td><td>- {}</td></tr>",
+                n, text
+            ));
+        }
+        for (n, text) in inserted.iter() {
+            html.push_str(&format!(
+                "<tr class=\"diff-ins\"><td class=\"diff-ln\"></td><td class=\"diff-ln\">{}</td><td>+ {}</td></tr>",
+                n, text
+            ));
+        }
+    }
+    deleted.clear();
+    inserted.clear();
+}
+
+#[test]
+fn test_unified_diff_marks_changed_lines() {
+    let html = unified_diff_html("a\nb\nc\n", "a\nB\nc\n");
+    assert!(html.contains("<tr class=\"diff-del\"><td class=\"diff-ln\">2</td><td class=\"diff-ln\"></td><td>- b</td></tr>"));
+    assert!(html.contains("<tr class=\"diff-ins\"><td class=\"diff-ln\"></td><td class=\"diff-ln\">2</td><td>+ B</td></tr>"));
+}
+
+#[test]
+fn test_diff_escapes_html() {
+    let html = side_by_side_diff_html("", "<script>\n");
+    assert!(html.contains("&lt;script&gt;"));
+    assert!(!html.contains("<script>"));
+}
+
+#[test]
+fn test_parse_patch() {
+    let patch = "From 1234 Mon Sep 17 00:00:00 2001\nSubject: [PATCH] Fix\n\n\
+diff --git a/src/main.rs b/src/main.rs\nindex 83db48f..bf269f4 100644\n\
+--- a/src/main.rs\n+++ b/src/main.rs\n@@ -1,3 +1,3 @@ fn main() {\n \
+fn main() {\n-    let x = 1;\n+    let x = 2;\n }\n\\ No newline at end of file\n\
+--- old.txt\t2024-01-01 10:00:00\n+++ new.txt\t2024-01-02 10:00:00\n@@ -1 +1,2 @@\n--- a\n+<b>\n+++ c\n";
+    let files = parse_patch(patch);
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[0].new_name, "src/main.rs");
+    assert_eq!(files[0].hunks[0].lines.len(), 4);
+    assert_eq!(files[0].hunks[0].lines[1], (ChangeTag::Delete, String::from("    let x = 1;")));
+    assert_eq!(files[1].old_name, "old.txt");
+    assert_eq!(files[1].hunks[0].lines[0], (ChangeTag::Delete, String::from("-- a")));
+    assert_eq!(files[1].hunks[0].lines[2], (ChangeTag::Insert, String::from("++ c")));
+    assert!(!has_hunks(&parse_patch("just some text\n")));
+
+    let html = patch_html(&files, true, true);
+    assert!(html.contains("<div class=\"diff-file-name\">old.txt &rarr; new.txt</div>"));
+    assert!(html.contains("hl-storage"));
+    assert!(html.contains("&lt;b&gt;"));
+    let html = patch_html(&files, false, false);
+    assert!(html.contains("<tr class=\"diff-ins\"><td class=\"diff-ln\"></td><td class=\"diff-ln\">1</td><td>+ &lt;b&gt;</td></tr>"));
+
+    let patch = compute_patch("a\nb\n", "a\nc\n");
+    assert!(patch.starts_with("--- old\n+++ new\n@@ -1,2 +1,2 @@\n"));
+    assert!(has_hunks(&parse_patch(&patch)));
+    assert!(!has_hunks(&parse_patch(&compute_patch("a\n", "a\n"))));
+}
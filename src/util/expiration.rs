@@ -0,0 +1,240 @@
+use chrono::DateTime;
+
+use crate::args::ARGS;
+
+/// Used when uploads can't be kept forever and nothing else says how long.
+pub const DEFAULT_LIFETIME: i64 = 60 * 60 * 24 * 7;
+
+/// The presets of older versions, still accepted from forms and scripts.
+const NAMED: [(&str, i64); 6] = [
+    ("1min", 60),
+    ("10min", 60 * 10),
+    ("1hour", 60 * 60),
+    ("24hour", 60 * 60 * 24),
+    ("3days", 60 * 60 * 24 * 3),
+    ("1week", 60 * 60 * 24 * 7),
+];
+
+/// How long uploads may live, from the admin's settings.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Bounds {
+    /// Shortest lifetime in seconds.
+    pub min: Option<i64>,
+    /// Longest lifetime in seconds. Rules out never expiring uploads.
+    pub max: Option<i64>,
+    pub allow_never: bool,
+}
+
+impl Bounds {
+    pub fn from_args() -> Bounds {
+        Bounds {
+            min: ARGS.min_expiry.as_ref().map(|l| l.0),
+            max: ARGS.max_expiry.as_ref().map(|l| l.0),
+            allow_never: ARGS.eternal_pasta && ARGS.max_expiry.is_none(),
+        }
+    }
+
+    pub fn allows(&self, lifetime: i64) -> bool {
+        self.min.map_or(true, |min| lifetime >= min) && self.max.map_or(true, |max| lifetime <= max)
+    }
+}
+
+/// A lifetime in seconds: an old preset name like `24hour`, units like `90m`
+/// or `1h30m`, or an ISO 8601 duration like `P30D` or `PT1H30M`. Years count
+/// as 365 days and months as 30.
+pub fn parse_duration(input: &str) -> Option<i64> {
+    let input = input.trim();
+    if let Some((_, seconds)) = NAMED.iter().find(|(name, _)| *name == input) {
+        return Some(*seconds);
+    }
+
+    let seconds = match input.strip_prefix(['P', 'p']) {
+        Some(iso) => parse_iso_duration(iso)?,
+        None => parse_units(input)?,
+    };
+    (seconds > 0).then_some(seconds)
+}
+
+/// `<number><unit>` pairs with the units s, m or min, h, d and w.
+fn parse_units(input: &str) -> Option<i64> {
+    let mut total: i64 = 0;
+    let mut rest = input.trim();
+    if rest.is_empty() {
+        return None;
+    }
+
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        let number: i64 = rest[..digits].parse().ok()?;
+        rest = rest[digits..].trim_start();
+
+        let unit_length = rest.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(rest.len());
+        let unit = match rest[..unit_length].to_lowercase().as_str() {
+            "s" | "sec" => 1,
+            "m" | "min" => 60,
+            "h" => 60 * 60,
+            "d" => 60 * 60 * 24,
+            "w" => 60 * 60 * 24 * 7,
+            _ => return None,
+        };
+        rest = rest[unit_length..].trim_start();
+
+        total = total.checked_add(number.checked_mul(unit)?)?;
+    }
+
+    Some(total)
+}
+
+/// The part of an ISO 8601 duration after the `P`.
+fn parse_iso_duration(input: &str) -> Option<i64> {
+    let (date, time) = match input.split_once(['T', 't']) {
+        Some((date, time)) if !time.is_empty() => (date, Some(time)),
+        Some(_) => return None,
+        None => (input, None),
+    };
+
+    let day = 60 * 60 * 24;
+    let mut total = designators(date, &[('Y', 365 * day), ('M', 30 * day), ('W', 7 * day), ('D', day)])?;
+    if let Some(time) = time {
+        total = total.checked_add(designators(time, &[('H', 60 * 60), ('M', 60), ('S', 1)])?)?;
+    }
+    Some(total)
+}
+
+/// Adds up `<number><designator>` pairs, which have to come in the order of
+/// `units`.
+fn designators(input: &str, units: &[(char, i64)]) -> Option<i64> {
+    let mut total: i64 = 0;
+    let mut rest = input;
+    let mut units = units.iter();
+
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+        let number: i64 = rest[..digits].parse().ok()?;
+        let designator = rest[digits..].chars().next()?.to_ascii_uppercase();
+        let (_, unit) = units.find(|(d, _)| *d == designator)?;
+        total = total.checked_add(number.checked_mul(*unit)?)?;
+        rest = &rest[digits + 1..];
+    }
+
+    Some(total)
+}
+
+/// The expiration timestamp for a form value: `never` (0), an RFC 3339 date
+/// or a duration from now. Fails with a message for the uploader if the value
+/// isn't understood or is outside of `bounds`.
+pub fn parse_expiration(input: &str, now: i64, bounds: &Bounds) -> Result<i64, String> {
+    let input = input.trim();
+
+    if input == "never" {
+        return if bounds.allow_never {
+            Ok(0)
+        } else {
+            Err(String::from("Uploads on this server can't be kept forever."))
+        };
+    }
+
+    let expiration = match DateTime::parse_from_rfc3339(input) {
+        Ok(date) => date.timestamp(),
+        Err(_) => match parse_duration(input) {
+            Some(lifetime) => now.saturating_add(lifetime),
+            None => {
+                return Err(format!(
+                    "Can't read the expiration \"{}\", use a duration like 90m or P30D or a date like 2030-01-01T00:00:00Z.",
+                    input
+                ))
+            }
+        },
+    };
+
+    if expiration <= now {
+        return Err(String::from("The expiration date is in the past."));
+    }
+    if let Some(min) = bounds.min.filter(|min| expiration - now < *min) {
+        return Err(format!("Uploads have to be kept for at least {}.", format_duration(min)));
+    }
+    if let Some(max) = bounds.max.filter(|max| expiration - now > *max) {
+        return Err(format!("Uploads can be kept for at most {}.", format_duration(max)));
+    }
+
+    Ok(expiration)
+}
+
+/// The configured default expiration, or the longest allowed one if the
+/// default doesn't fit the bounds. See `Args::check_default_expiry`.
+pub fn default_expiration(now: i64, bounds: &Bounds) -> i64 {
+    parse_expiration(&ARGS.default_expiry, now, bounds)
+        .unwrap_or_else(|_| now + bounds.max.unwrap_or(DEFAULT_LIFETIME))
+}
+
+/// A burn after count from a form, 0 for no limit. `max` of 0 allows any.
+pub fn parse_burn_after(input: &str, max: u64) -> Result<u64, String> {
+    let count: u64 = input
+        .trim()
+        .parse()
+        .map_err(|_| format!("Can't read the burn after count \"{}\".", input.trim()))?;
+    if max > 0 && count > max {
+        return Err(format!("Uploads can be burnt after at most {} reads.", max));
+    }
+    Ok(count)
+}
+
+/// Seconds in the compact form `parse_duration` reads, e.g. `1h30m`.
+pub fn format_duration(seconds: i64) -> String {
+    let mut rest = seconds;
+    let mut result = String::new();
+    for (unit, length) in [("w", 604800), ("d", 86400), ("h", 3600), ("m", 60), ("s", 1)] {
+        if rest >= length {
+            result.push_str(&format!("{}{}", rest / length, unit));
+            rest %= length;
+        }
+    }
+    if result.is_empty() {
+        result.push_str("0s");
+    }
+    result
+}
+
+#[test]
+fn test_parse_expiration() {
+    assert_eq!(parse_duration("24hour"), Some(86400));
+    assert_eq!(parse_duration("90m"), Some(5400));
+    assert_eq!(parse_duration("1h 30min"), Some(5400));
+    assert_eq!(parse_duration("P30D"), Some(30 * 86400));
+    assert_eq!(parse_duration("P1DT2H"), Some(93600));
+    assert_eq!(parse_duration("PT1H30M"), Some(5400));
+    assert_eq!(parse_duration("P1M"), Some(30 * 86400));
+    assert_eq!(parse_duration("PT"), None);
+    assert_eq!(parse_duration("P1H"), None);
+    assert_eq!(parse_duration("0m"), None);
+    assert_eq!(parse_duration("soon"), None);
+    assert_eq!(format_duration(5400), "1h30m");
+
+    let now = 1_700_000_000;
+    let open = Bounds {
+        allow_never: true,
+        ..Bounds::default()
+    };
+    assert_eq!(parse_expiration("never", now, &open), Ok(0));
+    assert_eq!(parse_expiration("90m", now, &open), Ok(now + 5400));
+    assert_eq!(
+        parse_expiration("2023-11-15T00:00:00+01:00", now, &open),
+        Ok(1_700_002_800)
+    );
+    assert!(parse_expiration("2001-01-01T00:00:00Z", now, &open).is_err());
+    assert!(parse_expiration("whenever", now, &open).is_err());
+
+    let bounded = Bounds {
+        min: Some(3600),
+        max: Some(86400),
+        allow_never: false,
+    };
+    assert!(parse_expiration("never", now, &bounded).is_err());
+    assert!(parse_expiration("10min", now, &bounded).is_err());
+    assert!(parse_expiration("P2D", now, &bounded).is_err());
+    assert_eq!(parse_expiration("12h", now, &bounded), Ok(now + 43200));
+
+    assert_eq!(parse_burn_after("42", 0), Ok(42));
+    assert!(parse_burn_after("42", 10).is_err());
+    assert!(parse_burn_after("-1", 0).is_err());
+}
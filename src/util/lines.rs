@@ -0,0 +1,88 @@
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+/// `?lines=42-57` on the pasta view and the raw endpoint.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct LinesQuery {
+    pub lines: String,
+}
+
+/// Lines counted from 1, both ends included.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LineRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl LineRange {
+    pub fn contains(&self, line: usize) -> bool {
+        line >= self.start && line <= self.end
+    }
+
+    /// Just these lines of `text`, with their line endings. Empty if the text
+    /// is shorter.
+    pub fn slice<'a>(&self, text: &'a str) -> &'a str {
+        let mut start = text.len();
+        let mut end = text.len();
+        let mut offset = 0;
+        for (i, line) in text.split_inclusive('\n').enumerate() {
+            if i + 1 == self.start {
+                start = offset;
+            }
+            offset += line.len();
+            if i + 1 == self.end {
+                end = offset;
+                break;
+            }
+        }
+        &text[start..end.max(start)]
+    }
+}
+
+/// `42`, `42-57`, or anchors like `L42-L57`. The ends may come in either order.
+impl FromStr for LineRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let number = |part: &str| {
+            part.trim()
+                .trim_start_matches(['L', 'l'])
+                .parse::<usize>()
+                .ok()
+                .filter(|n| *n > 0)
+        };
+        let input = s.trim().trim_start_matches('#');
+        let (start, end) = match input.split_once('-') {
+            Some((start, end)) => (number(start), number(end)),
+            None => (number(input), number(input)),
+        };
+        match (start, end) {
+            (Some(start), Some(end)) => Ok(LineRange {
+                start: start.min(end),
+                end: start.max(end),
+            }),
+            _ => Err(format!(
+                "Can't read the line range \"{}\", use a line like 42 or a range like 42-57.",
+                input
+            )),
+        }
+    }
+}
+
+#[test]
+fn test_line_range() {
+    assert_eq!("42".parse(), Ok(LineRange { start: 42, end: 42 }));
+    assert_eq!("L42-L57".parse(), Ok(LineRange { start: 42, end: 57 }));
+    assert_eq!("#57-42".parse(), Ok(LineRange { start: 42, end: 57 }));
+    assert!("0-3".parse::<LineRange>().is_err());
+    assert!("4-".parse::<LineRange>().is_err());
+    assert!("all".parse::<LineRange>().is_err());
+
+    let text = "one\ntwo\r\nthree\nfour";
+    assert_eq!(LineRange { start: 2, end: 3 }.slice(text), "two\r\nthree\n");
+    assert_eq!(LineRange { start: 4, end: 9 }.slice(text), "four");
+    assert_eq!(LineRange { start: 1, end: 1 }.slice(text), "one\n");
+    assert_eq!(LineRange { start: 5, end: 9 }.slice(text), "");
+}
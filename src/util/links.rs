@@ -0,0 +1,143 @@
+use chrono::{TimeZone, Utc};
+
+use crate::pasta::{DailyClicks, Link, Pasta};
+use crate::util::animalnumbers::to_u64;
+use crate::util::hashids::to_u64 as hashid_to_u64;
+
+/// Days of clicks kept per link, older days are dropped first.
+const MAX_DAYS: usize = 366;
+
+/// Days shown in a link's click history.
+const HISTORY_DAYS: i64 = 30;
+
+const MIN_SLUG_LENGTH: usize = 3;
+const MAX_SLUG_LENGTH: usize = 64;
+
+/// Status codes a link can redirect with.
+pub const REDIRECT_STATUSES: [u16; 3] = [302, 301, 307];
+
+/// The slug in lower case if it's free: made of letters, digits, `-` and
+/// `_`, not already used by another link, and not readable as an animal or
+/// hash id, which would make `/url/{slug}` ambiguous.
+pub fn validate_slug(slug: &str, pastas: &[Pasta]) -> Result<String, String> {
+    let slug = slug.trim().to_lowercase();
+    if slug.len() < MIN_SLUG_LENGTH || slug.len() > MAX_SLUG_LENGTH {
+        return Err(format!(
+            "Custom links need {} to {} characters.",
+            MIN_SLUG_LENGTH, MAX_SLUG_LENGTH
+        ));
+    }
+    if !slug
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(String::from(
+            "Custom links can only have letters, digits, - and _.",
+        ));
+    }
+    if to_u64(&slug).is_ok() || hashid_to_u64(&slug).is_ok() {
+        return Err(format!("\"{}\" can't be used, it reads as an upload id.", slug));
+    }
+    if pastas
+        .iter()
+        .any(|pasta| pasta.link.as_ref().and_then(|link| link.slug.as_deref()) == Some(slug.as_str()))
+    {
+        return Err(format!("The custom link \"{}\" is already taken.", slug));
+    }
+    Ok(slug)
+}
+
+/// The status code from the upload form, 302 if left out.
+pub fn parse_status(status: &str) -> Result<u16, String> {
+    match status.trim() {
+        "" => Ok(302),
+        status => status
+            .parse()
+            .ok()
+            .filter(|status| REDIRECT_STATUSES.contains(status))
+            .ok_or_else(|| format!("Links can redirect with 301, 302 or 307, not \"{}\".", status)),
+    }
+}
+
+/// Index of the pasta with this id, or else of the link with this slug.
+/// Hash ids are case sensitive, so the id goes first: a slug is only ever
+/// the lower case version of one.
+pub fn find_link(pastas: &[Pasta], slug: &str, id: Option<u64>) -> Option<usize> {
+    pastas.iter().position(|pasta| Some(pasta.id) == id).or_else(|| {
+        pastas.iter().position(|pasta| {
+            pasta.link.as_ref().and_then(|link| link.slug.as_deref()) == Some(slug)
+        })
+    })
+}
+
+/// Counts a redirect on the day of `now`. The caller saves the pasta.
+pub fn record_click(link: &mut Link, now: i64) {
+    let day = now / 86400;
+    match link.clicks.last_mut() {
+        Some(last) if last.day == day => last.count += 1,
+        _ => link.clicks.push(DailyClicks { day, count: 1 }),
+    }
+    if link.clicks.len() > MAX_DAYS {
+        link.clicks.drain(..link.clicks.len() - MAX_DAYS);
+    }
+}
+
+/// Every day of the history up to `today`, including the ones without
+/// clicks, as (date, count, percentage of the busiest day).
+pub fn daily_clicks(clicks: &[DailyClicks], today: i64) -> Vec<(String, u64, u64)> {
+    let days: Vec<(i64, u64)> = (today - HISTORY_DAYS + 1..=today)
+        .map(|day| {
+            let count = clicks
+                .iter()
+                .find(|clicks| clicks.day == day)
+                .map_or(0, |clicks| clicks.count);
+            (day, count)
+        })
+        .collect();
+    let busiest = days.iter().map(|(_, count)| *count).max().unwrap_or(0).max(1);
+
+    days.into_iter()
+        .map(|(day, count)| {
+            let date = Utc
+                .timestamp_opt(day * 86400, 0)
+                .single()
+                .map_or_else(String::new, |date| date.format("%m-%d").to_string());
+            (date, count, count * 100 / busiest)
+        })
+        .collect()
+}
+
+#[test]
+fn test_validate_slug() {
+    assert_eq!(validate_slug(" Docs-2024 ", &[]), Ok(String::from("docs-2024")));
+    assert!(validate_slug("ab", &[]).is_err());
+    assert!(validate_slug("a/b/c", &[]).is_err());
+    assert!(validate_slug("cat-dog", &[]).is_err());
+    let hashid = (0..)
+        .map(crate::util::hashids::to_hashids)
+        .find(|hashid| *hashid == hashid.to_lowercase())
+        .unwrap();
+    assert!(validate_slug(&hashid, &[]).is_err());
+
+    assert_eq!(parse_status(""), Ok(302));
+    assert_eq!(parse_status("307"), Ok(307));
+    assert!(parse_status("308").is_err());
+}
+
+#[test]
+fn test_record_click() {
+    let mut link = Link::default();
+    record_click(&mut link, 86400 * 10 + 5);
+    record_click(&mut link, 86400 * 10 + 600);
+    record_click(&mut link, 86400 * 12);
+    assert_eq!(
+        link.clicks,
+        vec![DailyClicks { day: 10, count: 2 }, DailyClicks { day: 12, count: 1 }]
+    );
+
+    let history = daily_clicks(&link.clicks, 12);
+    assert_eq!(history.len(), HISTORY_DAYS as usize);
+    assert_eq!(history[HISTORY_DAYS as usize - 3], (String::from("01-11"), 2, 100));
+    assert_eq!(history[HISTORY_DAYS as usize - 2], (String::from("01-12"), 0, 0));
+    assert_eq!(history[HISTORY_DAYS as usize - 1], (String::from("01-13"), 1, 50));
+}
@@ -0,0 +1,148 @@
+use std::io;
+use std::net::{IpAddr, SocketAddr, TcpListener};
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+
+use listenfd::ListenFd;
+use socket2::{Domain, Socket, Type};
+
+use crate::args::ARGS;
+
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+/// Collects the sockets MicroBin should serve on. Sockets handed over by
+/// systemd socket activation take precedence, then the configured Unix
+/// socket, and otherwise one TCP socket is opened per `--bind` address.
+pub fn listeners() -> io::Result<Vec<Listener>> {
+    let activated = activated_listeners()?;
+    if !activated.is_empty() {
+        return Ok(activated);
+    }
+
+    configured_listeners(ARGS.unix_socket.as_deref(), &ARGS.bind, ARGS.port)
+}
+
+/// The configured Unix socket if there is one, else a TCP socket for each
+/// bind address.
+fn configured_listeners(
+    unix_socket: Option<&str>,
+    bind: &[IpAddr],
+    port: u16,
+) -> io::Result<Vec<Listener>> {
+    #[cfg(unix)]
+    if let Some(path) = unix_socket {
+        return Ok(vec![Listener::Unix(unix_listener(path)?)]);
+    }
+    #[cfg(not(unix))]
+    let _ = unix_socket;
+
+    bind.iter()
+        .map(|addr| tcp_listener(SocketAddr::new(*addr, port)).map(Listener::Tcp))
+        .collect()
+}
+
+/// Opens a TCP listener. IPv6 sockets are restricted to IPv6 so that `::` and
+/// `0.0.0.0` can be bound side by side on the same port.
+pub fn tcp_listener(addr: SocketAddr) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    socket.set_reuse_address(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+
+    log::info!("Listening on {}", addr);
+
+    Ok(socket.into())
+}
+
+#[cfg(unix)]
+fn unix_listener(path: &str) -> io::Result<UnixListener> {
+    use std::fs;
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    // clean up a socket left behind by a previous run, but never anything else
+    if let Ok(metadata) = fs::metadata(path) {
+        if metadata.file_type().is_socket() {
+            fs::remove_file(path)?;
+        }
+    }
+
+    let listener = UnixListener::bind(path)?;
+
+    if let Some(mode) = ARGS.unix_socket_mode.as_ref() {
+        let mode = u32::from_str_radix(mode, 8).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid Unix socket mode {}, expected octal like 660", mode),
+            )
+        })?;
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    }
+
+    log::info!("Listening on unix:{}", path);
+
+    Ok(listener)
+}
+
+/// Takes over the sockets passed in through `LISTEN_FDS` by systemd.
+fn activated_listeners() -> io::Result<Vec<Listener>> {
+    let mut listenfd = ListenFd::from_env();
+    let mut listeners = Vec::new();
+
+    for i in 0..listenfd.len() {
+        // each take_* call fails without consuming the socket if the type
+        // doesn't match, so just try them in turn
+        if let Ok(Some(listener)) = listenfd.take_tcp_listener(i) {
+            log::info!("Listening on {} (socket activation)", listener.local_addr()?);
+            listeners.push(Listener::Tcp(listener));
+            continue;
+        }
+
+        #[cfg(unix)]
+        if let Ok(Some(listener)) = listenfd.take_unix_listener(i) {
+            log::info!("Listening on Unix socket {} (socket activation)", i);
+            listeners.push(Listener::Unix(listener));
+            continue;
+        }
+
+        log::warn!("Ignoring unsupported socket passed in by the service manager");
+    }
+
+    Ok(listeners)
+}
+
+#[cfg(unix)]
+#[test]
+fn test_listener_selection() {
+    use std::fs;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    let bind = [IpAddr::V4(Ipv4Addr::LOCALHOST), IpAddr::V6(Ipv6Addr::LOCALHOST)];
+    let listeners = configured_listeners(None, &bind, 0).unwrap();
+    assert_eq!(listeners.len(), 2);
+    assert!(listeners.iter().all(|l| matches!(l, Listener::Tcp(_))));
+
+    // a Unix socket replaces the TCP ones, and a stale one is cleaned up
+    let dir = std::env::temp_dir().join(format!("microbin-listeners-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("microbin.sock");
+    let path = path.to_str().unwrap();
+    for _ in 0..2 {
+        let listeners = configured_listeners(Some(path), &bind, 0).unwrap();
+        assert!(matches!(listeners.as_slice(), [Listener::Unix(_)]));
+    }
+
+    // but anything that isn't a socket is left alone
+    let file = dir.join("data.txt");
+    fs::write(&file, "keep").unwrap();
+    assert!(configured_listeners(Some(file.to_str().unwrap()), &bind, 0).is_err());
+    assert_eq!(fs::read_to_string(&file).unwrap(), "keep");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
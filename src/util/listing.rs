@@ -0,0 +1,221 @@
+use std::cmp::Ordering;
+
+use serde::{Deserialize, Serialize};
+
+use crate::pasta::Pasta;
+
+pub const DEFAULT_PER_PAGE: usize = 50;
+pub const MAX_PER_PAGE: usize = 500;
+
+/// Sorting, filtering and paging options shared by `/list`, the admin page
+/// and the JSON listing. Everything is optional and values that aren't
+/// understood fall back to the defaults: everything, newest first.
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct ListQuery {
+    /// created, expiration, size, read_count or title
+    pub sort: String,
+    /// asc or desc
+    pub order: String,
+    pub page: String,
+    #[serde(alias = "limit")]
+    pub per_page: String,
+    /// text, url or diff
+    #[serde(rename = "type")]
+    pub pasta_type: String,
+    /// yes or no
+    pub has_file: String,
+    pub extension: String,
+    pub tag: String,
+    /// public, unlisted, readonly, private or secret
+    pub privacy: String,
+    /// where the previous JSON page ended
+    pub cursor: String,
+}
+
+/// One page of a sorted and filtered listing.
+pub struct Page<'a> {
+    pub items: Vec<&'a Pasta>,
+    /// 1-based
+    pub page: usize,
+    pub pages: usize,
+    pub total: usize,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(untagged)]
+enum SortValue {
+    Number(i64),
+    Text(String),
+}
+
+/// Where a page of the JSON listing ended: the sort it belongs to and the
+/// sort value and id of its last item.
+#[derive(Serialize, Deserialize)]
+struct Cursor {
+    sort: String,
+    value: SortValue,
+    id: u64,
+}
+
+impl ListQuery {
+    pub fn sort_key(&self) -> &str {
+        match self.sort.as_str() {
+            "expiration" | "size" | "read_count" | "title" => &self.sort,
+            _ => "created",
+        }
+    }
+
+    pub fn descending(&self) -> bool {
+        self.order != "asc"
+    }
+
+    pub fn per_page(&self) -> usize {
+        self.per_page
+            .parse()
+            .unwrap_or(DEFAULT_PER_PAGE)
+            .clamp(1, MAX_PER_PAGE)
+    }
+
+    fn sort_value(&self, pasta: &Pasta) -> SortValue {
+        match self.sort_key() {
+            "expiration" => SortValue::Number(pasta.expiration),
+            "size" => SortValue::Number(pasta.total_size() as i64),
+            "read_count" => SortValue::Number(pasta.read_count as i64),
+            "title" => SortValue::Text(pasta.title.to_lowercase()),
+            _ => SortValue::Number(pasta.created),
+        }
+    }
+
+    /// Order of two items in the listing, with the id breaking ties so that
+    /// the order is total and cursors stay stable.
+    fn compare(&self, a: (&SortValue, u64), b: (&SortValue, u64)) -> Ordering {
+        let ordering = a.0.cmp(b.0).then(a.1.cmp(&b.1));
+        if self.descending() {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+
+    pub fn is_filtered(&self) -> bool {
+        !(self.pasta_type.is_empty()
+            && self.has_file.is_empty()
+            && self.extension.is_empty()
+            && self.tag.is_empty()
+            && self.privacy.is_empty())
+    }
+
+    pub fn matches(&self, pasta: &Pasta) -> bool {
+        (self.pasta_type.is_empty() || pasta.pasta_type == self.pasta_type)
+            && match self.has_file.as_str() {
+                "yes" => pasta.has_file(),
+                "no" => !pasta.has_file(),
+                _ => true,
+            }
+            && (self.extension.is_empty() || pasta.extension == self.extension)
+            && (self.privacy.is_empty() || pasta.privacy() == self.privacy)
+            && (self.tag.is_empty() || pasta.tags.contains(&self.tag.trim().to_lowercase()))
+    }
+
+    /// The matching pastas in listing order. Only references are sorted, the
+    /// shared collection keeps its order.
+    pub fn apply<'a>(&self, pastas: impl Iterator<Item = &'a Pasta>) -> Vec<&'a Pasta> {
+        let mut items: Vec<(SortValue, &Pasta)> = pastas
+            .filter(|pasta| self.matches(pasta))
+            .map(|pasta| (self.sort_value(pasta), pasta))
+            .collect();
+        items.sort_by(|a, b| self.compare((&a.0, a.1.id), (&b.0, b.1.id)));
+        items.into_iter().map(|(_, pasta)| pasta).collect()
+    }
+
+    /// The page asked for, clamped to the pages there are.
+    pub fn page<'a>(&self, items: Vec<&'a Pasta>) -> Page<'a> {
+        let per_page = self.per_page();
+        let total = items.len();
+        let pages = total.div_ceil(per_page).max(1);
+        let page = self.page.parse().unwrap_or(1).clamp(1, pages);
+
+        Page {
+            items: items
+                .into_iter()
+                .skip((page - 1) * per_page)
+                .take(per_page)
+                .collect(),
+            page,
+            pages,
+            total,
+        }
+    }
+
+    /// The items following the cursor, if any, and the cursor for the page
+    /// after them. Fails on cursors that can't be read or were made for a
+    /// different sort order.
+    pub fn cursor_page<'a>(
+        &self,
+        items: Vec<&'a Pasta>,
+    ) -> Result<(Vec<&'a Pasta>, Option<String>), &'static str> {
+        let start = if self.cursor.is_empty() {
+            0
+        } else {
+            let cursor = decode_cursor(&self.cursor).ok_or("Invalid cursor.")?;
+            if cursor.sort != self.sort_key() {
+                return Err("The cursor belongs to a different sort order.");
+            }
+            items.partition_point(|pasta| {
+                self.compare((&self.sort_value(pasta), pasta.id), (&cursor.value, cursor.id))
+                    != Ordering::Greater
+            })
+        };
+
+        let limit = self.per_page();
+        let page: Vec<&Pasta> = items.iter().skip(start).take(limit).copied().collect();
+        let next = if start + page.len() < items.len() {
+            page.last().map(|last| {
+                encode_cursor(&Cursor {
+                    sort: self.sort_key().to_string(),
+                    value: self.sort_value(last),
+                    id: last.id,
+                })
+            })
+        } else {
+            None
+        };
+
+        Ok((page, next))
+    }
+}
+
+/// Cursors are hex encoded JSON, which keeps them opaque and URL safe.
+fn encode_cursor(cursor: &Cursor) -> String {
+    serde_json::to_string(cursor)
+        .unwrap_or_default()
+        .bytes()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn decode_cursor(cursor: &str) -> Option<Cursor> {
+    if cursor.len() % 2 != 0 || !cursor.is_ascii() {
+        return None;
+    }
+    let bytes = (0..cursor.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&cursor[i..i + 2], 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+#[test]
+fn test_cursor_round_trip() {
+    let cursor = Cursor {
+        sort: String::from("title"),
+        value: SortValue::Text(String::from("naïve title")),
+        id: 42,
+    };
+    let decoded = decode_cursor(&encode_cursor(&cursor)).unwrap();
+    assert_eq!(decoded.sort, "title");
+    assert_eq!(decoded.value, SortValue::Text(String::from("naïve title")));
+    assert_eq!(decoded.id, 42);
+    assert!(decode_cursor("zz").is_none());
+}
@@ -0,0 +1,98 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use ammonia::Builder;
+use comrak::adapters::SyntaxHighlighterAdapter;
+use comrak::html::write_opening_tag;
+use comrak::{markdown_to_html_with_plugins, Options, Plugins};
+use once_cell::sync::Lazy;
+
+use crate::util::syntaxhighlighter::highlight_snippet;
+
+/// Highlights fenced code blocks like the pasta view, with the classes of
+/// the theme stylesheets.
+struct CodeBlocks;
+
+impl SyntaxHighlighterAdapter for CodeBlocks {
+    fn write_highlighted(&self, output: &mut dyn Write, lang: Option<&str>, code: &str) -> io::Result<()> {
+        output.write_all(highlight_snippet(code, lang.unwrap_or("")).as_bytes())
+    }
+
+    fn write_pre_tag(&self, output: &mut dyn Write, attributes: HashMap<String, String>) -> io::Result<()> {
+        write_opening_tag(output, "pre", attributes)
+    }
+
+    fn write_code_tag(&self, output: &mut dyn Write, mut attributes: HashMap<String, String>) -> io::Result<()> {
+        let class = match attributes.remove("class") {
+            Some(class) => format!("hl-code {}", class),
+            None => String::from("hl-code"),
+        };
+        attributes.insert(String::from("class"), class);
+        write_opening_tag(output, "code", attributes)
+    }
+}
+
+/// Everything Markdown produces, minus scripts, styles, event handlers and
+/// the like. Classes are only kept for highlighting.
+static SANITIZER: Lazy<Builder<'static>> = Lazy::new(|| {
+    let mut builder = Builder::default();
+    builder
+        .add_tags(["input"])
+        .add_tag_attributes("input", ["type", "checked", "disabled"])
+        .add_tag_attribute_values("input", "type", ["checkbox"])
+        .add_tag_attributes("span", ["class"])
+        .add_tag_attributes("code", ["class"])
+        .attribute_filter(|_, attribute, value| {
+            if attribute != "class" {
+                return Some(Cow::Borrowed(value));
+            }
+            let classes: Vec<&str> = value
+                .split_whitespace()
+                .filter(|class| class.starts_with("hl-") || class.starts_with("language-"))
+                .collect();
+            (!classes.is_empty()).then(|| Cow::Owned(classes.join(" ")))
+        });
+    builder
+});
+
+/// CommonMark with GitHub's tables, task lists, strikethrough and autolinks,
+/// as sanitized HTML. Raw HTML in the source is left out. Code blocks are
+/// highlighted unless `highlight` is off.
+pub fn render_markdown(source: &str, highlight: bool) -> String {
+    let mut options = Options::default();
+    options.extension.table = true;
+    options.extension.tasklist = true;
+    options.extension.strikethrough = true;
+    options.extension.autolink = true;
+
+    let mut plugins = Plugins::default();
+    if highlight {
+        plugins.render.codefence_syntax_highlighter = Some(&CodeBlocks);
+    }
+
+    let html = markdown_to_html_with_plugins(source, &options, &plugins);
+    SANITIZER.clean(&html).to_string()
+}
+
+#[test]
+fn test_render_markdown() {
+    let html = render_markdown(
+        "# Notes\n\n| a | b |\n|---|---|\n| 1 | 2 |\n\n- [x] done\n- [ ] todo\n\n```rust\nlet x = 1;\n```\n",
+        true,
+    );
+    assert!(html.contains("<h1>Notes</h1>"));
+    assert!(html.contains("<td>1</td>"));
+    assert!(html.contains("<input type=\"checkbox\" checked=\"\" disabled=\"\">"));
+    assert!(html.contains("<code class=\"hl-code language-rust\">"));
+    assert!(html.contains("<span class=\"hl-storage"));
+
+    let html = render_markdown(
+        "<script>alert(1)</script>\n\n[x](javascript:alert(1)) <img src=x onerror=alert(1)>\n\n<span class=\"selected\" style=\"x\">y</span>",
+        true,
+    );
+    assert!(!html.contains("<script"));
+    assert!(!html.contains("javascript:"));
+    assert!(!html.contains("onerror"));
+    assert!(!html.contains("selected"));
+}
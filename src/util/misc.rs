@@ -1,4 +1,10 @@
 use crate::args::ARGS;
+use actix_multipart::Field;
+use actix_web::error::ErrorBadRequest;
+use actix_web::http::header;
+use actix_web::{web, HttpResponse};
+use bytes::BytesMut;
+use futures::TryStreamExt;
 use linkify::{LinkFinder, LinkKind};
 use magic_crypt::{new_magic_crypt, MagicCryptTrait};
 use qrcode_generator::QrCodeEcc;
@@ -10,6 +16,7 @@ use std::time::{SystemTime, UNIX_EPOCH};
 use crate::Pasta;
 
 use super::db::delete;
+use super::webhooks::{notify, Event};
 
 pub fn remove_expired(pastas: &mut Vec<Pasta>) {
     // get current time - this will be needed to check which pastas have expired
@@ -35,6 +42,11 @@ pub fn remove_expired(pastas: &mut Vec<Pasta>) {
             // keep
             true
         } else {
+            // burned pastas were announced on the read that burned them
+            if p.burn_after_reads == 0 || p.read_count < p.burn_after_reads {
+                notify(Event::Expired, p);
+            }
+
             // remove from database
             delete(None, Some(p.id));
 
@@ -67,6 +79,46 @@ pub fn remove_expired(pastas: &mut Vec<Pasta>) {
     });
 }
 
+/// Most tags a pasta can have, and the longest a tag can be.
+const MAX_TAGS: usize = 20;
+const MAX_TAG_LENGTH: usize = 32;
+
+/// Turns the comma separated tags from a form into a clean list: trimmed,
+/// lowercase, without duplicates and limited in number and length.
+pub fn parse_tags(input: &str) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for tag in input.split(',') {
+        let tag: String = tag
+            .trim()
+            .to_lowercase()
+            .split_whitespace()
+            .collect::<Vec<&str>>()
+            .join(" ")
+            .chars()
+            .take(MAX_TAG_LENGTH)
+            .collect();
+        if !tag.is_empty() && !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    tags.truncate(MAX_TAGS);
+    tags
+}
+
+/// The start of `text`, at most `length` characters and without control
+/// characters, which XML doesn't allow.
+pub fn excerpt(text: &str, length: usize) -> String {
+    let mut excerpt: String = text
+        .chars()
+        .filter(|c| !c.is_control() || matches!(c, '\n' | '\t'))
+        .take(length)
+        .collect();
+    if text.chars().count() > length {
+        excerpt.push('…');
+    }
+    excerpt
+}
+
 pub fn string_to_qr_svg(str: &str) -> String {
     qrcode_generator::to_svg_to_string(str, QrCodeEcc::Low, 256, None::<&str>).unwrap()
 }
@@ -77,6 +129,32 @@ pub fn is_valid_url(url: &str) -> bool {
     spans[0].as_str() == url && Some(&LinkKind::Url) == spans[0].kind()
 }
 
+/// Content-Security-Policy sent with every response that carries user
+/// supplied bytes, so nothing in them can run as active content.
+pub const USER_CONTENT_CSP: &str = "default-src 'none'; style-src 'unsafe-inline'; sandbox";
+
+/// Detects the MIME type of an upload from its leading bytes, defaulting to
+/// `application/octet-stream` when no known signature matches.
+pub fn sniff_mime(head: &[u8]) -> String {
+    infer::get(head)
+        .map(|kind| kind.mime_type())
+        .unwrap_or("application/octet-stream")
+        .to_string()
+}
+
+/// Adds the headers that stop browsers from sniffing or executing user content.
+pub fn harden_user_content(response: &mut HttpResponse) {
+    let headers = response.headers_mut();
+    headers.insert(
+        header::X_CONTENT_TYPE_OPTIONS,
+        header::HeaderValue::from_static("nosniff"),
+    );
+    headers.insert(
+        header::CONTENT_SECURITY_POLICY,
+        header::HeaderValue::from_static(USER_CONTENT_CSP),
+    );
+}
+
 pub fn encrypt(text_str: &str, key_str: &str) -> String {
     if text_str.is_empty() {
         return String::from("");
@@ -97,6 +175,33 @@ pub fn decrypt(text_str: &str, key_str: &str) -> Result<String, magic_crypt::Mag
     mc.decrypt_base64_to_string(text_str)
 }
 
+/// Streams an uploaded file to `filepath`, giving up once it grows past
+/// `max_size` bytes. Returns the size and the MIME type detected from the
+/// leading bytes. Cleaning up a partial file on error is left to the caller.
+pub async fn save_upload(
+    field: &mut Field,
+    filepath: &str,
+    max_size: usize,
+) -> Result<(usize, String), actix_web::Error> {
+    let filepath = filepath.to_string();
+    let mut f = web::block(move || File::create(filepath)).await??;
+    let mut size = 0;
+    // keep the first few KB around to detect the real file type
+    let mut head = BytesMut::new();
+    while let Some(chunk) = field.try_next().await? {
+        size += chunk.len();
+        if head.len() < 8192 {
+            let take = chunk.len().min(8192 - head.len());
+            head.extend_from_slice(&chunk[..take]);
+        }
+        if size > max_size {
+            return Err(ErrorBadRequest("File exceeded size limit."));
+        }
+        f = web::block(move || f.write_all(&chunk).map(|_| f)).await??;
+    }
+    Ok((size, sniff_mime(&head)))
+}
+
 pub fn encrypt_file(
     passphrase: &str,
     input_file_path: &str,
@@ -148,3 +253,39 @@ pub fn decrypt_file(
 
     Ok(res.unwrap())
 }
+
+#[test]
+fn test_parse_tags_normalizes_and_dedupes() {
+    assert_eq!(
+        parse_tags(" Rust,  snippets ,rust,, Team   Notes"),
+        vec!["rust", "snippets", "team notes"]
+    );
+    assert!(parse_tags(" , ").is_empty());
+}
+
+#[test]
+fn test_excerpt_drops_control_characters() {
+    assert_eq!(excerpt("a\u{1}b\nc", 10), "ab\nc");
+    assert_eq!(excerpt("abcdef", 3), "abc…");
+}
+
+#[test]
+fn test_sniff_mime() {
+    assert_eq!(sniff_mime(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"), "image/png");
+    assert_eq!(sniff_mime(b"GIF89a"), "image/gif");
+    assert_eq!(sniff_mime(b"%PDF-1.7"), "application/pdf");
+    assert_eq!(sniff_mime(b"hello world"), "application/octet-stream");
+    assert_eq!(sniff_mime(b""), "application/octet-stream");
+}
+
+#[test]
+fn test_harden_user_content() {
+    let mut response = HttpResponse::Ok()
+        .insert_header((header::CONTENT_SECURITY_POLICY, "default-src *"))
+        .finish();
+    harden_user_content(&mut response);
+    let headers = response.headers();
+    assert_eq!(headers.get(header::X_CONTENT_TYPE_OPTIONS).unwrap(), "nosniff");
+    assert_eq!(headers.get(header::CONTENT_SECURITY_POLICY).unwrap(), USER_CONTENT_CSP);
+    assert_eq!(headers.get_all(header::CONTENT_SECURITY_POLICY).count(), 1);
+}
@@ -0,0 +1,178 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+
+use crate::pasta::Pasta;
+
+/// In-memory index used with the JSON backend. SQLite keeps its own FTS5
+/// table next to the pastas instead, see `db_sqlite`.
+pub static INDEX: Lazy<Mutex<SearchIndex>> = Lazy::new(|| Mutex::new(SearchIndex::default()));
+
+/// Words in the title count this many times as much as words elsewhere.
+const TITLE_WEIGHT: u32 = 3;
+
+/// Splits text into lowercase words. Used for both documents and queries so
+/// that they are matched the same way.
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+/// The searchable fields of a pasta: title, content, attachment name and
+/// extension. Encrypted content is left out, there is nothing to find in it.
+pub fn searchable_fields(pasta: &Pasta) -> [&str; 4] {
+    let content = if pasta.encrypt_server || pasta.encrypt_client {
+        ""
+    } else {
+        pasta.content.as_str()
+    };
+    [
+        pasta.title.as_str(),
+        content,
+        pasta.file.as_ref().map_or("", |f| f.name()),
+        pasta.extension.as_str(),
+    ]
+}
+
+#[derive(Default)]
+pub struct SearchIndex {
+    /// word -> pasta id -> weighted number of occurrences
+    terms: BTreeMap<String, HashMap<u64, u32>>,
+    /// the words indexed for each pasta, to remove them again
+    docs: HashMap<u64, Vec<String>>,
+}
+
+impl SearchIndex {
+    pub fn rebuild(&mut self, pastas: &[Pasta]) {
+        self.terms.clear();
+        self.docs.clear();
+        for pasta in pastas {
+            self.put(pasta);
+        }
+    }
+
+    /// Adds a pasta to the index, replacing what was indexed for it before.
+    pub fn put(&mut self, pasta: &Pasta) {
+        self.put_fields(pasta.id, searchable_fields(pasta));
+    }
+
+    fn put_fields(&mut self, id: u64, fields: [&str; 4]) {
+        self.remove(id);
+
+        let [title, content, file_name, extension] = fields;
+        let mut counts: HashMap<String, u32> = HashMap::new();
+        for word in tokenize(title) {
+            *counts.entry(word).or_default() += TITLE_WEIGHT;
+        }
+        for text in [content, file_name, extension] {
+            for word in tokenize(text) {
+                *counts.entry(word).or_default() += 1;
+            }
+        }
+
+        for (word, count) in counts.iter() {
+            self.terms
+                .entry(word.to_owned())
+                .or_default()
+                .insert(id, *count);
+        }
+        self.docs.insert(id, counts.into_keys().collect());
+    }
+
+    pub fn remove(&mut self, id: u64) {
+        for word in self.docs.remove(&id).unwrap_or_default() {
+            if let Some(ids) = self.terms.get_mut(&word) {
+                ids.remove(&id);
+                if ids.is_empty() {
+                    self.terms.remove(&word);
+                }
+            }
+        }
+    }
+
+    /// Ids of the pastas containing every word of the query, each word also
+    /// matching as a prefix, best matches first.
+    pub fn search(&self, query: &str) -> Vec<u64> {
+        let mut scores: Option<HashMap<u64, u32>> = None;
+
+        for word in tokenize(query) {
+            let mut matches: HashMap<u64, u32> = HashMap::new();
+            for (_, ids) in self
+                .terms
+                .range(word.clone()..)
+                .take_while(|(term, _)| term.starts_with(&word))
+            {
+                for (id, count) in ids {
+                    *matches.entry(*id).or_default() += count;
+                }
+            }
+
+            scores = Some(match scores {
+                None => matches,
+                Some(scores) => scores
+                    .into_iter()
+                    .filter_map(|(id, score)| matches.get(&id).map(|count| (id, score + count)))
+                    .collect(),
+            });
+        }
+
+        let mut results: Vec<(u64, u32)> = scores.unwrap_or_default().into_iter().collect();
+        results.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        results.into_iter().map(|(id, _)| id).collect()
+    }
+}
+
+/// A short excerpt of `content` around the first word of the query found in
+/// it, or the beginning of the content if none is.
+pub fn snippet(content: &str, query: &str) -> String {
+    const RADIUS: usize = 60;
+
+    let lowercase = content.to_lowercase();
+    let position = tokenize(query)
+        .iter()
+        .filter_map(|word| lowercase.find(word.as_str()))
+        .min()
+        .unwrap_or(0);
+
+    // positions in the lowercase copy may not line up with the original, so
+    // count characters rather than bytes
+    let center = lowercase[..position].chars().count();
+    let start = center.saturating_sub(RADIUS);
+    let excerpt: String = content.chars().skip(start).take(RADIUS * 2).collect();
+    let excerpt = excerpt.split_whitespace().collect::<Vec<&str>>().join(" ");
+
+    let mut snippet = String::new();
+    if start > 0 {
+        snippet.push('…');
+    }
+    snippet.push_str(&excerpt);
+    if content.chars().count() > start + RADIUS * 2 {
+        snippet.push('…');
+    }
+    snippet
+}
+
+#[test]
+fn test_search_index_matches_all_words_by_prefix() {
+    let mut index = SearchIndex::default();
+    index.put_fields(1, ["Deploy notes", "restart the webserver after deploying", "", "none"]);
+    index.put_fields(2, ["", "deploy script", "deploy.sh", "sh"]);
+
+    assert_eq!(index.search("deploy"), vec![1, 2]);
+    assert_eq!(index.search("web deploy"), vec![1]);
+    assert!(index.search("missing").is_empty());
+
+    index.remove(1);
+    assert_eq!(index.search("deploy"), vec![2]);
+}
+
+#[test]
+fn test_snippet_centers_on_match() {
+    let content = format!("{}needle{}", "a ".repeat(100), " b".repeat(100));
+    let snippet = snippet(&content, "needle");
+    assert!(snippet.starts_with('…') && snippet.ends_with('…'));
+    assert!(snippet.contains("needle"));
+}
@@ -0,0 +1,159 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpMessage, HttpRequest};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+
+use crate::args::ARGS;
+
+const PERMISSIONS_POLICY: &str =
+    "camera=(), microphone=(), geolocation=(), payment=(), usb=(), interest-cohort=()";
+
+/// Per-request nonce that inline `<script>` tags in the templates must carry
+/// to be allowed by the Content-Security-Policy.
+#[derive(Clone)]
+pub struct CspNonce(pub String);
+
+/// Returns the nonce generated for this request, or an empty string if the
+/// security header middleware is disabled.
+pub fn csp_nonce(req: &HttpRequest) -> String {
+    req.extensions()
+        .get::<CspNonce>()
+        .map(|n| n.0.to_owned())
+        .unwrap_or_default()
+}
+
+fn content_security_policy(nonce: &str) -> String {
+    let mut style_src = String::from("'self' 'unsafe-inline'");
+    if let Some(custom_css) = ARGS.custom_css.as_ref().filter(|c| !c.is_empty()) {
+        style_src.push(' ');
+        style_src.push_str(custom_css);
+    }
+
+    let mut policy = format!(
+        "default-src 'self'; script-src 'self' 'nonce-{nonce}'; style-src {style_src}; \
+        img-src 'self' data: blob:; media-src 'self' blob:; object-src 'none'; \
+        base-uri 'self'; form-action 'self'; frame-ancestors {}",
+        ARGS.frame_ancestors
+    );
+
+    if let Some(uri) = ARGS.csp_report_uri.as_ref() {
+        policy.push_str("; report-uri ");
+        policy.push_str(uri);
+    }
+
+    policy
+}
+
+fn insert_if_absent(
+    headers: &mut header::HeaderMap,
+    name: HeaderName,
+    value: &str,
+) {
+    if headers.contains_key(&name) {
+        return;
+    }
+    match HeaderValue::from_str(value) {
+        Ok(value) => {
+            headers.insert(name, value);
+        }
+        Err(_) => log::error!("Invalid value for {} header: {}", name, value),
+    }
+}
+
+/// Middleware that adds the Content-Security-Policy and the other browser
+/// hardening headers to every response. Headers already set by a handler
+/// (e.g. the sandbox policy on user content) are left untouched.
+pub async fn security_headers(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let nonce: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(24)
+        .map(char::from)
+        .collect();
+    req.extensions_mut().insert(CspNonce(nonce.to_owned()));
+
+    let https = req.connection_info().scheme() == "https";
+
+    let mut res = next.call(req).await?;
+    let headers = res.headers_mut();
+
+    let csp_header = if ARGS.csp_report_only {
+        HeaderName::from_static("content-security-policy-report-only")
+    } else {
+        header::CONTENT_SECURITY_POLICY
+    };
+    if !headers.contains_key(header::CONTENT_SECURITY_POLICY) {
+        insert_if_absent(headers, csp_header, &content_security_policy(&nonce));
+    }
+
+    insert_if_absent(headers, header::X_CONTENT_TYPE_OPTIONS, "nosniff");
+    insert_if_absent(headers, header::REFERRER_POLICY, &ARGS.referrer_policy);
+    insert_if_absent(
+        headers,
+        HeaderName::from_static("permissions-policy"),
+        PERMISSIONS_POLICY,
+    );
+
+    // X-Frame-Options only knows about these two cases, newer browsers use
+    // frame-ancestors from the CSP for everything else
+    match ARGS.frame_ancestors.as_str() {
+        "'none'" => insert_if_absent(headers, header::X_FRAME_OPTIONS, "DENY"),
+        "'self'" => insert_if_absent(headers, header::X_FRAME_OPTIONS, "SAMEORIGIN"),
+        _ => {}
+    }
+
+    if https && ARGS.hsts_max_age > 0 {
+        insert_if_absent(
+            headers,
+            header::STRICT_TRANSPORT_SECURITY,
+            &format!("max-age={}; includeSubDomains", ARGS.hsts_max_age),
+        );
+    }
+
+    Ok(res)
+}
+
+#[actix_web::test]
+async fn test_csp_nonce_and_headers() {
+    use actix_web::{middleware, test, web, App, HttpResponse};
+
+    let app = test::init_service(
+        App::new()
+            .wrap(middleware::from_fn(security_headers))
+            .route("/", web::get().to(|req: HttpRequest| async move { csp_nonce(&req) }))
+            .route(
+                "/sandboxed",
+                web::get().to(|| async {
+                    HttpResponse::Ok()
+                        .insert_header((header::CONTENT_SECURITY_POLICY, "sandbox"))
+                        .finish()
+                }),
+            ),
+    )
+    .await;
+
+    let mut nonces = Vec::new();
+    for _ in 0..2 {
+        let res = test::call_service(&app, test::TestRequest::get().uri("/").to_request()).await;
+        let csp = res.headers().get(header::CONTENT_SECURITY_POLICY).unwrap().to_str().unwrap().to_string();
+        assert_eq!(res.headers().get(header::X_CONTENT_TYPE_OPTIONS).unwrap(), "nosniff");
+        assert!(res.headers().contains_key("permissions-policy"));
+        assert!(!res.headers().contains_key(header::STRICT_TRANSPORT_SECURITY));
+
+        let nonce = String::from_utf8(test::read_body(res).await.to_vec()).unwrap();
+        assert_eq!(nonce.len(), 24);
+        assert!(csp.contains(&format!("script-src 'self' 'nonce-{}'", nonce)));
+        assert!(csp.contains("object-src 'none'"));
+        nonces.push(nonce);
+    }
+    assert_ne!(nonces[0], nonces[1]);
+
+    // a policy set by the handler is kept as is
+    let res = test::call_service(&app, test::TestRequest::get().uri("/sandboxed").to_request()).await;
+    assert_eq!(res.headers().get(header::CONTENT_SECURITY_POLICY).unwrap(), "sandbox");
+}
@@ -0,0 +1,115 @@
+use std::collections::HashSet;
+use std::fs;
+use std::sync::atomic::Ordering;
+
+use actix_web::dev::ServerHandle;
+use actix_web::web;
+
+use crate::args::ARGS;
+use crate::pasta::Pasta;
+use crate::util::animalnumbers::to_animal_names;
+use crate::util::db::flush;
+use crate::util::hashids::to_hashids;
+use crate::AppState;
+
+/// Waits for SIGINT or SIGTERM, then stops taking new uploads and asks the
+/// servers to shut down gracefully. Requests already in flight get up to
+/// `--shutdown-timeout` seconds to finish.
+pub async fn wait_for_signal(data: web::Data<AppState>, handles: Vec<ServerHandle>) {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut terminate =
+            signal(SignalKind::terminate()).expect("Could not listen for SIGTERM");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {},
+            _ = terminate.recv() => {},
+        }
+    }
+
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+
+    log::info!(
+        "Shutting down, waiting up to {} seconds for active transfers",
+        ARGS.shutdown_timeout
+    );
+    data.shutting_down.store(true, Ordering::SeqCst);
+
+    for handle in handles {
+        handle.stop(true).await;
+    }
+}
+
+/// Writes the in-memory state out once the servers have stopped. The lock is
+/// only granted after any write that was still running has completed.
+pub fn flush_state(data: &web::Data<AppState>) {
+    let pastas = data.pastas.lock().unwrap();
+    flush(&pastas);
+    log::info!("Saved {} uploads, bye!", pastas.len());
+}
+
+/// Removes attachment directories that don't belong to any stored pasta, e.g.
+/// uploads that were cut off by a crash or a forced shutdown, along with any
+/// leftover pending replacement uploads.
+pub fn remove_orphaned_attachments(pastas: &[Pasta]) {
+    remove_orphans_in(&format!("{}/attachments", ARGS.data_dir), pastas);
+}
+
+fn remove_orphans_in(attachments_dir: &str, pastas: &[Pasta]) {
+    let entries = match fs::read_dir(attachments_dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    // accept both id styles so toggling --hash-ids never deletes attachments
+    let known: HashSet<String> = pastas
+        .iter()
+        .flat_map(|p| [to_animal_names(p.id), to_hashids(p.id)])
+        .collect();
+
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+
+        // replacement attachments that never made it into their pasta
+        if name.starts_with(".pending-") {
+            if let Err(e) = fs::remove_file(entry.path()) {
+                log::error!("Failed to remove pending upload {}: {}", name, e);
+            }
+            continue;
+        }
+
+        if !entry.path().is_dir() || known.contains(&name) {
+            continue;
+        }
+
+        match fs::remove_dir_all(entry.path()) {
+            Ok(_) => log::info!("Removed orphaned attachment directory {}", name),
+            Err(e) => log::error!("Failed to remove orphaned directory {}: {}", name, e),
+        }
+    }
+}
+
+#[test]
+fn test_remove_orphaned_attachments() {
+    let dir = std::env::temp_dir().join(format!("microbin-shutdown-test-{}", std::process::id()));
+    let kept = dir.join(to_animal_names(1));
+    let kept_hashid = dir.join(to_hashids(1));
+    let orphan = dir.join(to_animal_names(2));
+    for path in [&kept, &kept_hashid, &orphan] {
+        fs::create_dir_all(path).unwrap();
+        fs::write(path.join("file.txt"), "data").unwrap();
+    }
+    fs::write(dir.join(".pending-42"), "partial").unwrap();
+    fs::write(dir.join("notes.txt"), "not an attachment").unwrap();
+
+    remove_orphans_in(dir.to_str().unwrap(), &[crate::pasta::test_pasta(1)]);
+
+    assert!(kept.join("file.txt").exists());
+    assert!(kept_hashid.join("file.txt").exists());
+    assert!(!orphan.exists());
+    assert!(!dir.join(".pending-42").exists());
+    assert!(dir.join("notes.txt").exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -1,37 +1,631 @@
-use syntect::easy::HighlightLines;
-use syntect::highlighting::{Style, ThemeSet};
-use syntect::html::append_highlighted_html_for_styled_line;
-use syntect::html::IncludeBackground::No;
-use syntect::parsing::SyntaxSet;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs;
+use std::sync::{Arc, Mutex};
+
+use actix_web::{web, HttpRequest};
+use once_cell::sync::Lazy;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::html::{css_for_theme_with_class_style, line_tokens_to_classed_spans, ClassStyle};
+use syntect::parsing::{ParseState, Scope, ScopeStack, SyntaxReference, SyntaxSet};
 use syntect::util::LinesWithEndings;
 
+use crate::args::ARGS;
+use crate::util::diff::{has_hunks, parse_patch, patch_html};
+use crate::util::lines::LineRange;
+use crate::util::markdown::render_markdown;
+
+static SYNTAXES: Lazy<SyntaxSet> = Lazy::new(SyntaxSet::load_defaults_newlines);
+
+/// Only the start of long uploads is looked at when guessing the language.
+const DETECTION_LIMIT: usize = 64 * 1024;
+
+/// Substrings that hint at a language and how strongly, for content that
+/// has no first line syntect recognises.
+const SIGNALS: &[(&str, &[(&str, usize)])] = &[
+    ("rs", &[("fn ", 2), ("let mut ", 3), ("impl ", 3), ("pub fn ", 4), ("use std::", 5), ("#[derive(", 5), ("println!(", 4), ("&self", 3), ("-> ", 1)]),
+    ("py", &[("def ", 3), ("self.", 2), ("elif ", 4), ("print(", 2), ("__init__", 5), ("import ", 1), ("None", 1)]),
+    ("js", &[("function ", 2), ("const ", 2), ("=> ", 2), ("console.log(", 5), ("document.", 4), ("require(", 3), ("===", 3)]),
+    ("go", &[("package ", 4), ("func ", 4), (":= ", 3), ("fmt.", 5), ("import (", 4)]),
+    ("java", &[("public class ", 4), ("System.out.", 5), ("public static void", 4), ("import java.", 5)]),
+    ("c", &[("#include <stdio.h>", 6), ("#include ", 2), ("printf(", 3), ("int main(", 3), ("malloc(", 3)]),
+    ("cpp", &[("std::", 4), ("#include <iostream>", 6), ("cout <<", 5), ("template<", 4), ("namespace ", 2)]),
+    ("cs", &[("using System", 6), ("Console.WriteLine", 6), ("namespace ", 2)]),
+    ("rb", &[("puts ", 4), ("require '", 4), (".each do", 5), ("attr_accessor", 5), ("end\n", 1)]),
+    ("php", &[("<?php", 10), ("$this->", 5)]),
+    ("html", &[("<!DOCTYPE html", 10), ("<html", 6), ("<body", 5), ("<head", 5), ("<div", 3), ("</", 1)]),
+    ("css", &[("color:", 3), ("margin:", 3), ("padding:", 3), ("font-", 2), ("@media", 5)]),
+    ("sql", &[("SELECT ", 4), ("FROM ", 3), ("WHERE ", 3), ("INSERT INTO", 5), ("CREATE TABLE", 6)]),
+    ("sh", &[("echo ", 2), ("fi\n", 4), ("then\n", 3), ("$(", 2), ("export ", 2), ("sudo ", 3)]),
+    ("md", &[("\n# ", 2), ("## ", 3), ("```", 4), ("](", 3)]),
+    ("yaml", &[("---\n", 3), (":\n  ", 2), ("\n  - ", 2)]),
+];
+
+/// Score a language needs before the classifier trusts it.
+const MIN_SCORE: usize = 8;
+
+/// Guesses the syntax of an upload and returns its extension: from the name
+/// of the attached file, a shebang or other first line syntect recognises,
+/// valid JSON, or else substrings typical for common languages.
+pub fn detect_syntax(content: &str, file_name: Option<&str>) -> Option<String> {
+    if let Some(name) = file_name {
+        let extension = name.rsplit_once('.').map_or(name, |(_, extension)| extension);
+        let syntax = SYNTAXES
+            .find_syntax_by_extension(name)
+            .or_else(|| SYNTAXES.find_syntax_by_extension(extension));
+        if let Some(syntax) = syntax {
+            return syntax.file_extensions.first().cloned();
+        }
+    }
+
+    let mut end = content.len().min(DETECTION_LIMIT);
+    while !content.is_char_boundary(end) {
+        end -= 1;
+    }
+    let content = &content[..end];
+    let trimmed = content.trim();
+    if trimmed.is_empty() {
+        return None;
+    }
+
+    if let Some(syntax) = SYNTAXES.find_syntax_by_first_line(trimmed.lines().next().unwrap_or("")) {
+        return syntax.file_extensions.first().cloned();
+    }
+
+    if (trimmed.starts_with('{') || trimmed.starts_with('['))
+        && serde_json::from_str::<serde_json::Value>(trimmed).is_ok()
+    {
+        return Some(String::from("json"));
+    }
+
+    classify(content)
+        .filter(|extension| SYNTAXES.find_syntax_by_extension(extension).is_some())
+        .map(str::to_string)
+}
+
+/// The language whose signals add up to the highest score, counting each
+/// signal at most three times.
+fn classify(content: &str) -> Option<&'static str> {
+    SIGNALS
+        .iter()
+        .map(|(extension, signals)| {
+            let score: usize = signals
+                .iter()
+                .map(|(signal, weight)| content.matches(signal).take(3).count() * weight)
+                .sum();
+            (*extension, score)
+        })
+        .filter(|(_, score)| *score >= MIN_SCORE)
+        .max_by_key(|(_, score)| *score)
+        .map(|(extension, _)| extension)
+}
+
+/// Every syntax the server can highlight as (extension, name), by name.
+pub fn syntax_options() -> Vec<(String, String)> {
+    let mut options: Vec<(String, String)> = SYNTAXES
+        .syntaxes()
+        .iter()
+        .filter(|syntax| !syntax.hidden)
+        .filter_map(|syntax| {
+            let extension = syntax.file_extensions.first()?;
+            Some((extension.clone(), syntax.name.clone()))
+        })
+        .collect();
+    options.sort_by_key(|(_, name)| name.to_lowercase());
+    options.dedup_by(|a, b| a.0 == b.0);
+    options
+}
+
+/// A rendered upload is kept until this many bytes of newer ones are cached.
+const CACHE_BUDGET: usize = 32 * 1024 * 1024;
+
+/// Uploads up to this size are highlighted right on the async workers, larger
+/// ones on the blocking thread pool.
+const INLINE_LIMIT: usize = 16 * 1024;
+
+/// How the content of an upload is shown.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum View {
+    Source,
+    Markdown,
+    SplitDiff,
+    InlineDiff,
+}
+
+impl View {
+    pub fn is_markdown(&self) -> bool {
+        *self == View::Markdown
+    }
+
+    pub fn is_diff(&self) -> bool {
+        matches!(self, View::SplitDiff | View::InlineDiff)
+    }
+
+    pub fn is_split(&self) -> bool {
+        *self == View::SplitDiff
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+struct CacheKey {
+    id: u64,
+    version: u64,
+    extension: String,
+    view: View,
+}
+
+/// Rendered HTML by pasta id and revision, dropping the least recently used
+/// entries once over the budget. Edits bump the revision, so stale entries are
+/// never hit and simply age out. Ids get reused, so a deleted pasta's entries
+/// are dropped right away.
+#[derive(Default)]
+struct Cache {
+    entries: HashMap<CacheKey, Arc<String>>,
+    order: VecDeque<CacheKey>,
+    size: usize,
+}
+
+impl Cache {
+    fn get(&mut self, key: &CacheKey) -> Option<Arc<String>> {
+        let html = self.entries.get(key)?.clone();
+        self.order.retain(|k| k != key);
+        self.order.push_back(key.clone());
+        Some(html)
+    }
+
+    fn insert(&mut self, key: CacheKey, html: Arc<String>, budget: usize) {
+        if html.len() > budget {
+            return;
+        }
+        if let Some(old) = self.entries.insert(key.clone(), html.clone()) {
+            self.size -= old.len();
+            self.order.retain(|k| k != &key);
+        }
+        self.size += html.len();
+        self.order.push_back(key);
+
+        while self.size > budget {
+            match self.order.pop_front() {
+                Some(oldest) => {
+                    if let Some(old) = self.entries.remove(&oldest) {
+                        self.size -= old.len();
+                    }
+                }
+                None => break,
+            }
+        }
+    }
+
+    fn remove(&mut self, id: u64) {
+        let size = &mut self.size;
+        self.entries.retain(|key, html| {
+            if key.id == id {
+                *size -= html.len();
+            }
+            key.id != id
+        });
+        self.order.retain(|key| key.id != id);
+    }
+}
+
+static CACHE: Lazy<Mutex<Cache>> = Lazy::new(|| Mutex::new(Cache::default()));
+
+/// Drops everything rendered for pasta `id`, once it's deleted.
+pub fn forget(id: u64) {
+    CACHE.lock().unwrap().remove(id);
+}
+
+/// What's needed to render the content of an upload.
+pub struct Highlight {
+    pub id: u64,
+    pub version: u64,
+    /// `txt` for plain text.
+    pub extension: String,
+    pub view: View,
+    pub content: String,
+    /// Off for content that must not stay in memory, like decrypted uploads.
+    pub cacheable: bool,
+}
+
+/// The content as HTML, from the cache if possible. Uploads over the
+/// configured size limit are shown as plain text.
+pub async fn highlight(job: Highlight) -> Arc<String> {
+    let key = CacheKey {
+        id: job.id,
+        version: job.version,
+        extension: job.extension,
+        view: job.view,
+    };
+    if job.cacheable {
+        if let Some(html) = CACHE.lock().unwrap().get(&key) {
+            return html;
+        }
+    }
+
+    let content = Arc::new(job.content);
+    let too_large = content.len() as u64 > ARGS.highlight_max_kb * 1024;
+    let html = if key.view == View::Source && (key.extension == "txt" || too_large) {
+        plain_html(&content)
+    } else if content.len() <= INLINE_LIMIT {
+        render(&content, &key, too_large)
+    } else {
+        let text = content.clone();
+        let job_key = key.clone();
+        web::block(move || render(&text, &job_key, too_large))
+            .await
+            .unwrap_or_else(|e| {
+                log::error!("Failed to highlight pasta {}: {}", key.id, e);
+                plain_html(&content)
+            })
+    };
+
+    let html = Arc::new(html);
+    if job.cacheable {
+        CACHE
+            .lock()
+            .unwrap()
+            .insert(key, html.clone(), CACHE_BUDGET);
+    }
+    html
+}
+
+/// Markdown and diffs stay plain when highlighting is off or the pasta is
+/// over the size limit. Diffs without any hunks are shown as their source.
+fn render(content: &str, key: &CacheKey, too_large: bool) -> String {
+    let highlight = key.extension != "txt" && !too_large;
+    match key.view {
+        View::Markdown => render_markdown(content, highlight),
+        View::SplitDiff | View::InlineDiff => {
+            let files = parse_patch(content);
+            if has_hunks(&files) {
+                patch_html(&files, key.view.is_split(), highlight)
+            } else {
+                html_highlight(content, "diff")
+            }
+        }
+        View::Source => html_highlight(content, &key.extension),
+    }
+}
+
+/// Escaped lines without any highlighting.
+pub fn plain_html(text: &str) -> String {
+    let mut html = String::with_capacity(text.len() + text.len() / 8);
+    for (i, line) in text.lines().enumerate() {
+        open_line(&mut html, i + 1);
+        html.push_str(&html_escape::encode_text(line));
+        html.push_str("</code-line>\n");
+    }
+    html
+}
+
+/// Starts a line with its `#L42` anchor. The number itself comes from the
+/// stylesheet so it isn't copied along with the code.
+fn open_line(html: &mut String, number: usize) {
+    html.push_str(&format!(
+        "<code-line id=\"L{0}\"><a class=\"line-number\" href=\"#L{0}\"></a>",
+        number
+    ));
+}
+
+/// Marks the lines in `range` as selected in HTML from `html_highlight` or
+/// `plain_html`, which has one `<code-line>` per line. The range comes from
+/// the query string, so only lines that exist are counted towards the size.
+pub fn mark_lines(html: &str, range: LineRange) -> String {
+    let lines = html.split_inclusive('\n').count();
+    let selected = (range.end.min(lines) + 1).saturating_sub(range.start);
+    let mut marked = String::with_capacity(html.len() + 20 * selected);
+    for (i, line) in html.split_inclusive('\n').enumerate() {
+        match line.strip_prefix("<code-line ") {
+            Some(rest) if range.contains(i + 1) => {
+                marked.push_str("<code-line class=\"selected\" ");
+                marked.push_str(rest);
+            }
+            _ => marked.push_str(line),
+        }
+    }
+    marked
+}
+
+/// Prefix of the classes in highlighted HTML, so themes can't clash with the
+/// page's own styles.
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+/// Class names for a scope, as syntect's HTML generator writes them.
+fn push_classes(html: &mut String, scope: Scope) {
+    for (i, atom) in scope.build_string().split('.').enumerate() {
+        if i != 0 {
+            html.push(' ');
+        }
+        html.push_str("hl-");
+        html.push_str(atom);
+    }
+}
+
+/// The content with a `<code-line>` per line and classes for the theme
+/// stylesheets.
 pub fn html_highlight(text: &str, extension: &str) -> String {
-    let ps = SyntaxSet::load_defaults_newlines();
-    let ts = ThemeSet::load_defaults();
+    let syntax = SYNTAXES
+        .find_syntax_by_extension(extension)
+        .unwrap_or_else(|| SYNTAXES.find_syntax_plain_text());
+    let lines = match classed_lines(text, syntax) {
+        Ok(lines) => lines,
+        Err(e) => {
+            log::error!("Failed to highlight as {}: {}", extension, e);
+            return plain_html(text);
+        }
+    };
 
-    let syntax = ps
+    let mut html = String::with_capacity(text.len() * 2);
+    for (i, line) in lines.iter().enumerate() {
+        open_line(&mut html, i + 1);
+        html.push_str(line);
+        html.push_str("</code-line>\n");
+    }
+    html
+}
+
+/// A code snippet with classes for the theme stylesheets but without line
+/// numbers, for code blocks in rendered Markdown. `language` is a name or an
+/// extension, like the info string of a fenced code block.
+pub fn highlight_snippet(code: &str, language: &str) -> String {
+    let syntax = SYNTAXES
+        .find_syntax_by_token(language)
+        .unwrap_or_else(|| SYNTAXES.find_syntax_plain_text());
+    match classed_lines(code, syntax) {
+        Ok(lines) => lines.iter().map(|line| format!("{}\n", line)).collect(),
+        Err(e) => {
+            log::error!("Failed to highlight a {} snippet: {}", language, e);
+            html_escape::encode_text(code).to_string()
+        }
+    }
+}
+
+/// Each line of `text` highlighted as `extension`, without line numbers, for
+/// diffs that show their lines one by one.
+pub fn highlight_lines(text: &str, extension: &str) -> Vec<String> {
+    let syntax = SYNTAXES
         .find_syntax_by_extension(extension)
-        .or_else(|| Option::from(ps.find_syntax_plain_text()))
-        .unwrap();
-    let mut h = HighlightLines::new(syntax, &ts.themes["InspiredGitHub"]);
+        .unwrap_or_else(|| SYNTAXES.find_syntax_plain_text());
+    classed_lines(text, syntax).unwrap_or_else(|e| {
+        log::error!("Failed to highlight as {}: {}", extension, e);
+        text.lines()
+            .map(|line| html_escape::encode_text(line).to_string())
+            .collect()
+    })
+}
 
-    let mut highlighted_content: String = String::from("");
+/// Each line as HTML without its line ending. Spans that continue over
+/// several lines are closed at the end of each line and opened again on the
+/// next one, so every line stands on its own.
+fn classed_lines(text: &str, syntax: &SyntaxReference) -> Result<Vec<String>, syntect::Error> {
+    let mut parse_state = ParseState::new(syntax);
+    let mut stack = ScopeStack::new();
+    let mut lines = Vec::new();
 
     for line in LinesWithEndings::from(text) {
-        let ranges: Vec<(Style, &str)> = h.highlight_line(line, &ps).unwrap();
-        append_highlighted_html_for_styled_line(&ranges[..], No, &mut highlighted_content)
-            .expect("Failed to append highlighted line!");
+        let mut html = String::with_capacity(line.len() * 2);
+        for scope in stack.as_slice() {
+            html.push_str("<span class=\"");
+            push_classes(&mut html, *scope);
+            html.push_str("\">");
+        }
+
+        let ops = parse_state.parse_line(line, &SYNTAXES)?;
+        let (mut spans, _) = line_tokens_to_classed_spans(line, &ops, CLASS_STYLE, &mut stack)?;
+        if let Some(newline) = spans.rfind('\n') {
+            spans.remove(newline);
+            if spans[..newline].ends_with('\r') {
+                spans.remove(newline - 1);
+            }
+        }
+        html.push_str(&spans);
+
+        for _ in stack.as_slice() {
+            html.push_str("</span>");
+        }
+        lines.push(html);
     }
 
-    let mut highlighted_content2: String = String::from("");
-    for line in highlighted_content.lines() {
-        highlighted_content2 += &*format!("<code-line>{}</code-line>\n", line);
+    Ok(lines)
+}
+
+/// A highlighting theme with its generated stylesheet.
+pub struct HighlightTheme {
+    /// How the theme appears in URLs and cookies, e.g. `solarized-dark`.
+    pub slug: String,
+    pub name: String,
+    css: String,
+}
+
+/// Used when the configured themes don't exist.
+const FALLBACK_LIGHT_THEME: &str = "inspiredgithub";
+const FALLBACK_DARK_THEME: &str = "base16-ocean-dark";
+
+/// The built-in themes with the `.tmTheme` files from `{data_dir}/themes/`.
+static THEMES: Lazy<Vec<HighlightTheme>> = Lazy::new(|| {
+    let mut themes = ThemeSet::load_defaults().themes;
+
+    let dir = format!("{}/themes", ARGS.data_dir);
+    for entry in fs::read_dir(&dir).into_iter().flatten().flatten() {
+        let path = entry.path();
+        if path.extension().map_or(true, |e| e != "tmTheme") {
+            continue;
+        }
+        let name = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+        match ThemeSet::get_theme(&path) {
+            Ok(theme) => {
+                log::info!("Loaded highlighting theme '{}' from {}", name, path.display());
+                themes.insert(name, theme);
+            }
+            Err(error) => log::error!("Skipping {}: {}", path.display(), error),
+        }
     }
 
-    // Rewrite colours to ones that are compatible with water.css and both light/dark modes
-    highlighted_content2 = highlighted_content2.replace("style=\"color:#323232;\"", "");
-    highlighted_content2 =
-        highlighted_content2.replace("style=\"color:#183691;\"", "style=\"color:blue;\"");
+    let themes = build_themes(themes);
+    for name in [&ARGS.highlight_theme, &ARGS.highlight_theme_dark] {
+        if find_in(&themes, name).is_none() {
+            log::warn!("Unknown highlighting theme \"{}\", using the built-in default", name);
+        }
+    }
+    themes
+});
+
+fn build_themes(themes: BTreeMap<String, Theme>) -> Vec<HighlightTheme> {
+    let mut built: Vec<HighlightTheme> = Vec::new();
+    for (name, theme) in themes {
+        let slug = theme_slug(&name);
+        if slug.is_empty() || built.iter().any(|t| t.slug == slug) {
+            continue;
+        }
+        match css_for_theme_with_class_style(&theme, CLASS_STYLE) {
+            Ok(css) => built.push(HighlightTheme { slug, name, css }),
+            Err(e) => log::error!("Skipping highlighting theme '{}': {}", name, e),
+        }
+    }
+    built.sort_by_key(|t| t.name.to_lowercase());
+    built
+}
+
+/// `Solarized (dark)` becomes `solarized-dark`.
+fn theme_slug(name: &str) -> String {
+    name.to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<&str>>()
+        .join("-")
+}
+
+fn find_in<'a>(themes: &'a [HighlightTheme], name_or_slug: &str) -> Option<&'a HighlightTheme> {
+    let slug = theme_slug(name_or_slug);
+    themes.iter().find(|t| t.slug == slug)
+}
+
+/// The slug of a theme given by name or slug.
+pub fn find_theme(name_or_slug: &str) -> Option<&'static str> {
+    find_in(&THEMES, name_or_slug).map(|t| t.slug.as_str())
+}
+
+/// Slugs and names of all themes, for the viewer's theme picker.
+pub fn theme_options() -> Vec<(String, String)> {
+    THEMES.iter().map(|t| (t.slug.clone(), t.name.clone())).collect()
+}
+
+pub fn theme_css(slug: &str) -> Option<&'static str> {
+    THEMES.iter().find(|t| t.slug == slug).map(|t| t.css.as_str())
+}
+
+/// The theme the viewer picked, if it still exists.
+pub fn request_theme(req: &HttpRequest) -> Option<String> {
+    let cookie = req.cookie("highlight_theme")?;
+    find_theme(cookie.value()).map(String::from)
+}
+
+/// Slugs and media queries of the stylesheets to link: the viewer's pick, or
+/// the server's light and dark themes following the colour scheme.
+pub fn theme_stylesheets(choice: Option<&str>) -> Vec<(String, String)> {
+    if let Some(slug) = choice {
+        return vec![(slug.to_string(), String::from("all"))];
+    }
+    let light = find_theme(&ARGS.highlight_theme).unwrap_or(FALLBACK_LIGHT_THEME);
+    let dark = find_theme(&ARGS.highlight_theme_dark).unwrap_or(FALLBACK_DARK_THEME);
+    vec![
+        (light.to_string(), String::from("(prefers-color-scheme: light)")),
+        (dark.to_string(), String::from("(prefers-color-scheme: dark)")),
+    ]
+}
+
+#[test]
+fn test_highlight_cache() {
+    let key = |id| CacheKey {
+        id,
+        version: 0,
+        extension: String::from("rs"),
+        view: View::Source,
+    };
+    let mut cache = Cache::default();
+    cache.insert(key(1), Arc::new(String::from("aaaa")), 10);
+    cache.insert(key(2), Arc::new(String::from("bbbb")), 10);
+    assert!(cache.get(&key(1)).is_some());
+    cache.insert(key(3), Arc::new(String::from("cccc")), 10);
+    assert!(cache.get(&key(2)).is_none());
+    assert!(cache.get(&key(1)).is_some());
+    assert_eq!(cache.size, 8);
+    cache.insert(key(4), Arc::new("x".repeat(11)), 10);
+    assert!(cache.get(&key(4)).is_none());
+    cache.remove(1);
+    assert!(cache.get(&key(1)).is_none());
+    assert!(cache.get(&key(3)).is_some());
+    assert_eq!(cache.size, 4);
+
+    let html = plain_html("a<b\nc");
+    assert_eq!(
+        html,
+        "<code-line id=\"L1\"><a class=\"line-number\" href=\"#L1\"></a>a&lt;b</code-line>\n\
+         <code-line id=\"L2\"><a class=\"line-number\" href=\"#L2\"></a>c</code-line>\n"
+    );
+    let marked = mark_lines(&html, LineRange { start: 2, end: 5 });
+    assert!(marked.starts_with("<code-line id=\"L1\">"));
+    assert!(marked.contains("\n<code-line class=\"selected\" id=\"L2\">"));
+    let marked = mark_lines(&html, LineRange { start: 1, end: usize::MAX });
+    assert_eq!(marked.matches("class=\"selected\"").count(), 2);
+    assert_eq!(mark_lines(&html, LineRange { start: usize::MAX - 1, end: usize::MAX }), html);
+}
+
+#[actix_web::test]
+async fn test_deleted_pasta_is_not_served_from_cache() {
+    let job = |content: &str| Highlight {
+        id: 65000,
+        version: 1,
+        extension: String::from("txt"),
+        view: View::Source,
+        content: content.to_string(),
+        cacheable: true,
+    };
+    assert!(highlight(job("burned")).await.contains("burned"));
+
+    // a new pasta that got the same id after the first one was deleted
+    forget(65000);
+    let html = highlight(job("fresh")).await;
+    assert!(html.contains("fresh") && !html.contains("burned"));
+}
+
+#[test]
+fn test_classed_highlighting() {
+    let html = html_highlight("/* a\n b */\nlet x = 1;\r\n", "rs");
+    let lines: Vec<&str> = html.lines().collect();
+    assert_eq!(lines.len(), 3);
+    for line in &lines {
+        assert_eq!(line.matches("<span").count(), line.matches("</span>").count());
+        assert!(!line.contains('\r'));
+    }
+    assert!(lines[1].starts_with(
+        "<code-line id=\"L2\"><a class=\"line-number\" href=\"#L2\"></a><span class=\"hl-source hl-rust\"><span class=\"hl-comment"
+    ));
+    assert!(lines[2].contains("hl-storage"));
+    assert!(!html.contains("style="));
+
+    assert_eq!(theme_slug("Solarized (dark)"), "solarized-dark");
+    assert_eq!(theme_slug("base16-ocean.dark"), "base16-ocean-dark");
+    let themes = build_themes(ThemeSet::load_defaults().themes);
+    assert!(find_in(&themes, "InspiredGitHub").is_some());
+    assert!(find_in(&themes, FALLBACK_DARK_THEME).is_some());
+    assert!(find_in(&themes, "solarized-light").unwrap().css.contains(".hl-code"));
+}
 
-    highlighted_content2
+#[test]
+fn test_detect_syntax() {
+    assert_eq!(detect_syntax("", Some("main.rs")).as_deref(), Some("rs"));
+    assert_eq!(detect_syntax("x = 1", Some("notes.txt")).as_deref(), Some("txt"));
+    assert_eq!(detect_syntax("#!/usr/bin/env python3\nprint(1)", None).as_deref(), Some("py"));
+    assert_eq!(detect_syntax("#!/bin/bash\necho hi", None).as_deref(), Some("sh"));
+    assert_eq!(detect_syntax("<?xml version=\"1.0\"?>\n<a/>", None).as_deref(), Some("xml"));
+    assert_eq!(detect_syntax(" {\"a\": [1, 2]}", None).as_deref(), Some("json"));
+    assert_eq!(
+        detect_syntax("use std::fmt;\n\npub fn main() {\n    let mut x = 1;\n}\n", None).as_deref(),
+        Some("rs")
+    );
+    assert_eq!(
+        detect_syntax("class A:\n    def __init__(self):\n        self.x = None\n", None).as_deref(),
+        Some("py")
+    );
+    assert_eq!(detect_syntax("just a note to self", None), None);
 }
@@ -0,0 +1,243 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use actix_web::http::header;
+use actix_web::{HttpRequest, HttpResponse};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls::ServerConfig;
+
+use crate::args::ARGS;
+
+/// How often the certificate files are checked for changes on disk.
+const WATCH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Certificate resolver that hands out whatever certificate was loaded last,
+/// so it can be swapped out without restarting the server.
+#[derive(Debug)]
+pub struct ReloadingCertResolver {
+    cert_path: String,
+    key_path: String,
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl ReloadingCertResolver {
+    fn new(cert_path: &str, key_path: &str) -> io::Result<Self> {
+        Ok(Self {
+            cert_path: cert_path.to_string(),
+            key_path: key_path.to_string(),
+            current: RwLock::new(Arc::new(load_certified_key(cert_path, key_path)?)),
+        })
+    }
+
+    /// Loads the certificate and key again. On failure the previous
+    /// certificate stays in use.
+    pub fn reload(&self) {
+        match load_certified_key(&self.cert_path, &self.key_path) {
+            Ok(key) => {
+                *self.current.write().unwrap() = Arc::new(key);
+                log::info!("Reloaded TLS certificate from {}", self.cert_path);
+            }
+            Err(e) => log::error!("Failed to reload TLS certificate: {}", e),
+        }
+    }
+
+    fn modified(&self) -> Option<(SystemTime, SystemTime)> {
+        let cert = fs::metadata(&self.cert_path).and_then(|m| m.modified()).ok()?;
+        let key = fs::metadata(&self.key_path).and_then(|m| m.modified()).ok()?;
+        Some((cert, key))
+    }
+}
+
+impl ResolvesServerCert for ReloadingCertResolver {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().unwrap().clone())
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn load_certified_key(cert_path: &str, key_path: &str) -> io::Result<CertifiedKey> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(File::open(cert_path)?))
+        .collect::<Result<Vec<_>, _>>()?;
+    if certs.is_empty() {
+        return Err(invalid_data(format!("No certificates found in {}", cert_path)));
+    }
+
+    let key = rustls_pemfile::private_key(&mut BufReader::new(File::open(key_path)?))?
+        .ok_or_else(|| invalid_data(format!("No private key found in {}", key_path)))?;
+
+    let signing_key = rustls_rustcrypto::provider()
+        .key_provider
+        .load_private_key(key)
+        .map_err(|e| invalid_data(format!("Unsupported private key {}: {}", key_path, e)))?;
+
+    Ok(CertifiedKey::new(certs, signing_key))
+}
+
+/// Builds the rustls server configuration if a certificate and key were
+/// configured, and starts watching them for changes.
+pub fn server_config() -> io::Result<Option<ServerConfig>> {
+    let (cert_path, key_path) = match (ARGS.tls_cert.as_ref(), ARGS.tls_key.as_ref()) {
+        (Some(cert), Some(key)) => (cert, key),
+        (None, None) => return Ok(None),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Both --tls-cert and --tls-key must be set to enable HTTPS",
+            ))
+        }
+    };
+
+    let resolver = Arc::new(ReloadingCertResolver::new(cert_path, key_path)?);
+
+    let config = ServerConfig::builder_with_provider(Arc::new(rustls_rustcrypto::provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| invalid_data(e.to_string()))?
+        .with_no_client_auth()
+        .with_cert_resolver(resolver.clone());
+
+    start_reload_watchers(resolver);
+
+    Ok(Some(config))
+}
+
+fn start_reload_watchers(resolver: Arc<ReloadingCertResolver>) {
+    // reload on SIGHUP, the way most daemons pick up renewed certificates
+    #[cfg(unix)]
+    {
+        let resolver = resolver.clone();
+        actix_web::rt::spawn(async move {
+            use tokio::signal::unix::{signal, SignalKind};
+            let mut hangup = match signal(SignalKind::hangup()) {
+                Ok(s) => s,
+                Err(e) => {
+                    log::error!("Could not listen for SIGHUP: {}", e);
+                    return;
+                }
+            };
+            while hangup.recv().await.is_some() {
+                resolver.reload();
+            }
+        });
+    }
+
+    // and whenever the files change, e.g. after a certbot renewal
+    thread::spawn(move || {
+        let mut last_modified = resolver.modified();
+        loop {
+            thread::sleep(WATCH_INTERVAL);
+            let modified = resolver.modified();
+            if needs_reload(last_modified, modified) {
+                resolver.reload();
+                last_modified = modified;
+            }
+        }
+    });
+}
+
+/// Whether the certificate files changed since they were last loaded. While
+/// either of them is missing, e.g. halfway through a renewal, the current
+/// certificate is kept.
+fn needs_reload<T: PartialEq>(last_modified: Option<T>, modified: Option<T>) -> bool {
+    modified.is_some() && modified != last_modified
+}
+
+/// Handler for the plain HTTP listener which sends every request over to the
+/// HTTPS port. The host is taken from the public path if one is set, else
+/// from the `Host` header, but never from forwarding headers.
+pub async fn redirect_to_https(req: HttpRequest) -> HttpResponse {
+    let host = match &ARGS.public_path {
+        Some(url) => public_host(&url.0).to_string(),
+        None => req
+            .headers()
+            .get(header::HOST)
+            .and_then(|h| h.to_str().ok())
+            .filter(|h| is_host(h))
+            .unwrap_or_else(|| req.app_config().host())
+            .to_string(),
+    };
+
+    let path = req
+        .uri()
+        .path_and_query()
+        .map(|p| p.as_str())
+        .unwrap_or("/");
+
+    HttpResponse::PermanentRedirect()
+        .append_header(("Location", https_location(&host, ARGS.port, path)))
+        .finish()
+}
+
+/// The host and port part of a URL like `https://example.com/bin`.
+fn public_host(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    rest.split('/').next().unwrap_or(rest)
+}
+
+/// Whether a `Host` header is a plain host name or address, with an optional
+/// port.
+fn is_host(host: &str) -> bool {
+    !host.is_empty()
+        && host
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':' | '[' | ']'))
+}
+
+fn https_location(host: &str, port: u16, path: &str) -> String {
+    // strip the port of the plain HTTP listener, keeping IPv6 literals intact
+    let hostname = match host.rsplit_once(':') {
+        Some((name, port)) if !host.ends_with(']') && port.chars().all(|c| c.is_ascii_digit()) => name,
+        _ => host,
+    };
+
+    let port = if port == 443 {
+        String::from("")
+    } else {
+        format!(":{}", port)
+    };
+
+    format!("https://{}{}{}", hostname, port, path)
+}
+
+#[test]
+fn test_https_location() {
+    assert_eq!(https_location("example.com:8080", 443, "/p/1?x=2"), "https://example.com/p/1?x=2");
+    assert_eq!(https_location("[::1]:80", 8443, "/"), "https://[::1]:8443/");
+    assert_eq!(https_location("[::1]", 443, "/"), "https://[::1]/");
+
+    assert_eq!(public_host("https://bin.example.com:8443/sub"), "bin.example.com:8443");
+    assert_eq!(public_host("bin.example.com"), "bin.example.com");
+
+    assert!(is_host("bin.example.com:8080"));
+    assert!(!is_host("evil.example/phish"));
+    assert!(!is_host("user@evil.example"));
+}
+
+#[test]
+fn test_certificate_reload_checks() {
+    assert!(!needs_reload(Some(1), Some(1)));
+    assert!(needs_reload(Some(1), Some(2)));
+    assert!(needs_reload(None, Some(1)));
+    assert!(!needs_reload(Some(1), None));
+
+    let dir = std::env::temp_dir().join(format!("microbin-tls-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let cert = dir.join("cert.pem");
+    let key = dir.join("key.pem");
+    fs::write(&cert, "").unwrap();
+    fs::write(&key, "").unwrap();
+    let (cert, key) = (cert.to_str().unwrap(), key.to_str().unwrap());
+
+    let err = load_certified_key(cert, key).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    let err = load_certified_key(&format!("{}.missing", cert), key).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,331 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use once_cell::sync::{Lazy, OnceCell};
+use serde::Serialize;
+use sha2::Sha256;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+use crate::args::ARGS;
+use crate::pasta::Pasta;
+
+/// Deliveries kept for the admin page, newest first.
+const LOG_SIZE: usize = 100;
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// Wait before the first retry, doubled after every failed attempt.
+const BACKOFF: Duration = Duration::from_secs(2);
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+static QUEUE: OnceCell<UnboundedSender<Delivery>> = OnceCell::new();
+static LOG: Lazy<Mutex<VecDeque<DeliveryRecord>>> = Lazy::new(|| Mutex::new(VecDeque::new()));
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    Created,
+    Edited,
+    FirstRead,
+    Burned,
+    Expired,
+}
+
+impl Event {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Event::Created => "pasta.created",
+            Event::Edited => "pasta.edited",
+            Event::FirstRead => "pasta.first_read",
+            Event::Burned => "pasta.burned",
+            Event::Expired => "pasta.expired",
+        }
+    }
+}
+
+/// What the receiver learns about the pasta. The content is left out, the
+/// hook only says that something happened.
+#[derive(Serialize)]
+struct PastaSummary<'a> {
+    id: String,
+    url: String,
+    title: &'a str,
+    pasta_type: &'a str,
+    extension: &'a str,
+    privacy: &'a str,
+    tags: &'a [String],
+    file: Option<&'a str>,
+    size: u64,
+    read_count: u64,
+    burn_after_reads: u64,
+    created: i64,
+    expiration: i64,
+}
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    event: &'static str,
+    delivery: &'a str,
+    timestamp: i64,
+    pasta: PastaSummary<'a>,
+}
+
+/// One request waiting to be sent to one target.
+struct Delivery {
+    id: String,
+    event: Event,
+    target: String,
+    body: String,
+    /// Hex HMAC-SHA256 of the body, if a secret is set.
+    signature: Option<String>,
+}
+
+/// A line of the delivery log on the admin page.
+#[derive(Clone)]
+pub struct DeliveryRecord {
+    pub id: String,
+    pub event: &'static str,
+    pub pasta: String,
+    pub target: String,
+    pub attempts: u32,
+    /// pending, delivered or failed
+    pub status: &'static str,
+    /// status code or error of the last attempt
+    pub response: String,
+    pub time: i64,
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |n| n.as_secs() as i64)
+}
+
+/// Hex encoded HMAC-SHA256 of `body`, sent as `X-MicroBin-Signature:
+/// sha256=<hex>` so receivers can check where a request came from.
+pub fn sign(secret: &str, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body.as_bytes());
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Starts the background worker that sends the webhooks, if any targets are
+/// configured. Must be called from within the runtime.
+pub fn start_worker() {
+    if ARGS.webhook_urls.is_empty() {
+        return;
+    }
+    let (sender, receiver) = unbounded_channel();
+    if QUEUE.set(sender).is_ok() {
+        actix_web::rt::spawn(worker(receiver));
+    }
+}
+
+async fn worker(mut receiver: UnboundedReceiver<Delivery>) {
+    let client = crate::util::http_client::new_async();
+    while let Some(delivery) = receiver.recv().await {
+        let client = client.clone();
+        // each delivery retries on its own, a slow target doesn't hold up the rest
+        actix_web::rt::spawn(async move {
+            deliver(&client, &delivery, ARGS.webhook_retries, BACKOFF).await;
+        });
+    }
+}
+
+/// Queues `event` for every configured target. Returns right away, sending
+/// happens on the worker.
+pub fn notify(event: Event, pasta: &Pasta) {
+    let Some(queue) = QUEUE.get() else {
+        return;
+    };
+
+    let timestamp = now();
+    let secret = ARGS.webhook_secret.as_deref().filter(|s| !s.is_empty());
+
+    for target in ARGS.webhook_urls.iter().map(|t| t.trim()).filter(|t| !t.is_empty()) {
+        let id = format!("{:016x}", rand::random::<u64>());
+        let body = serde_json::to_string(&Payload {
+            event: event.as_str(),
+            delivery: &id,
+            timestamp,
+            pasta: PastaSummary {
+                id: pasta.id_as_animals(),
+                url: format!("{}/upload/{}", ARGS.public_path_as_str(), pasta.id_as_animals()),
+                title: &pasta.title,
+                pasta_type: &pasta.pasta_type,
+                extension: &pasta.extension,
+                privacy: pasta.privacy(),
+                tags: &pasta.tags,
+                file: pasta.file.as_ref().map(|f| f.name()),
+                size: pasta.total_size(),
+                read_count: pasta.read_count,
+                burn_after_reads: pasta.burn_after_reads,
+                created: pasta.created,
+                expiration: pasta.expiration,
+            },
+        })
+        .unwrap_or_default();
+
+        let delivery = Delivery {
+            signature: secret.map(|secret| sign(secret, &body)),
+            id,
+            event,
+            target: target.to_string(),
+            body,
+        };
+
+        record(&delivery, &pasta.id_as_animals(), 0, "pending", String::new());
+        if queue.send(delivery).is_err() {
+            log::error!("Webhook worker has stopped, dropping {} event", event.as_str());
+        }
+    }
+}
+
+/// To be called after a read was counted: fires the first read and burn
+/// events when the count reaches them.
+pub fn notify_read(pasta: &Pasta) {
+    if pasta.read_count == 1 {
+        notify(Event::FirstRead, pasta);
+    }
+    if pasta.burn_after_reads > 0 && pasta.read_count == pasta.burn_after_reads {
+        notify(Event::Burned, pasta);
+    }
+}
+
+/// Sends one delivery, retrying failed attempts with exponential backoff.
+/// Any 2xx response counts as delivered.
+async fn deliver(
+    client: &reqwest::Client,
+    delivery: &Delivery,
+    retries: u32,
+    backoff: Duration,
+) -> bool {
+    let mut wait = backoff;
+
+    for attempt in 1..=retries + 1 {
+        let mut request = client
+            .post(&delivery.target)
+            .timeout(TIMEOUT)
+            .header("Content-Type", "application/json")
+            .header("User-Agent", concat!("MicroBin/", env!("CARGO_PKG_VERSION")))
+            .header("X-MicroBin-Event", delivery.event.as_str())
+            .header("X-MicroBin-Delivery", &delivery.id)
+            .body(delivery.body.clone());
+        if let Some(signature) = &delivery.signature {
+            request = request.header("X-MicroBin-Signature", format!("sha256={}", signature));
+        }
+
+        let response = match request.send().await {
+            Ok(response) if response.status().is_success() => Ok(response.status().to_string()),
+            Ok(response) => Err(response.status().to_string()),
+            Err(error) => Err(error.to_string()),
+        };
+
+        match response {
+            Ok(status) => {
+                update_record(&delivery.id, attempt, "delivered", status);
+                return true;
+            }
+            Err(error) if attempt <= retries => {
+                update_record(&delivery.id, attempt, "pending", error);
+                actix_web::rt::time::sleep(wait).await;
+                wait = (wait * 2).min(MAX_BACKOFF);
+            }
+            Err(error) => {
+                log::warn!(
+                    "Webhook {} to {} failed after {} attempts: {}",
+                    delivery.event.as_str(),
+                    delivery.target,
+                    attempt,
+                    error
+                );
+                update_record(&delivery.id, attempt, "failed", error);
+            }
+        }
+    }
+
+    false
+}
+
+fn record(delivery: &Delivery, pasta: &str, attempts: u32, status: &'static str, response: String) {
+    let mut log = LOG.lock().unwrap();
+    log.push_front(DeliveryRecord {
+        id: delivery.id.clone(),
+        event: delivery.event.as_str(),
+        pasta: pasta.to_string(),
+        target: delivery.target.clone(),
+        attempts,
+        status,
+        response,
+        time: now(),
+    });
+    log.truncate(LOG_SIZE);
+}
+
+fn update_record(id: &str, attempts: u32, status: &'static str, response: String) {
+    let mut log = LOG.lock().unwrap();
+    if let Some(entry) = log.iter_mut().find(|entry| entry.id == id) {
+        entry.attempts = attempts;
+        entry.status = status;
+        entry.response = response;
+        entry.time = now();
+    }
+}
+
+/// The most recent deliveries, newest first.
+pub fn deliveries() -> Vec<DeliveryRecord> {
+    LOG.lock().unwrap().iter().cloned().collect()
+}
+
+#[test]
+fn test_sign_matches_rfc_4231() {
+    assert_eq!(
+        sign("Jefe", "what do ya want for nothing?"),
+        "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+    );
+}
+
+#[actix_web::test]
+async fn test_deliver_retries_until_accepted() {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    // a stub that fails the first request and accepts the second
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let target = format!("http://{}/hook", listener.local_addr().unwrap());
+    let stub = std::thread::spawn(move || {
+        let mut requests = Vec::new();
+        for status in ["500 Internal Server Error", "204 No Content"] {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buffer = [0; 4096];
+            let read = stream.read(&mut buffer).unwrap();
+            requests.push(String::from_utf8_lossy(&buffer[..read]).to_lowercase());
+            write!(stream, "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status)
+                .unwrap();
+        }
+        requests
+    });
+
+    let body = String::from(r#"{"event":"pasta.created"}"#);
+    let delivery = Delivery {
+        id: String::from("test"),
+        event: Event::Created,
+        target,
+        signature: Some(sign("secret", &body)),
+        body,
+    };
+
+    assert!(deliver(&crate::util::http_client::new_async(), &delivery, 2, Duration::from_millis(10)).await);
+
+    let requests = stub.join().unwrap();
+    assert_eq!(requests.len(), 2);
+    assert!(requests[1].contains("x-microbin-event: pasta.created"));
+    assert!(requests[1].contains(&format!(
+        "x-microbin-signature: sha256={}",
+        sign("secret", r#"{"event":"pasta.created"}"#)
+    )));
+}
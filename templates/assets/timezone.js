@@ -0,0 +1,10 @@
+// Remembers the viewer's offset from UTC in minutes so that the server can
+// show times in their timezone. Takes effect from the next page on.
+(function () {
+    "use strict";
+
+    var cookie = "tz=" + (-new Date().getTimezoneOffset());
+    if (document.cookie.split("; ").indexOf(cookie) === -1) {
+        document.cookie = cookie + "; path=/; max-age=31536000; SameSite=Lax";
+    }
+})();
@@ -0,0 +1,294 @@
+# English messages, also the fallback for anything another catalog leaves out.
+#
+# Each message is `key = text`. Placeables like `{ $count }` are filled in by
+# the code, and messages that depend on a number select a variant by plural
+# category:
+#
+#     key = { $count ->
+#         [one] { $count } thing
+#        *[other] { $count } things
+#     }
+#
+# The variant marked with `*` is the default. To add a language, copy this file
+# to `<code>.ftl`, e.g. `de.ftl`, and translate the text after each `=`.
+
+language_name = English
+
+## Header & Footer
+
+title = MicroBin
+upload_new = Upload New
+list = List
+tags_label = Tags
+tags_placeholder = Comma separated, e.g. rust, deployment
+access_log_enable = Keep an access log
+view_access_log = Access log
+access_log_title = Access log of
+access_log_password_hint = Enter the upload's password or the admin password to see who opened it.
+access_log_show = Show
+access_log_empty = Nobody has opened this upload yet.
+access_log_route = Opened as
+access_log_client = Network
+access_log_user_agent = Browser
+access_log_retention = Entries are kept for this many days:
+collections = Collections
+collections_title = Collections
+collection_new = New collection
+collection_name = Name
+collection_description = Description
+collection_members = Uploads
+collection_members_hint = Uploads, one link or ID per line, in the order to show them
+collection_password = Password for editing (optional)
+collection_create = Create
+collection_saved = Collection saved.
+collection_empty = This collection is empty.
+collection_open_separately = This upload is protected or burns after reading, open it on its own page.
+collection_edit = Edit collection
+collection_delete = Delete collection
+search = Search
+search_title = Search uploads
+search_placeholder = Words in the title, content, file name or syntax
+search_button = Search
+search_no_results = Nothing found.
+search_as_admin = Search as admin, including private uploads
+search_admin_username = Admin username
+search_admin_active = Searching as admin, private uploads are included. Encrypted content can't be searched.
+admin = Admin
+guide = Guide
+footer_text = Powered by MicroBin
+
+## Table Headers
+
+table_file = Filename
+table_date = Date
+table_size = Size
+table_hits = Hits
+table_hits_count = { $count ->
+    [one] { $count } hit
+   *[other] { $count } hits
+}
+table_expires = Expires
+table_ip = IP
+table_actions = Actions
+table_key = Key
+table_title = Title
+table_content = Content
+table_valid = Valid
+table_encryption = Encryption
+table_privacy = Priv.
+table_editable = Edit.
+
+## Edit Page
+
+edit_title = Editing upload
+edit_password_prompt = Re-enter Password
+edit_editor_label = Your name (optional)
+edit_settings = Settings
+edit_settings_hint = Changing settings needs the upload's password, or the admin password if it doesn't have one. Leave the password field empty to keep the current password.
+edit_unchanged = Unchanged
+edit_new_password = New password
+edit_replace_file = Replace attachment
+edit_remove_file = Remove attachment
+edit_admin_password = Admin password
+
+## History Page
+
+history_title = History of
+history_revision = Revision
+history_editor = Editor
+history_anonymous = Anonymous
+history_current = current
+history_old = Old
+history_new = New
+history_unified = Unified
+history_side_by_side = Side by side
+history_compare = Compare
+history_no_changes = No differences between these revisions.
+history_restore = Restore
+history_restored = Revision restored.
+
+## Edit Conflict Page
+
+conflict_title = Someone else saved this upload while you were editing it
+conflict_explanation = Your changes were not saved. Merge them with the current version below and save again.
+conflict_changes = Changes from the current version to yours
+conflict_theirs = Current version
+conflict_yours = Your version
+
+## Error Page
+
+error_404 = 404 not found :(
+go_home = Go Home
+
+## Link Preview Page
+
+link_preview_title = Where this link leads
+link_preview_intro = You're about to be sent to:
+link_preview_continue = Continue
+link_preview_details = Details
+
+## View Page
+
+view_copy_text = Copy Text
+view_copy_redirect = Copy Redirect
+view_raw = Raw Text
+view_qr = QR
+view_edit = Edit
+view_history = History
+view_fork = Fork
+view_forked_from = Forked from
+view_remove = Remove
+view_copy_url = Copy URL
+view_theme_auto = Automatic theme
+view_rendered = Rendered
+view_source = Source
+view_split = Side by Side
+view_inline = Inline
+view_patch = Download .patch
+view_click_count = { $count ->
+    [one] Clicked { $count } time
+   *[other] Clicked { $count } times
+}
+view_decrypt_prompt = Please enter your key to decrypt this upload.
+view_decrypt_button = Decrypt text
+view_download = Download
+view_last = last
+view_read_count = { $count ->
+    [one] Read { $count } time
+   *[other] Read { $count } times
+}
+view_copied = Copied
+
+## List Page
+
+list_copy = Copy
+list_edit = Edit
+list_remove = Remove
+list_text = Text
+list_redirect = Redirect
+list_diff = Diff
+list_empty = No uploads yet. 😔 Create one
+list_empty_link = here
+list_sort = Sort by
+list_descending = Descending
+list_ascending = Ascending
+list_any_type = Any type
+list_any_file = With or without file
+list_with_file = With file
+list_without_file = Without file
+list_any_privacy = Any privacy
+list_per_page = page
+list_apply = Apply
+list_feeds = Subscribe
+list_previous = Previous
+list_next = Next
+list_page = Page
+
+## Remove/Delete Confirmation
+
+remove_confirm_prompt = Please type "confirm" to delete this upload.
+remove_confirm_placeholder = Type "confirm"
+remove_confirm_button = Delete
+remove_confirm_word = confirm
+remove_incorrect = Incorrect. Please type "confirm" to delete.
+
+## Index / Upload Form
+
+expiration = Expiration
+burn_after = Burn After
+syntax_highlight = Syntax
+privacy = Privacy
+pasta_type = Type
+password = Password
+title_label = Title
+title_placeholder = Enter a title for easy reference...
+content_label = Content
+content_placeholder = Type something here.
+diff_new_label = Changed Text
+diff_new_placeholder = Paste the new version here, the content above is the old one.
+link_slug_label = Custom Link
+link_slug_placeholder = Optional name for the short link, like my-talk
+redirect_status = Redirect
+redirect_302 = 302 Found (temporary)
+redirect_301 = 301 Moved Permanently (cached by browsers)
+redirect_307 = 307 Temporary Redirect (keeps the method)
+link_preview_enable = Show the destination before redirecting
+select_file = Select or drop file attachment
+save_button = Save
+uploader_password_placeholder = Uploader Password
+incorrect_password = Incorrect password!
+fork_from = New upload based on
+fork_attachment = Copy attachment
+
+## Dropdowns
+
+expire_never = Never Expire
+expire_custom_placeholder = Or your own: 90m, P30D, 2030-01-01T00:00:00Z
+burn_no_limit = No Limit
+burn_after_count = { $count ->
+    [one] First Read
+   *[other] After { $count } Reads
+}
+burn_custom_placeholder = Or your own number of reads
+privacy_public = Public
+privacy_unlisted = Unlisted
+privacy_readonly = Read-only
+privacy_private = Private
+privacy_secret = Secret
+type_text = Text or Link
+type_diff = Diff or Patch
+type_compare = Compare Two Texts
+
+## Dates & Sizes
+
+# strftime patterns, see https://docs.rs/chrono/latest/chrono/format/strftime/
+date_format = %b %-d, %H:%M
+datetime_format = %Y-%m-%d %H:%M:%S
+date_never = Never
+date_unknown = Unknown
+time_just_now = just now
+time_seconds_ago = { $count ->
+    [one] { $count } second ago
+   *[other] { $count } seconds ago
+}
+time_minutes_ago = { $count ->
+    [one] { $count } minute ago
+   *[other] { $count } minutes ago
+}
+time_hours_ago = { $count ->
+    [one] { $count } hour ago
+   *[other] { $count } hours ago
+}
+time_days_ago = { $count ->
+    [one] { $count } day ago
+   *[other] { $count } days ago
+}
+duration_seconds = { $count ->
+    [one] { $count } second
+   *[other] { $count } seconds
+}
+duration_minutes = { $count ->
+    [one] { $count } minute
+   *[other] { $count } minutes
+}
+duration_hours = { $count ->
+    [one] { $count } hour
+   *[other] { $count } hours
+}
+duration_days = { $count ->
+    [one] { $count } day
+   *[other] { $count } days
+}
+duration_weeks = { $count ->
+    [one] { $count } week
+   *[other] { $count } weeks
+}
+time_seconds_ago_short = { $count } s ago
+time_minutes_ago_short = { $count } m ago
+time_hours_ago_short = { $count } h ago
+time_days_ago_short = { $count } d ago
+decimal_separator = .
+size_unit_b = B
+size_unit_kb = KB
+size_unit_mb = MB
+size_unit_gb = GB
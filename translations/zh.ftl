@@ -0,0 +1,242 @@
+# 简体中文
+
+language_name = 中文
+
+## Header & Footer
+
+title = MicroBin
+upload_new = 新建上传
+list = 列表
+tags_label = 标签
+tags_placeholder = 用逗号分隔，例如 rust, deployment
+access_log_enable = 记录访问日志
+view_access_log = 访问日志
+access_log_title = 访问日志
+access_log_password_hint = 输入上传内容的密码或管理员密码以查看访问记录。
+access_log_show = 查看
+access_log_empty = 还没有人打开过此上传内容。
+access_log_route = 打开方式
+access_log_client = 网络
+access_log_user_agent = 浏览器
+access_log_retention = 记录保留天数：
+collections = 合集
+collections_title = 合集
+collection_new = 新建合集
+collection_name = 名称
+collection_description = 描述
+collection_members = 上传内容
+collection_members_hint = 上传内容，每行一个链接或ID，按显示顺序排列
+collection_password = 编辑密码 (可选)
+collection_create = 创建
+collection_saved = 合集已保存。
+collection_empty = 这个合集是空的。
+collection_open_separately = 此上传内容受保护或阅后即焚，请在其单独页面打开。
+collection_edit = 编辑合集
+collection_delete = 删除合集
+search = 搜索
+search_title = 搜索上传内容
+search_placeholder = 标题、内容、文件名或语法中的词语
+search_button = 搜索
+search_no_results = 没有找到任何内容。
+search_as_admin = 以管理员身份搜索，包括私密上传内容
+search_admin_username = 管理员用户名
+search_admin_active = 正在以管理员身份搜索，包括私密上传内容。加密内容无法被搜索。
+admin = 管理
+guide = 指南
+footer_text = 由 MicroBin 驱动
+
+## Table Headers
+
+table_file = 文件名
+table_date = 日期
+table_size = 大小
+table_hits = 点击
+table_hits_count = { $count } 次点击
+table_expires = 过期
+table_ip = IP
+table_actions = 操作
+table_key = 键值
+table_title = 标题
+table_content = 内容
+table_valid = 有效
+table_encryption = 加密
+table_privacy = 隐私
+table_editable = 编辑
+
+## Edit Page
+
+edit_title = 正在编辑
+edit_password_prompt = 重新输入密码
+edit_editor_label = 您的名字 (可选)
+edit_settings = 设置
+edit_settings_hint = 修改设置需要上传内容的密码，没有密码的上传内容需要管理员密码。新密码留空则保留当前密码。
+edit_unchanged = 不变
+edit_new_password = 新密码
+edit_replace_file = 替换附件
+edit_remove_file = 删除附件
+edit_admin_password = 管理员密码
+
+## History Page
+
+history_title = 历史记录
+history_revision = 版本
+history_editor = 编辑者
+history_anonymous = 匿名
+history_current = 当前
+history_old = 旧
+history_new = 新
+history_unified = 合并视图
+history_side_by_side = 并排视图
+history_compare = 比较
+history_no_changes = 这两个版本之间没有差异。
+history_restore = 恢复
+history_restored = 版本已恢复。
+
+## Edit Conflict Page
+
+conflict_title = 在您编辑期间，其他人已保存了此上传
+conflict_explanation = 您的修改尚未保存。请将其与下方的当前版本合并后再次保存。
+conflict_changes = 从当前版本到您的版本的改动
+conflict_theirs = 当前版本
+conflict_yours = 您的版本
+
+## Error Page
+
+error_404 = 未找到页面
+go_home = 返回首页
+
+## Link Preview Page
+
+link_preview_title = 此链接将前往
+link_preview_intro = 您即将被带往：
+link_preview_continue = 继续
+link_preview_details = 详情
+
+## View Page
+
+view_copy_text = 复制文本
+view_copy_redirect = 复制跳转
+view_raw = 原始内容
+view_qr = 二维码
+view_edit = 编辑
+view_history = 历史
+view_fork = 复刻
+view_forked_from = 复刻自
+view_remove = 删除
+view_copy_url = 复制链接
+view_theme_auto = 自动主题
+view_rendered = 渲染
+view_source = 源码
+view_split = 并排
+view_inline = 行内
+view_patch = 下载 .patch
+view_click_count = 点击 { $count } 次
+view_decrypt_prompt = 请输入密钥以解密内容。
+view_decrypt_button = 解密文本
+view_download = 下载
+view_last = 上次
+view_read_count = 阅读 { $count } 次
+view_copied = 已复制
+
+## List Page
+
+list_copy = 复制
+list_edit = 编辑
+list_remove = 删除
+list_text = 文本
+list_redirect = 跳转
+list_diff = 差异
+list_empty = 还没有上传内容。😔 在这里创建一个
+list_empty_link = 点击这里
+list_sort = 排序方式
+list_descending = 降序
+list_ascending = 升序
+list_any_type = 所有类型
+list_any_file = 有无附件均可
+list_with_file = 有附件
+list_without_file = 无附件
+list_any_privacy = 所有隐私设置
+list_per_page = 页
+list_apply = 应用
+list_feeds = 订阅
+list_previous = 上一页
+list_next = 下一页
+list_page = 第
+
+## Remove/Delete Confirmation
+
+remove_confirm_prompt = 请输入"确认"以删除此上传。
+remove_confirm_placeholder = 输入"确认"
+remove_confirm_button = 删除
+remove_confirm_word = 确认
+remove_incorrect = 输入错误。请输入"确认"以删除。
+
+## Index / Upload Form
+
+expiration = 过期时间
+burn_after = 阅后即焚
+syntax_highlight = 语法高亮
+privacy = 隐私设置
+pasta_type = 类型
+password = 密码保护
+title_label = 标题
+title_placeholder = 输入标题以便查找...
+content_label = 已发布内容
+content_placeholder = 在此输入内容...
+diff_new_label = 修改后的文本
+diff_new_placeholder = 在此粘贴新版本，上方的内容为旧版本。
+link_slug_label = 自定义链接
+link_slug_placeholder = 短链接的可选名称，例如 my-talk
+redirect_status = 跳转方式
+redirect_302 = 302 Found（临时）
+redirect_301 = 301 Moved Permanently（会被浏览器缓存）
+redirect_307 = 307 Temporary Redirect（保留请求方法）
+link_preview_enable = 跳转前先显示目标地址
+select_file = 选择或拖放文件附件
+save_button = 保存
+uploader_password_placeholder = 上传者密码
+incorrect_password = 密码错误!
+fork_from = 基于以下上传创建
+fork_attachment = 复制附件
+
+## Dropdowns
+
+expire_never = 永不过期
+expire_custom_placeholder = 或自定义：90m、P30D、2030-01-01T00:00:00Z
+burn_no_limit = 无限制
+burn_after_count = 阅读 { $count } 次后
+burn_custom_placeholder = 或自定义阅读次数
+privacy_public = 公开
+privacy_unlisted = 未列出 (Unlisted)
+privacy_readonly = 只读
+privacy_private = 私有 (Private)
+privacy_secret = 加密 (Secret)
+type_text = 文本或链接
+type_diff = 差异或补丁
+type_compare = 比较两段文本
+
+## Dates & Sizes
+
+date_format = %m月%d日 %H:%M
+datetime_format = %Y年%m月%d日 %H:%M:%S
+date_never = 永不
+date_unknown = 未知
+time_just_now = 刚刚
+time_seconds_ago = { $count } 秒前
+time_minutes_ago = { $count } 分钟前
+time_hours_ago = { $count } 小时前
+time_days_ago = { $count } 天前
+duration_seconds = { $count } 秒
+duration_minutes = { $count } 分钟
+duration_hours = { $count } 小时
+duration_days = { $count } 天
+duration_weeks = { $count } 周
+time_seconds_ago_short = { $count }秒前
+time_minutes_ago_short = { $count }分钟前
+time_hours_ago_short = { $count }小时前
+time_days_ago_short = { $count }天前
+decimal_separator = .
+size_unit_b = 字节
+size_unit_kb = KB
+size_unit_mb = MB
+size_unit_gb = GB